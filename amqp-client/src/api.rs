@@ -1,6 +1,22 @@
 pub (crate) mod connection;
+pub (crate) mod cancel;
 pub (crate) mod channel;
+pub (crate) mod codec;
 pub (crate) mod exchange;
 pub (crate) mod queue;
+pub (crate) mod ack;
 pub (crate) mod basic;
+pub (crate) mod consumer;
 pub (crate) mod default_channel;
+pub (crate) mod pool;
+pub (crate) mod publish;
+pub (crate) mod publisher;
+pub (crate) mod retry;
+pub (crate) mod rpc;
+pub (crate) mod topology;
+#[cfg(feature = "json")]
+pub (crate) mod definitions;
+pub (crate) mod blocking;
+pub (crate) mod supervisor;
+#[cfg(feature = "json")]
+pub (crate) mod typed;
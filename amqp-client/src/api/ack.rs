@@ -0,0 +1,140 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tracing::info;
+use tokio::sync::mpsc::UnboundedSender;
+use crate::protocol::frame::{BasicAck, FrameEnvelope};
+use crate::protocol::message::{Acker, Delivery};
+use crate::protocol::types::{ChannelId, Long};
+use crate::{bail, runtime, Result};
+
+#[derive(Default)]
+struct AckState {
+  // tracked deliveries which are not settled yet
+  outstanding: BTreeSet<Long>,
+  // settled by the application, waiting for the next multiple ack
+  completed: BTreeSet<Long>,
+}
+
+// batches acks of one channel into Basic.Ack with multiple set, a multiple ack never
+// covers a tracked delivery which is still being processed
+pub struct AckManager {
+  channel: ChannelId,
+  outgoing_tx: UnboundedSender<FrameEnvelope>,
+  max_batch: usize,
+  state: Arc<Mutex<AckState>>,
+}
+
+impl AckManager {
+  pub(crate) fn new(channel: ChannelId, outgoing_tx: UnboundedSender<FrameEnvelope>, max_batch: usize, window: Duration) -> Self {
+    let manager = Self {
+      channel,
+      outgoing_tx,
+      max_batch: max_batch.max(1),
+      state: Arc::new(Mutex::new(AckState::default())),
+    };
+    manager.spawn_flush_timer(window);
+
+    manager
+  }
+
+  // deliveries should be tracked as soon as they are received
+  pub fn track(&self, delivery: &Delivery) -> Result<()> {
+    let acker = self.acker_of(delivery)?;
+    self.state.lock().unwrap().outstanding.insert(acker.delivery_tag());
+    Ok(())
+  }
+
+  pub fn ack(&self, delivery: &Delivery) -> Result<()> {
+    let acker = self.acker_of(delivery)?;
+    acker.mark_processed()?;
+
+    let completed = {
+      let mut state = self.state.lock().unwrap();
+      state.outstanding.remove(&acker.delivery_tag());
+      state.completed.insert(acker.delivery_tag());
+      state.completed.len()
+    };
+
+    if completed >= self.max_batch {
+      self.flush()?;
+    }
+
+    Ok(())
+  }
+
+  // negative acks are sent right away, they never block acks of other deliveries
+  pub fn nack(&self, delivery: &Delivery, requeue: bool) -> Result<()> {
+    let acker = self.acker_of(delivery)?;
+    acker.nack(false, requeue)?;
+    self.state.lock().unwrap().outstanding.remove(&acker.delivery_tag());
+    Ok(())
+  }
+
+  pub fn flush(&self) -> Result<()> {
+    flush(&self.state, self.channel, &self.outgoing_tx)
+  }
+
+  pub fn pending(&self) -> usize {
+    self.state.lock().unwrap().completed.len()
+  }
+
+  fn acker_of(&self, delivery: &Delivery) -> Result<Acker> {
+    let acker = delivery.acker();
+    if acker.channel() != self.channel {
+      bail!("Delivery {} belongs to channel {}, not {}", acker.delivery_tag(), acker.channel(), self.channel);
+    }
+
+    Ok(acker)
+  }
+
+  fn spawn_flush_timer(&self, window: Duration) {
+    let state = Arc::downgrade(&self.state);
+    let channel = self.channel;
+    let outgoing_tx = self.outgoing_tx.clone();
+
+    runtime::spawn(async move {
+      loop {
+        runtime::sleep(window).await;
+        let state = match Weak::upgrade(&state) {
+          Some(state) => state,
+          None => break,
+        };
+
+        if flush(&state, channel, &outgoing_tx).is_err() {
+          break;
+        }
+      }
+
+      info!("ack manager of channel {} stopped", channel);
+    });
+  }
+}
+
+impl Drop for AckManager {
+  fn drop(&mut self) {
+    let _ = self.flush();
+  }
+}
+
+fn flush(state: &Mutex<AckState>, channel: ChannelId, outgoing_tx: &UnboundedSender<FrameEnvelope>) -> Result<()> {
+  let delivery_tag = {
+    let mut state = state.lock().unwrap();
+    let cutoff = state.outstanding.first().copied();
+    let last = match cutoff {
+      Some(cutoff) => state.completed.range(..cutoff).next_back().copied(),
+      None => state.completed.last().copied(),
+    };
+
+    match last {
+      Some(last) => {
+        state.completed = state.completed.split_off(&(last + 1));
+        last
+      },
+      None => return Ok(()),
+    }
+  };
+
+  outgoing_tx.send((channel, BasicAck { delivery_tag, multiple: true }.into_frame()))?;
+  Ok(())
+}
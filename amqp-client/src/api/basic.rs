@@ -0,0 +1,255 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use crate::protocol::types::{ChannelId, Int, PropTable, Property};
+use crate::protocol::frame::{BasicConsume, BasicPublish};
+
+pub const CONSUMER_PRIORITY_ARG: &str = "x-priority";
+
+static CONSUMER_TAG_SEQ: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone)]
+pub struct BasicConsumeOpts {
+  pub queue: String,
+  pub tag: String,
+  pub no_local: bool,
+  pub no_ack: bool,
+  pub exclusive: bool,
+  pub no_wait: bool,
+  // consumers with higher priority get deliveries first, lower ones only when those are blocked
+  pub priority: Option<Int>,
+  pub arguments: PropTable,
+  // client side only, warns about the consumer falling behind
+  pub slow_consumer: Option<SlowConsumerOpts>,
+  // overrides the connection's method timeout for the consume
+  pub timeout: Option<Duration>,
+}
+
+impl Default for BasicConsumeOpts {
+  fn default() -> Self {
+    Self {
+      queue: "".to_string(),
+      tag: "".to_string(),
+      no_local: false,
+      no_ack: false,
+      exclusive: false,
+      no_wait: false,
+      priority: None,
+      arguments: PropTable::new(),
+      slow_consumer: None,
+      timeout: None,
+    }
+  }
+}
+
+impl BasicConsumeOpts {
+  // with no_wait the broker doesn't reply with a generated tag, so the client has to pick one
+  pub(crate) fn ensure_tag(&mut self, channel: ChannelId) {
+    if self.tag.is_empty() {
+      let seq = CONSUMER_TAG_SEQ.fetch_add(1, Ordering::Relaxed);
+      self.tag = format!("ctag-{}-{}", channel, seq);
+    }
+  }
+}
+
+// a consumer is behind once either threshold is reached, zero disables a threshold.
+// Checked every check_interval for as long as the consumer lives
+#[derive(Debug, Clone)]
+pub struct SlowConsumerOpts {
+  // deliveries received from the broker and not taken by the application yet
+  pub max_queued: usize,
+  // how long the oldest handed out delivery may wait for its ack
+  pub max_time_to_ack: Duration,
+  pub check_interval: Duration,
+}
+
+impl Default for SlowConsumerOpts {
+  fn default() -> Self {
+    Self {
+      max_queued: 1000,
+      max_time_to_ack: Duration::from_secs(30),
+      check_interval: Duration::from_secs(5),
+    }
+  }
+}
+
+// what a supervised consumer does with deliveries its handler failed to process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NackPolicy {
+  #[default]
+  Requeue,
+  // requeue once, drop (or dead letter) deliveries which already failed before
+  RequeueOnce,
+  Discard,
+}
+
+impl NackPolicy {
+  pub fn requeue(&self, redelivered: bool) -> bool {
+    match self {
+      NackPolicy::Requeue => true,
+      NackPolicy::RequeueOnce => !redelivered,
+      NackPolicy::Discard => false,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsumeHandlerOpts {
+  pub consume: BasicConsumeOpts,
+  pub on_error: NackPolicy,
+  // deliveries processed in parallel, prefetch is raised to at least this value
+  pub concurrency: u16,
+}
+
+impl Default for ConsumeHandlerOpts {
+  fn default() -> Self {
+    Self {
+      consume: BasicConsumeOpts::default(),
+      on_error: NackPolicy::default(),
+      concurrency: 1,
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct BasicConsumeOptsBuilder {
+  opts: BasicConsumeOpts
+}
+
+impl BasicConsumeOptsBuilder {
+  pub fn new() -> Self {
+    Self {
+      opts: BasicConsumeOpts::default()
+    }
+  }
+
+  pub fn build(self) -> BasicConsumeOpts {
+    self.opts
+  }
+
+  pub fn queue(&mut self, queue: String) {
+    self.opts.queue = queue;
+  }
+
+  pub fn tag(&mut self, tag: String) {
+    self.opts.tag = tag;
+  }
+
+  pub fn no_local(&mut self, no_local: bool) {
+    self.opts.no_local = no_local;
+  }
+
+  pub fn no_ack(&mut self, no_ack: bool) {
+    self.opts.no_ack = no_ack;
+  }
+
+  pub fn exclusive(&mut self, exclusive: bool) {
+    self.opts.exclusive = exclusive;
+  }
+
+  pub fn no_wait(&mut self, no_wait: bool) {
+    self.opts.no_wait = no_wait;
+  }
+
+  pub fn priority(&mut self, priority: Int) {
+    self.opts.priority = Some(priority);
+  }
+
+  pub fn arguments(&mut self, arguments: PropTable) {
+    self.opts.arguments = arguments;
+  }
+
+  pub fn argument(&mut self, key: &str, value: Property) {
+    self.opts.arguments.insert(key.into(), value);
+  }
+
+  pub fn slow_consumer(&mut self, opts: SlowConsumerOpts) {
+    self.opts.slow_consumer = Some(opts);
+  }
+
+  pub fn timeout(&mut self, timeout: Duration) {
+    self.opts.timeout = Some(timeout);
+  }
+}
+
+impl From<BasicConsumeOpts> for BasicConsume {
+  fn from(options: BasicConsumeOpts) -> Self {
+    let mut props = options.arguments;
+    if let Some(priority) = options.priority {
+      props.insert(CONSUMER_PRIORITY_ARG.into(), Property::Int(priority));
+    }
+
+    Self {
+      ticket: 0,
+      queue: options.queue.into(),
+      consumer_tag: options.tag.into(),
+      no_local: options.no_local,
+      no_ack: options.no_ack,
+      exclusive: options.exclusive,
+      no_wait: options.no_wait,
+      arguments: props
+    }
+  }
+}
+
+// what a publish waits for before it returns, see AmqChannel::publish_guaranteed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GuaranteeMode {
+  // queued for the socket, lost without notice when the connection fails before the broker got it
+  FireAndForget,
+  // acked by the broker, a nack fails the publish
+  #[default]
+  Confirmed,
+  // committed in a transaction of its own, a lot slower than confirms
+  Transactional,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BasicPublishOpts {
+  pub exchange: String,
+  pub routing_key: String,
+  pub mandatory: bool,
+  pub immediate: bool,
+}
+
+// publishes per second allowed on a channel, zero doesn't limit. Bursts up to a second worth of
+// the rate go through at once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishRate {
+  pub messages_per_sec: u32,
+  // body bytes, headers and frame overhead aren't counted
+  pub bytes_per_sec: u64,
+}
+
+// publishes failing for a reason that may go away, a closed channel or connection, a nack or a
+// missing reply, are repeated. The delay doubles with every attempt, up to max_backoff
+#[derive(Debug, Clone)]
+pub struct PublishRetry {
+  // the first attempt included
+  pub max_attempts: u32,
+  pub initial_backoff: Duration,
+  pub max_backoff: Duration,
+  pub retry_nacks: bool,
+}
+
+impl Default for PublishRetry {
+  fn default() -> Self {
+    Self {
+      max_attempts: 5,
+      initial_backoff: Duration::from_millis(100),
+      max_backoff: Duration::from_secs(10),
+      retry_nacks: true,
+    }
+  }
+}
+
+impl From<BasicPublishOpts> for BasicPublish {
+  fn from(options: BasicPublishOpts) -> Self {
+    Self {
+      ticket: 0,
+      exchange: options.exchange.into(),
+      routing_key: options.routing_key.into(),
+      mandatory: options.mandatory,
+      immediate: options.immediate,
+    }
+  }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+use std::time::Duration;
+use crate::api::channel::AmqChannel;
+use crate::api::connection::{Connection, Negotiated};
+use crate::api::connection::factory::ConnectionFactory;
+use crate::api::connection::options::ConnectionArgs;
+use crate::api::connection::snapshot::ConnectionSnapshot;
+use crate::api::connection::state::ConnectionState;
+use crate::api::consumer::{Consumer, ConsumerState};
+use crate::api::exchange::{ExchangeDeclareOptsBuilder, ExchangeType};
+use crate::api::queue::{QueueBindOptsBuilder, QueueDeclareOk, QueueDeclareOptsBuilder};
+use crate::api::basic::BasicConsumeOptsBuilder;
+use crate::protocol::message::{BasicProperties, Delivery};
+use crate::protocol::types::{Long, PropTable};
+use crate::runtime::{self, BlockingRuntime};
+use crate::Result;
+
+// synchronous wrappers for code that doesn't run on an async runtime, every call blocks
+// the calling thread. The runtime driving the connection is owned by the connection and
+// shared with its channels and consumers, it stays alive as long as any of them does
+pub struct BlockingConnection {
+  runtime: Arc<BlockingRuntime>,
+  connection: Connection,
+}
+
+impl BlockingConnection {
+  pub fn connect(uri: &str) -> Result<Self> {
+    Self::connect_with_args(ConnectionArgs::new(uri)?)
+  }
+
+  pub fn connect_with_args(args: ConnectionArgs) -> Result<Self> {
+    let runtime = Arc::new(BlockingRuntime::new()?);
+    let connection = runtime.block_on(ConnectionFactory::create_with_args(args))?;
+
+    Ok(Self {
+      runtime,
+      connection,
+    })
+  }
+
+  pub fn create_channel(&self) -> Result<BlockingChannel> {
+    let channel = self.runtime.block_on(self.connection.create_channel())?;
+
+    Ok(BlockingChannel {
+      runtime: self.runtime.clone(),
+      channel,
+    })
+  }
+
+  pub fn state(&self) -> ConnectionState {
+    self.connection.state()
+  }
+
+  pub fn negotiated(&self) -> Negotiated {
+    self.connection.negotiated()
+  }
+
+  pub fn snapshot(&self) -> Result<ConnectionSnapshot> {
+    self.runtime.block_on(self.connection.snapshot())
+  }
+
+  pub fn close(self) -> Result<()> {
+    self.runtime.block_on(self.connection.close())
+  }
+}
+
+pub struct BlockingChannel {
+  runtime: Arc<BlockingRuntime>,
+  channel: AmqChannel,
+}
+
+impl BlockingChannel {
+  #[allow(clippy::too_many_arguments)]
+  pub fn declare_exchange(
+    &self,
+    name: &str,
+    ty: ExchangeType,
+    durable: bool,
+    passive: bool,
+    auto_delete: bool,
+    internal: bool,
+    props: Option<PropTable>
+  ) -> Result<()> {
+    self.runtime.block_on(self.channel.declare_exchange(name, ty, durable, passive, auto_delete, internal, props))
+  }
+
+  pub fn declare_exchange_with_builder<F>(&self, configure: F) -> Result<()>
+    where F: FnOnce(&mut ExchangeDeclareOptsBuilder)
+  {
+    self.runtime.block_on(self.channel.declare_exchange_with_builder(configure))
+  }
+
+  pub fn declare_queue(
+    &self,
+    name: &str,
+    durable: bool,
+    passive: bool,
+    auto_delete: bool,
+    exclusive: bool,
+    props: Option<PropTable>
+  ) -> Result<QueueDeclareOk> {
+    self.runtime.block_on(self.channel.declare_queue(name, durable, passive, auto_delete, exclusive, props))
+  }
+
+  pub fn declare_queue_with_builder<F>(&self, configure: F) -> Result<QueueDeclareOk>
+    where F: FnOnce(&mut QueueDeclareOptsBuilder)
+  {
+    self.runtime.block_on(self.channel.declare_queue_with_builder(configure))
+  }
+
+  pub fn bind(&self, queue_name: &str, exchange_name: &str, routing_key: &str, props: Option<PropTable>) -> Result<()> {
+    self.runtime.block_on(self.channel.bind(queue_name, exchange_name, routing_key, props))
+  }
+
+  pub fn bind_with_builder<F>(&self, configure: F) -> Result<()>
+    where F: FnOnce(&mut QueueBindOptsBuilder)
+  {
+    self.runtime.block_on(self.channel.bind_with_builder(configure))
+  }
+
+  pub fn unbind(&self, queue: &str, exchange: &str, routing_key: &str, props: Option<PropTable>) -> Result<()> {
+    self.runtime.block_on(self.channel.unbind(queue, exchange, routing_key, props))
+  }
+
+  pub fn qos(&self, prefetch_count: u16, global: bool) -> Result<()> {
+    self.runtime.block_on(self.channel.qos(prefetch_count, global))
+  }
+
+  pub fn publish(&self, exchange: &str, routing_key: &str, body: Vec<u8>, properties: BasicProperties) -> Result<()> {
+    self.runtime.block_on(self.channel.publish(exchange, routing_key, body, properties))
+  }
+
+  pub fn consume(&self, queue: &str) -> Result<BlockingConsumer> {
+    let consumer = self.runtime.block_on(self.channel.consume(queue))?;
+    Ok(self.blocking_consumer(consumer))
+  }
+
+  pub fn consume_with_builder<F>(&self, configure: F) -> Result<BlockingConsumer>
+    where F: FnOnce(&mut BasicConsumeOptsBuilder)
+  {
+    let consumer = self.runtime.block_on(self.channel.consume_with_builder(configure))?;
+    Ok(self.blocking_consumer(consumer))
+  }
+
+  pub fn ack(&self, delivery_tag: Long, multiple: bool) -> Result<()> {
+    self.channel.ack(delivery_tag, multiple)
+  }
+
+  pub fn nack(&self, delivery_tag: Long, multiple: bool, requeue: bool) -> Result<()> {
+    self.channel.nack(delivery_tag, multiple, requeue)
+  }
+
+  pub fn reject(&self, delivery_tag: Long, requeue: bool) -> Result<()> {
+    self.channel.reject(delivery_tag, requeue)
+  }
+
+  pub fn close(&self) -> Result<()> {
+    self.runtime.block_on(self.channel.close())
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.channel.is_closed()
+  }
+
+  fn blocking_consumer(&self, consumer: Consumer) -> BlockingConsumer {
+    BlockingConsumer {
+      runtime: self.runtime.clone(),
+      consumer,
+    }
+  }
+}
+
+// deliveries keep arriving in the background, recv only waits for the next one.
+// Iterating ends once the consumer is cancelled or its channel closed
+pub struct BlockingConsumer {
+  runtime: Arc<BlockingRuntime>,
+  consumer: Consumer,
+}
+
+impl BlockingConsumer {
+  pub fn tag(&self) -> &str {
+    self.consumer.tag()
+  }
+
+  pub fn state(&self) -> ConsumerState {
+    self.consumer.state()
+  }
+
+  pub fn recv(&mut self) -> Option<Delivery> {
+    self.runtime.block_on(self.consumer.recv())
+  }
+
+  // None as well when nothing arrived in time, state tells the two apart
+  pub fn recv_timeout(&mut self, timeout: Duration) -> Option<Delivery> {
+    self.runtime.block_on(runtime::timeout(timeout, self.consumer.recv())).flatten()
+  }
+
+  pub fn shutdown(self, grace_period: Duration, requeue: bool) -> Result<()> {
+    self.runtime.block_on(self.consumer.shutdown(grace_period, requeue))
+  }
+}
+
+impl Iterator for BlockingConsumer {
+  type Item = Delivery;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.recv()
+  }
+}
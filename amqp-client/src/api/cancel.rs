@@ -0,0 +1,16 @@
+use std::future::Future;
+use crate::error::Cancelled;
+use crate::Result;
+
+// runs the operation until it completes or the signal does, whichever is first, e.g.
+// cancellable(publisher.send("key", &order), token.cancelled()). A cancelled operation is
+// dropped midway, which is safe for every awaitable operation of the crate
+pub async fn cancellable<T, F, S>(operation: F, signal: S) -> Result<T>
+  where F: Future<Output = Result<T>>,
+        S: Future<Output = ()>
+{
+  tokio::select! {
+    result = operation => result,
+    _ = signal => Err(Cancelled.into()),
+  }
+}
@@ -1,55 +1,295 @@
-use std::collections::HashMap;
-use log::{info};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use bytes::Bytes;
+use tracing::{debug, debug_span, info, warn, Instrument};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{oneshot, watch, Semaphore};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
-use crate::building_blocks::{Command, CommandPayload};
-use crate::protocol::types::{ChannelId, Long, ShortStr, PropTable};
-use crate::{invoke_sync_method, invoke_command_async, Result, unwrap_frame_variant, MessageProperties};
-use crate::api::exchange::{ExchangeDeclareOptsBuilder, ExchangeType};
-use crate::api::queue::QueueDeclareOptsBuilder;
-use crate::protocol::message::{Message};
-use crate::protocol::frame::{FrameEnvelope, Frame, BasicConsume, BasicPublish, ChannelOpen,
-                             ContentBody, ContentHeader, ExchangeDeclare, QueueBind, QueueDeclare,
-                             QueueUnbind};
+use crate::building_blocks::{delivery_channel, mark_closed, ChannelBacklogs, ChannelShared, Command, CommandPayload, DeliverySender, PendingConfirms, PublishLimiter, PublishWindow};
+use crate::metrics::ClientMetrics;
+use crate::runtime::{self, JoinHandle};
+use crate::protocol::types::{ChannelId, Int, Long, Short, PropTable, Property};
+use crate::{invoke_sync_method, invoke_command_async, Result, unwrap_frame_variant, bail, ChannelException, BasicProperties, ReplyCode};
+use crate::api::exchange::{ExchangeDeclareOptsBuilder, ExchangeType, DELAY_HEADER};
+use crate::api::basic::{BasicConsumeOpts, BasicConsumeOptsBuilder, BasicPublishOpts, ConsumeHandlerOpts, GuaranteeMode, NackPolicy, PublishRate};
+use crate::api::publish::{Confirmation, PublishBuilder};
+use crate::api::consumer::{watch_lag, Consumer, Subscription};
+#[cfg(feature = "json")]
+use crate::api::typed::TypedConsumer;
+#[cfg(feature = "json")]
+use crate::api::codec::{JsonCodec, MessageCodec};
+use crate::api::ack::AckManager;
+use crate::interceptor::{DeliveryInterceptor, MessageInterceptors, PublishInterceptor};
+use crate::api::topology::{DeadLetterOptsBuilder, DeadLetterTopology, Topology, TopologyEntity};
+use crate::error::{Error, Timeout, TopologyError};
+use crate::api::queue::{HeaderMatch, QueueBindOptsBuilder, QueueDeclareOk, QueueDeclareOptsBuilder};
+use crate::protocol::message::{Delivery, UnsettledCount};
+use crate::utils::{allocate_channel_id, duration_millis, IdAllocator};
+use crate::protocol::frame::{FrameEnvelope, Frame, BasicAck, BasicCancelOk, BasicConsume, BasicPublish, BasicNack, BasicQos, BasicReject, ChannelClose, ChannelCloseOk,
+                             ChannelFlow, ChannelFlowOk, ChannelOpen, ContentBody, ContentHeader, ExchangeDeclare, QueueBind,
+                             QueueDeclare, QueueUnbind, RawMethod, ConfirmSelect, TxCommit, TxRollback, TxSelect};
+
+// why a channel closed, see AmqChannel::on_close
+#[derive(Debug, Clone)]
+pub enum CloseReason {
+  // by the application, or by the client itself, e.g. after a method timed out or for content above the limits
+  Client { reply_code: ReplyCode, reply_text: String },
+  // the broker closed the channel with an exception
+  Exception(ChannelException),
+  // the connection closed or failed, taking the channel with it
+  ConnectionClosed,
+}
+
+pub(crate) type Subscriptions = Arc<Mutex<Vec<(BasicConsumeOpts, DeliverySender)>>>;
 
 pub struct AmqChannel {
   pub id: ChannelId,
   outgoing_tx: UnboundedSender<FrameEnvelope>,
-  command_tx: UnboundedSender<Command>
+  command_tx: UnboundedSender<Command>,
+  id_allocator: Arc<Mutex<IdAllocator>>,
+  frame_max: Int,
+  publish_window: PublishWindow,
+  // content frames of one message must not interleave with another publish on the channel
+  publish_lock: tokio::sync::Mutex<()>,
+  publish_limiter: Mutex<Option<Arc<PublishLimiter>>>,
+  #[cfg(any(feature = "gzip", feature = "zstd"))]
+  compression: Mutex<Option<crate::compression::Compression>>,
+  // set once the channel is in confirm mode
+  confirms: Arc<Mutex<Option<PendingConfirms>>>,
+  // set once the channel is in tx mode, publishes and acks take effect on commit only
+  transactional: AtomicBool,
+  // deliveries of the channel's consumers handed out and not settled yet
+  unsettled: UnsettledCount,
+  backlogs: ChannelBacklogs,
+  // publishing is allowed only while the broker keeps the channel flow active
+  flow_rx: watch::Receiver<bool>,
+  closed_tx: Arc<watch::Sender<Option<CloseReason>>>,
+  // settings restored by reopen after a channel level exception
+  qos: Mutex<Option<BasicQos>>,
+  consumers: Subscriptions,
+  raw_methods: Arc<Mutex<Option<UnboundedSender<RawMethod>>>>,
+  // the broker closes channels asynchronously for no_wait methods, keep the reason for later calls
+  exception: Arc<Mutex<Option<ChannelException>>>,
+  interceptors: MessageInterceptors,
+  metrics: Arc<dyn ClientMetrics>,
+  method_timeout: Duration,
 }
 
 impl AmqChannel {
-  pub async fn open(
+  pub(crate) async fn create(
+    outgoing_tx: UnboundedSender<FrameEnvelope>,
+    command_tx: UnboundedSender<Command>,
+    id_allocator: Arc<Mutex<IdAllocator>>,
+    frame_max: Int,
+    publish_window: PublishWindow,
+    metrics: Arc<dyn ClientMetrics>,
+    method_timeout: Duration,
+  ) -> Result<Self> {
+    let id = allocate_channel_id(&id_allocator)?;
+    info!("create channel {}", id);
+
+    let (channel_tx, channel_rx) = mpsc::unbounded_channel();
+    let shared = ChannelShared::default();
+    invoke_command_async!(command_tx, CommandPayload::RegisterChannel((id, channel_tx, shared.clone())));
+
+    match AmqChannel::open(id, outgoing_tx, channel_rx, command_tx, id_allocator.clone(), frame_max, publish_window, shared, metrics, method_timeout).await {
+      Ok(channel) => {
+        info!("channel {} created", id);
+        Ok(channel)
+      },
+      Err(err) => {
+        id_allocator.lock().unwrap().release(id);
+        Err(err)
+      }
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) async fn open(
     id: ChannelId,
     outgoing_tx: UnboundedSender<FrameEnvelope>,
     incoming_rx: UnboundedReceiver<FrameEnvelope>,
     command_tx: UnboundedSender<Command>,
+    id_allocator: Arc<Mutex<IdAllocator>>,
+    frame_max: Int,
+    publish_window: PublishWindow,
+    shared: ChannelShared,
+    metrics: Arc<dyn ClientMetrics>,
+    method_timeout: Duration,
   ) -> Result<Self> {
-    let open_method = ChannelOpen { reserved1: ShortStr("".into()) }.into_frame();
-    let _frame = invoke_sync_method!(id, command_tx, outgoing_tx, open_method).await?;
+    let open_method = ChannelOpen::builder().build()?.into_frame();
+    let responder_rx = invoke_sync_method!(id, command_tx, outgoing_tx, open_method);
+    await_reply(id, (ChannelOpen::CLASS_ID, ChannelOpen::METHOD_ID), responder_rx, method_timeout, &command_tx).await?;
+    let (flow_tx, flow_rx) = watch::channel(true);
     let channel = Self {
       id,
       outgoing_tx,
-      command_tx
+      command_tx,
+      id_allocator,
+      frame_max,
+      publish_window,
+      publish_lock: tokio::sync::Mutex::new(()),
+      publish_limiter: Mutex::new(None),
+      confirms: shared.confirms,
+      transactional: AtomicBool::new(false),
+      unsettled: shared.unsettled,
+      backlogs: shared.backlogs,
+      closed_tx: shared.closed,
+      #[cfg(any(feature = "gzip", feature = "zstd"))]
+      compression: Mutex::new(None),
+      flow_rx,
+      qos: Mutex::new(None),
+      consumers: Arc::new(Mutex::new(vec![])),
+      raw_methods: Arc::new(Mutex::new(None)),
+      exception: Arc::new(Mutex::new(None)),
+      interceptors: Default::default(),
+      metrics,
+      method_timeout,
     };
 
-    // channel.spawn_incoming_msg_handler(incoming_rx);
+    channel.spawn_incoming_msg_handler(incoming_rx, flow_tx, channel.closed_tx.clone());
 
     Ok(channel)
   }
 
-  // todo: do we need this?
-  fn spawn_incoming_msg_handler(&self, mut incoming_rx: UnboundedReceiver<FrameEnvelope>) {
-    tokio::spawn(async move {
-      while let Some((_, frame)) = incoming_rx.recv().await {
+  // opens a fresh channel in place of one closed by a channel level exception,
+  // re-applying the last qos and re-subscribing consumers still held by the application
+  pub async fn reopen(&mut self) -> Result<()> {
+    if !self.is_closed() {
+      bail!("Channel {} is still open", self.id);
+    }
+
+    info!("reopening channel {}", self.id);
+    let reopened = AmqChannel::create(
+      self.outgoing_tx.clone(),
+      self.command_tx.clone(),
+      self.id_allocator.clone(),
+      self.frame_max,
+      self.publish_window.clone(),
+      self.metrics.clone(),
+      self.method_timeout,
+    ).await?;
+
+    let qos = self.qos.lock().unwrap().take();
+    let confirm_mode = self.is_confirm_mode();
+    let transactional = self.is_transactional();
+    let consumers = std::mem::take(&mut *self.consumers.lock().unwrap());
+    let interceptors = self.interceptors.clone();
+    let publish_limiter = self.publish_limiter.lock().unwrap().take();
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    let compression = self.compression.lock().unwrap().take();
+    *self = reopened;
+    self.interceptors = interceptors;
+    *self.publish_limiter.lock().unwrap() = publish_limiter;
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    self.set_compression(compression);
+
+    if let Some(qos) = qos {
+      self.qos(qos.prefetch_count as u16, qos.global).await?;
+    }
+
+    if confirm_mode {
+      self.confirm_select().await?;
+    }
+
+    // work left uncommitted on the closed channel is gone, only the mode carries over
+    if transactional {
+      self.tx_select().await?;
+    }
+
+    for (opts, consumer_tx) in consumers {
+      if consumer_tx.is_closed() {
+        continue;
+      }
+
+      self.subscribe(opts, consumer_tx).await?;
+    }
+
+    info!("channel {} reopened", self.id);
+    Ok(())
+  }
+
+  fn spawn_incoming_msg_handler(
+    &self,
+    mut incoming_rx: UnboundedReceiver<FrameEnvelope>,
+    flow_tx: watch::Sender<bool>,
+    closed_tx: Arc<watch::Sender<Option<CloseReason>>>
+  ) {
+    let id = self.id;
+    let outgoing_tx = self.outgoing_tx.clone();
+    let id_allocator = self.id_allocator.clone();
+    let exception = self.exception.clone();
+    let consumers = self.consumers.clone();
+    let raw_methods = self.raw_methods.clone();
+    let confirms = self.confirms.clone();
+    runtime::spawn(async move {
+      while let Some((channel, frame)) = incoming_rx.recv().await {
         match frame {
+          Frame::ChannelFlow(flow) => {
+            let active = flow.active;
+            info!("channel {} flow changed by broker, active: {}", channel, active);
+            flow_tx.send_replace(active);
+            let _ = outgoing_tx.send((channel, ChannelFlowOk { active: flow.active }.into_frame()));
+          },
+          Frame::ChannelClose(close) => {
+            let channel_exception = ChannelException::from(close);
+            warn!("{}", channel_exception);
+            *exception.lock().unwrap() = Some(channel_exception.clone());
+            // close-ok goes out together with the change of state, see send_while_open. The id is
+            // released before, whoever sees the channel closed may reopen it right away
+            closed_tx.send_if_modified(|closed| {
+              let _ = outgoing_tx.send((channel, ChannelCloseOk {}.into_frame()));
+              id_allocator.lock().unwrap().release(channel);
+              closed.get_or_insert(CloseReason::Exception(channel_exception));
+              true
+            });
+            fail_confirms(&confirms);
+            break;
+          },
+          Frame::ChannelCloseOk(..) => {
+            // closed by the connection, e.g. for a delivery above the content limits, which left the reason
+            warn!("channel {} closed by the client", channel);
+            fail_confirms(&confirms);
+            id_allocator.lock().unwrap().release(channel);
+            break;
+          },
+          Frame::BasicCancel(cancel) => {
+            // the queue was deleted or a single active consumer lost its turn, the consumer stream ends
+            warn!("consumer {} cancelled by broker on channel {}", cancel.consumer_tag, channel);
+            consumers.lock().unwrap().retain(|(opts, _)| opts.tag != cancel.consumer_tag.as_str());
+            if !cancel.no_wait {
+              let _ = outgoing_tx.send((channel, BasicCancelOk { consumer_tag: cancel.consumer_tag }.into_frame()));
+            }
+          },
+          Frame::BasicAck(ack) => {
+            settle_confirms(&confirms, channel, ack.delivery_tag, ack.multiple, true);
+          },
+          Frame::BasicNack(nack) => {
+            settle_confirms(&confirms, channel, nack.delivery_tag, nack.multiple, false);
+          },
+          Frame::RawMethod(method) => {
+            let subscriber = raw_methods.lock().unwrap().clone();
+            match subscriber {
+              Some(subscriber) if subscriber.send(method.clone()).is_ok() => {},
+              _ => warn!("unhandled method {}.{} on channel {}", method.class_id, method.method_id, channel),
+            }
+          },
           _ => {
-            todo!("Implement handler")
+            warn!("unhandled frame on channel {}: {:?}", channel, frame);
           }
         }
       }
+
+      // the connection went away, calls on the channel fail from now on
+      fail_confirms(&confirms);
+      mark_closed(&closed_tx, CloseReason::ConnectionClosed);
+      info!("exited channel {} loop", id);
     });
   }
 
+  #[allow(clippy::too_many_arguments)]
   pub async fn declare_exchange(
     &self,
     name: &str,
@@ -68,23 +308,56 @@ impl AmqChannel {
       builder.passive(passive);
       builder.auto_delete(auto_delete);
       builder.internal(internal);
-      builder.props(props.unwrap_or_else(|| HashMap::new()))
+      builder.arguments(props.unwrap_or_default());
     }).await
   }
 
   pub async fn declare_exchange_with_builder<F>(&self, configure: F) -> Result<()>
-    where F: FnOnce(&mut ExchangeDeclareOptsBuilder) -> ()
+    where F: FnOnce(&mut ExchangeDeclareOptsBuilder)
   {
     info!("declare exchange");
     let mut builder = ExchangeDeclareOptsBuilder::new();
     configure(&mut builder);
-    let method = ExchangeDeclare::from(builder.build());
-    let _frame = invoke_sync_method!(self.id, self.command_tx, self.outgoing_tx, method.into_frame()).await?;
+    let opts = builder.build();
+    let no_wait = opts.no_wait;
+    let timeout = opts.timeout;
+    let method = ExchangeDeclare::from(opts);
+
+    if no_wait {
+      self.invoke_async_method(method.into_frame())?;
+      info!("declare exchange sent without waiting for confirmation");
+      return Ok(());
+    }
+
+    let frame = self.invoke_sync_method_within(method.into_frame(), timeout).await?;
+    let _declare_ok = unwrap_frame_variant!(frame, ExchangeDeclareOk);
     info!("declared exchange");
 
     Ok(())
   }
 
+  pub async fn declare_delayed_exchange(&self, name: &str, delayed_type: ExchangeType) -> Result<()> {
+    self.declare_exchange_with_builder(|builder| {
+      builder.name(name.into());
+      builder.delayed(delayed_type);
+    }).await
+  }
+
+  // fanout exchange with a catch-all queue, meant to be set as alternate_exchange of other exchanges
+  pub async fn declare_alternate_exchange(&self, name: &str, queue: &str) -> Result<QueueDeclareOk> {
+    self.declare_exchange_with_builder(|builder| {
+      builder.name(name.into());
+      builder.ty(ExchangeType::Fanout);
+    }).await?;
+
+    let queue = self.declare_queue_with_builder(|builder| {
+      builder.name(queue.into());
+    }).await?;
+    self.bind(&queue.name, name, "", None).await?;
+
+    Ok(queue)
+  }
+
   pub async fn declare_queue(
     &self,
     name: &str,
@@ -93,7 +366,7 @@ impl AmqChannel {
     auto_delete: bool,
     exclusive: bool,
     props: Option<PropTable>
-  ) -> Result<String> {
+  ) -> Result<QueueDeclareOk> {
     self.declare_queue_with_builder(move |builder| {
       builder.name(name.to_string());
       builder.durable(durable);
@@ -101,150 +374,958 @@ impl AmqChannel {
       builder.auto_delete(auto_delete);
       builder.exclusive(exclusive);
       builder.no_wait(false);
-      builder.props(props.unwrap_or_else(|| PropTable::new()));
+      builder.arguments(props.unwrap_or_default());
     }).await
   }
+
+  // escape hatch for broker extensions, the arguments are sent as they are
+  pub fn send_raw_method(&self, method: RawMethod) -> Result<()> {
+    self.invoke_async_method(method.into_frame())
+  }
+
+  // methods the crate doesn't model are delivered here, only the latest receiver gets them
+  pub fn raw_methods(&self) -> UnboundedReceiver<RawMethod> {
+    let (raw_tx, raw_rx) = mpsc::unbounded_channel();
+    *self.raw_methods.lock().unwrap() = Some(raw_tx);
+    raw_rx
+  }
+
+  fn invoke_async_method(&self, frame: Frame) -> Result<()> {
+    self.send_while_open(|| Ok(self.outgoing_tx.send((self.id, frame))?))
+  }
+
   async fn invoke_sync_method(&self, frame: Frame) -> Result<Frame> {
-    Ok(invoke_sync_method!(self.id, self.command_tx, self.outgoing_tx, frame).await?)
+    self.invoke_sync_method_within(frame, None).await
   }
 
-  pub async fn declare_queue_with_builder<F>(&self, configure: F) -> Result<String>
-    where F: FnOnce(&mut QueueDeclareOptsBuilder) -> ()
+  // waits for the reply up to the timeout given, the channel's method timeout otherwise
+  async fn invoke_sync_method_within(&self, frame: Frame, timeout: Option<Duration>) -> Result<Frame> {
+    self.ensure_open()?;
+    let ids = frame.method_ids().unwrap_or_default();
+    let (responder_tx, responder_rx) = oneshot::channel::<Frame>();
+    invoke_command_async!(self.command_tx, CommandPayload::RegisterResponder((self.id, responder_tx)));
+    // a responder left behind by a close meanwhile is dropped once the id is registered again
+    self.send_while_open(|| Ok(self.outgoing_tx.send((self.id, frame))?))?;
+    self.await_method_reply(ids, responder_rx, timeout).await
+  }
+
+  // for the close of the channel, which is marked closed already
+  async fn call_within(&self, frame: Frame, timeout: Option<Duration>) -> Result<Frame> {
+    let ids = frame.method_ids().unwrap_or_default();
+    let responder_rx = invoke_sync_method!(self.id, self.command_tx, self.outgoing_tx, frame);
+    self.await_method_reply(ids, responder_rx, timeout).await
+  }
+
+  async fn await_method_reply(&self, ids: (Short, Short), responder_rx: oneshot::Receiver<Frame>, timeout: Option<Duration>) -> Result<Frame> {
+    let span = debug_span!("sync_method", channel = self.id, class_id = ids.0, method_id = ids.1);
+
+    async {
+      match await_reply(self.id, ids, responder_rx, timeout.unwrap_or(self.method_timeout), &self.command_tx).await {
+        Ok(Frame::ChannelClose(close)) => {
+          // the channel's task replies close-ok and releases the id, the caller could reopen right away
+          // and must not get ahead of it
+          let exception = ChannelException::from(close);
+          self.exception.lock().unwrap().get_or_insert_with(|| exception.clone());
+          let _ = runtime::timeout(self.method_timeout, self.on_close()).await;
+          mark_closed(&self.closed_tx, CloseReason::Exception(exception.clone()));
+          Err(exception.into())
+        },
+        Ok(frame) => Ok(frame),
+        // a timeout closed the channel already, further calls are refused right away
+        Err(err) => Err(err),
+      }
+    }.instrument(span).await
+  }
+
+  pub async fn declare_queue_with_builder<F>(&self, configure: F) -> Result<QueueDeclareOk>
+    where F: FnOnce(&mut QueueDeclareOptsBuilder)
   {
     info!("declare queue");
     let mut opts = QueueDeclareOptsBuilder::new();
 
     configure(&mut opts);
 
-    let method = QueueDeclare::from(opts.build());
-    let frame = self.invoke_sync_method(method.into_frame()).await?;
+    let opts = opts.build();
+    opts.validate()?;
+    if opts.no_wait {
+      if opts.name.is_empty() {
+        bail!("Queue name is required when declaring with no_wait");
+      }
+
+      let name = opts.name.clone();
+      let method = QueueDeclare::from(opts);
+      self.invoke_async_method(method.into_frame())?;
+      info!("declare queue {} sent without waiting for confirmation", &name);
+
+      return Ok(QueueDeclareOk { name, message_count: 0, consumer_count: 0 });
+    }
+
+    let timeout = opts.timeout;
+    let method = QueueDeclare::from(opts);
+    let frame = self.invoke_sync_method_within(method.into_frame(), timeout).await?;
     let declare_ok = unwrap_frame_variant!(frame, QueueDeclareOk);
-    info!("declared queue {}", &declare_ok.name.0);
-    // todo: into impl
-    Ok(declare_ok.name.0)
+    info!("declared queue {}", declare_ok.queue);
+
+    Ok(declare_ok.into())
   }
 
-  pub async fn bind(&self, queue_name: &str, exchange_name: &str, routing_key: &str) -> Result<()> {
-    info!("bind queue: {} to: exchange {} with key: {}", queue_name.clone(), exchange_name.clone(), routing_key.clone());
-    let method = QueueBind {
-      reserved1: 0,
-      queue: queue_name.into(),
-      exchange: exchange_name.into(),
-      routing_key: routing_key.into(),
-      no_wait: 0,
-      table: HashMap::new()
-    };
+  pub async fn bind(&self, queue_name: &str, exchange_name: &str, routing_key: &str, props: Option<PropTable>) -> Result<()> {
+    self.bind_with_builder(|builder| {
+      builder.queue(queue_name.into());
+      builder.exchange(exchange_name.into());
+      builder.routing_key(routing_key.into());
+      builder.arguments(props.unwrap_or_default());
+    }).await
+  }
 
-    let frame = self.invoke_sync_method(method.into_frame()).await?;
+  pub async fn bind_headers(&self, queue: &str, exchange: &str, header_match: HeaderMatch) -> Result<()> {
+    self.bind_with_builder(|builder| {
+      builder.queue(queue.into());
+      builder.exchange(exchange.into());
+      builder.header_match(header_match);
+    }).await
+  }
+
+  pub async fn bind_with_builder<F>(&self, configure: F) -> Result<()>
+    where F: FnOnce(&mut QueueBindOptsBuilder)
+  {
+    let mut builder = QueueBindOptsBuilder::new();
+    configure(&mut builder);
+    let opts = builder.build();
+    info!("bind queue: {} to: exchange {} with key: {}", opts.queue, opts.exchange, opts.routing_key);
+
+    let no_wait = opts.no_wait;
+    let timeout = opts.timeout;
+    let method = QueueBind::from(opts);
+
+    if no_wait {
+      self.invoke_async_method(method.into_frame())?;
+      info!("queue bind sent without waiting for confirmation");
+      return Ok(());
+    }
+
+    let frame = self.invoke_sync_method_within(method.into_frame(), timeout).await?;
     let _bind_ok = unwrap_frame_variant!(frame, QueueBindOk);
     info!("queue bound");
 
     Ok(())
   }
 
-  pub async fn unbind(&self, queue: &str, exchange: &str, routing_key: &str) -> Result<()> {
+  pub async fn unbind(&self, queue: &str, exchange: &str, routing_key: &str, props: Option<PropTable>) -> Result<()> {
+    info!("unbind queue: {} from: exchange {} with key: {}", queue, exchange, routing_key);
     let method = QueueUnbind {
-      reserved1: 0,
+      ticket: 0,
       queue: queue.into(),
       exchange: exchange.into(),
       routing_key: routing_key.into(),
-      table: HashMap::new()
+      arguments: props.unwrap_or_default()
     };
 
     let frame = self.invoke_sync_method(method.into_frame()).await?;
-    let _bind_ok = unwrap_frame_variant!(frame, QueueUnbindOk);
-    info!("queue bound");
+    let _unbind_ok = unwrap_frame_variant!(frame, QueueUnbindOk);
+    info!("queue unbound");
 
     Ok(())
   }
 
-  pub async fn consume(&self, queue: &str) -> Result<UnboundedReceiver<Message>> {
-    info!("consuming queue: {}", queue.clone());
-    let method = BasicConsume {
-      reserved1: 0,
-      queue: queue.into(),
-      tag: "".into(),
-      flags: 0,
-      props: HashMap::new()
+  pub async fn declare_dead_lettered_queue(&self, queue: &str) -> Result<DeadLetterTopology> {
+    self.declare_dead_lettered_queue_with_builder(|builder| {
+      builder.queue(queue.into());
+    }).await
+  }
+
+  // declares the dead letter exchange and queue first, so the work queue never points to a missing exchange
+  pub async fn declare_dead_lettered_queue_with_builder<F>(&self, configure: F) -> Result<DeadLetterTopology>
+    where F: FnOnce(&mut DeadLetterOptsBuilder)
+  {
+    let mut builder = DeadLetterOptsBuilder::new();
+    configure(&mut builder);
+    let opts = builder.build();
+    if opts.queue.is_empty() {
+      bail!("Queue name is required for a dead lettered queue");
+    }
+
+    let dead_letter_exchange = opts.dead_letter_exchange();
+    let durable = opts.durable;
+    self.declare_exchange_with_builder(|builder| {
+      builder.name(dead_letter_exchange.clone());
+      builder.ty(ExchangeType::Direct);
+      builder.durable(durable);
+    }).await?;
+
+    let dead_letter_queue = self.declare_queue_with_builder(|builder| {
+      builder.name(opts.dead_letter_queue());
+      builder.durable(durable);
+    }).await?;
+    self.bind(&dead_letter_queue.name, &dead_letter_exchange, &opts.dead_letter_routing_key(), None).await?;
+
+    let queue = self.declare_queue_with_builder(|builder| {
+      builder.name(opts.queue.clone());
+      builder.durable(durable);
+      builder.arguments(opts.queue_arguments());
+    }).await?;
+
+    Ok(DeadLetterTopology {
+      queue,
+      dead_letter_exchange,
+      dead_letter_queue,
+    })
+  }
+
+  // declares exchanges, then queues, then bindings, stopping at the first entity the broker refuses.
+  // Returns the declared queues in order, server named ones included
+  pub async fn apply(&self, topology: &Topology) -> Result<Vec<QueueDeclareOk>> {
+    topology.validate()?;
+
+    for opts in &topology.exchanges {
+      let entity = TopologyEntity::Exchange(opts.name.clone());
+      self.declare_exchange_with_builder(|builder| *builder = ExchangeDeclareOptsBuilder::from(opts.clone())).await
+        .map_err(|err| TopologyError::new(entity, err))?;
+    }
+
+    let mut queues = Vec::with_capacity(topology.queues.len());
+    for opts in &topology.queues {
+      let entity = TopologyEntity::Queue(opts.name.clone());
+      let queue = self.declare_queue_with_builder(|builder| *builder = QueueDeclareOptsBuilder::from(opts.clone())).await
+        .map_err(|err| TopologyError::new(entity, err))?;
+      queues.push(queue);
+    }
+
+    for opts in &topology.bindings {
+      let entity = TopologyEntity::binding(opts);
+      self.bind_with_builder(|builder| *builder = QueueBindOptsBuilder::from(opts.clone())).await
+        .map_err(|err| TopologyError::new(entity, err))?;
+    }
+
+    info!("applied topology of {} exchanges, {} queues and {} bindings", topology.exchanges.len(), queues.len(), topology.bindings.len());
+    Ok(queues)
+  }
+
+  pub async fn qos(&self, prefetch_count: u16, global: bool) -> Result<()> {
+    info!("channel {} qos, prefetch count: {}", self.id, prefetch_count);
+    let method = BasicQos {
+      prefetch_size: 0,
+      prefetch_count: prefetch_count as Short,
+      global
     };
 
-    let frame = self.invoke_sync_method(method.into_frame()).await?;
+    let frame = self.invoke_sync_method(method.clone().into_frame()).await?;
+    let _qos_ok = unwrap_frame_variant!(frame, BasicQosOk);
+    *self.qos.lock().unwrap() = Some(method);
+
+    Ok(())
+  }
+
+  pub async fn consume(&self, queue: &str) -> Result<Consumer> {
+    self.consume_with_builder(|builder| {
+      builder.queue(queue.into());
+    }).await
+  }
+
+  pub async fn consume_with_builder<F>(&self, configure: F) -> Result<Consumer>
+    where F: FnOnce(&mut BasicConsumeOptsBuilder)
+  {
+    let mut builder = BasicConsumeOptsBuilder::new();
+    configure(&mut builder);
+    self.consume_with_opts(builder.build()).await
+  }
+
+  pub async fn consume_with_opts(&self, opts: BasicConsumeOpts) -> Result<Consumer> {
+    let (consumer_tx, consumer_rx) = delivery_channel();
+    let no_ack = opts.no_ack;
+    let slow_consumer = opts.slow_consumer.clone();
+    let tag = self.subscribe(opts, consumer_tx.clone()).await?;
+    if let Some(slow_consumer) = slow_consumer {
+      watch_lag(self.id, tag.clone(), slow_consumer, consumer_tx.backlog().clone(), self.metrics.clone());
+    }
+
+    Ok(Consumer::new(
+      tag,
+      self.id,
+      no_ack,
+      self.outgoing_tx.clone(),
+      self.command_tx.clone(),
+      consumer_rx,
+      self.exception.clone(),
+      self.interceptors.clone(),
+      self.consumers.clone(),
+      self.method_timeout,
+    ))
+  }
+
+  // deliveries with JSON bodies deserialized, bodies without a content type are taken as JSON
+  #[cfg(feature = "json")]
+  pub async fn consume_typed<T>(&self, queue: &str, opts: BasicConsumeOpts) -> Result<TypedConsumer<T>>
+    where T: serde::Serialize + serde::de::DeserializeOwned
+  {
+    self.consume_with_codec(queue, opts, JsonCodec).await
+  }
+
+  // bodies of a content type the codec doesn't accept are surfaced as UndecodableDelivery
+  #[cfg(feature = "json")]
+  pub async fn consume_with_codec<T, C: MessageCodec<T>>(&self, queue: &str, mut opts: BasicConsumeOpts, codec: C) -> Result<TypedConsumer<T, C>> {
+    opts.queue = queue.into();
+    Ok(TypedConsumer::new(self.consume_with_opts(opts).await?, codec))
+  }
+
+  // runs the handler for deliveries on supervised tasks, up to options.concurrency at a time,
+  // acking on success and nacking according to the policy when the handler fails or panics
+  pub async fn basic_consume_with<F, Fut>(&self, queue: &str, options: ConsumeHandlerOpts, handler: F) -> Result<JoinHandle<()>>
+    where F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<()>> + Send + 'static
+  {
+    self.basic_consume_until(queue, options, handler, std::future::pending()).await
+  }
+
+  // like basic_consume_with, cancelling the subscription once the signal completes, e.g.
+  // token.cancelled_owned() of a CancellationToken. Deliveries received until then are still handled,
+  // the returned task finishes once the last of them is settled
+  pub async fn basic_consume_until<F, Fut, S>(&self, queue: &str, mut options: ConsumeHandlerOpts, handler: F, signal: S) -> Result<JoinHandle<()>>
+    where F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<()>> + Send + 'static,
+          S: Future<Output = ()> + Send + 'static
+  {
+    options.consume.queue = queue.into();
+    options.consume.no_ack = false;
+    let concurrency = options.concurrency.max(1);
+    self.ensure_prefetch(concurrency).await?;
+
+    let (consumer_tx, mut consumer_rx) = delivery_channel();
+    let slow_consumer = options.consume.slow_consumer.clone();
+    let tag = self.subscribe(options.consume, consumer_tx.clone()).await?;
+    if let Some(slow_consumer) = slow_consumer {
+      watch_lag(self.id, tag.clone(), slow_consumer, consumer_tx.backlog().clone(), self.metrics.clone());
+    }
+    let subscription = Subscription {
+      tag: tag.clone(),
+      channel: self.id,
+      outgoing_tx: self.outgoing_tx.clone(),
+      command_tx: self.command_tx.clone(),
+      subscriptions: self.consumers.clone(),
+      timeout: self.method_timeout,
+    };
+    let on_error = options.on_error;
+    let handler = Arc::new(handler);
+    let workers = Arc::new(Semaphore::new(concurrency as usize));
+    let interceptors = self.interceptors.clone();
+
+    let handle = runtime::spawn(async move {
+      let mut signal = Box::pin(signal);
+      let mut cancelled = false;
+      loop {
+        // deliveries wait in the queue for a free worker, so they count as queued until one takes them
+        let worker = match workers.clone().acquire_owned().await {
+          Ok(worker) => worker,
+          Err(_) => break,
+        };
+        let delivery = tokio::select! {
+          delivery = consumer_rx.recv() => delivery,
+          _ = &mut signal, if !cancelled => {
+            cancelled = true;
+            info!("cancelling consumer {} on signal", tag);
+            if let Err(err) = subscription.cancel().await {
+              // the channel is gone, so are the deliveries
+              warn!("failed to cancel consumer {} on signal: {}", tag, err);
+              drop(worker);
+              break;
+            }
+            continue;
+          },
+        };
+        let Some(delivery) = delivery else {
+          drop(worker);
+          break;
+        };
+        let Some(delivery) = interceptors.on_delivery(delivery, false) else {
+          continue;
+        };
+        let acker = delivery.acker();
+        acker.track();
+        consumer_rx.backlog().handed_out(acker);
+        let handler = handler.clone();
+        let tag = tag.clone();
+
+        runtime::spawn(async move {
+          handle_delivery(&tag, delivery, handler, on_error).await;
+          drop(worker);
+        });
+      }
+
+      // wait for deliveries still being processed
+      let _ = workers.acquire_many(concurrency as u32).await;
+      info!("consumer {} handler finished", tag);
+    });
+
+    Ok(handle)
+  }
+
+  // prefetch below the handler concurrency would leave workers idle
+  async fn ensure_prefetch(&self, concurrency: u16) -> Result<()> {
+    let current = self.qos.lock().unwrap().as_ref().map(|qos| qos.prefetch_count as u16);
+
+    match current {
+      Some(0) => Ok(()),
+      Some(prefetch_count) if prefetch_count >= concurrency => Ok(()),
+      _ => self.qos(concurrency, false).await
+    }
+  }
+
+  async fn subscribe(&self, mut opts: BasicConsumeOpts, consumer_tx: DeliverySender) -> Result<String> {
+    info!("consuming queue: {}", opts.queue);
+    self.backlogs.register(consumer_tx.backlog());
+
+    if opts.no_wait {
+      opts.ensure_tag(self.id);
+      // register first, deliveries may arrive right after the consume frame
+      invoke_command_async!(self.command_tx, CommandPayload::RegisterConsumer(self.id, opts.tag.clone(), consumer_tx.clone()));
+      self.invoke_async_method(BasicConsume::from(opts.clone()).into_frame())?;
+      info!("consume sent without waiting for confirmation, tag: {}", opts.tag);
+      let tag = opts.tag.clone();
+      self.consumers.lock().unwrap().push((opts, consumer_tx));
+
+      return Ok(tag);
+    }
+
+    let frame = self.invoke_sync_method_within(BasicConsume::from(opts.clone()).into_frame(), opts.timeout).await?;
     let consume_ok = unwrap_frame_variant!(frame, BasicConsumeOk);
 
-    let (consumer_tx, consumer_rx) = mpsc::unbounded_channel();
-    invoke_command_async!(self.command_tx, CommandPayload::RegisterConsumer(self.id, consume_ok.tag.0.clone(), consumer_tx));
-    info!("consume ok with tag: {}", consume_ok.tag.0);
+    invoke_command_async!(self.command_tx, CommandPayload::RegisterConsumer(self.id, consume_ok.consumer_tag.to_string(), consumer_tx.clone()));
+    // keep the broker generated tag, it identifies the consumer in cancel notifications and on reopen
+    opts.tag = consume_ok.consumer_tag.to_string();
+    self.consumers.lock().unwrap().push((opts, consumer_tx));
+    info!("consume ok with tag: {}", consume_ok.consumer_tag);
+
+    Ok(consume_ok.consumer_tag.into())
+  }
+
+  pub async fn publish(&self, exchange: &str, routing_key: &str, body: Vec<u8>, properties: BasicProperties) -> Result<()> {
+    let opts = BasicPublishOpts {
+      exchange: exchange.into(),
+      routing_key: routing_key.into(),
+      ..Default::default()
+    };
+    self.publish_with_opts(opts, body, properties).await
+  }
+
+  pub async fn publish_delayed(
+    &self,
+    exchange: &str,
+    routing_key: &str,
+    delay: Duration,
+    body: Vec<u8>,
+    mut properties: BasicProperties
+  ) -> Result<()> {
+    properties.headers
+      .get_or_insert_with(PropTable::new)
+      .insert(DELAY_HEADER.into(), Property::Int(duration_millis(delay)));
+    self.publish(exchange, routing_key, body, properties).await
+  }
+
+  pub fn add_publish_interceptor(&self, interceptor: Arc<dyn PublishInterceptor>) {
+    self.interceptors.add_publish(interceptor);
+  }
+
+  // consumers created before see the deliveries through the interceptor as well
+  pub fn add_delivery_interceptor(&self, interceptor: Arc<dyn DeliveryInterceptor>) {
+    self.interceptors.add_delivery(interceptor);
+  }
+
+  // publishes wait for the rate, try_publish fails with PublishRateLimited instead. None removes the limit
+  pub fn set_publish_rate(&self, rate: Option<PublishRate>) {
+    *self.publish_limiter.lock().unwrap() = rate.map(|rate| Arc::new(PublishLimiter::new(rate)));
+  }
+
+  // compresses bodies of publish and publish_batch, streamed bodies are sent as they are
+  #[cfg(any(feature = "gzip", feature = "zstd"))]
+  pub fn set_compression(&self, compression: Option<crate::compression::Compression>) {
+    *self.compression.lock().unwrap() = compression;
+  }
+
+  // consumers and the confirm or tx mode stay with the channel until it's closed
+  pub(crate) fn is_reusable(&self) -> bool {
+    self.consumers.lock().unwrap().is_empty() && !self.is_confirm_mode() && !self.is_transactional()
+  }
+
+  // drops the hooks of a borrower before a pool lends the channel again
+  pub(crate) fn reset_hooks(&mut self, publish_rate: Option<PublishRate>) {
+    self.interceptors = Default::default();
+    self.set_publish_rate(publish_rate);
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    self.set_compression(None);
+    *self.raw_methods.lock().unwrap() = None;
+  }
+
+  pub fn publish_rate(&self) -> Option<PublishRate> {
+    self.publish_limiter.lock().unwrap().as_ref().map(|limiter| limiter.rate())
+  }
+
+  // serialized with serde_json, the content type is set to application/json
+  #[cfg(feature = "json")]
+  pub async fn publish_json<T: serde::Serialize + ?Sized>(&self, exchange: &str, routing_key: &str, value: &T) -> Result<()> {
+    self.publish_to(exchange, routing_key).json(value)?.send().await
+  }
+
+  pub fn publish_to(&self, exchange: &str, routing_key: &str) -> PublishBuilder<'_> {
+    PublishBuilder::new(self, exchange, routing_key)
+  }
+
+  pub async fn publish_with_opts(&self, opts: BasicPublishOpts, body: Vec<u8>, properties: BasicProperties) -> Result<()> {
+    self.publish_batch([(opts, body, properties)]).await
+  }
+
+  // the broker acks or nacks every publish from here on, it can't be turned off for the channel
+  pub async fn confirm_select(&self) -> Result<()> {
+    if self.is_transactional() {
+      bail!("Channel {} is in tx mode, it can't be put in confirm mode", self.id);
+    }
+
+    let frame = self.invoke_sync_method(ConfirmSelect { no_wait: false }.into_frame()).await?;
+    let _select_ok = unwrap_frame_variant!(frame, ConfirmSelectOk);
+    self.confirms.lock().unwrap().get_or_insert_with(PendingConfirms::default);
+    info!("channel {} in confirm mode", self.id);
+    Ok(())
+  }
+
+  pub fn is_confirm_mode(&self) -> bool {
+    self.confirms.lock().unwrap().is_some()
+  }
+
+  // publishes waiting for the broker's ack
+  pub fn unconfirmed(&self) -> usize {
+    self.confirms.lock().unwrap().as_ref().map_or(0, |confirms| confirms.pending())
+  }
 
-    Ok(consumer_rx)
+  // deliveries handed to the channel's consumers and not settled yet
+  pub fn unacked(&self) -> usize {
+    self.unsettled.get()
   }
 
-  pub async fn publish(&self, exchange: &str, routing_key: &str, body: Vec<u8>, properties: MessageProperties) -> Result<()> {
+  // returns once the message is queued, the confirmation resolves once the broker settled it
+  pub async fn publish_with_confirm(&self, opts: BasicPublishOpts, body: Vec<u8>, properties: BasicProperties) -> Result<Confirmation> {
+    if !self.is_confirm_mode() {
+      bail!("Channel {} is not in confirm mode", self.id);
+    }
 
-    info!("Publishing message");
-    let method = BasicPublish {
-      reserved1: 0,
+    let acks = self.send_publishes([(opts, body, properties)], true).await?;
+    Ok(Confirmation::new(self.id, acks, self.exception.clone()))
+  }
+
+  // publishes and acks of the channel take effect only once committed, from here on. Like confirm
+  // mode it can't be turned off, and the two exclude each other
+  pub async fn tx_select(&self) -> Result<()> {
+    if self.is_confirm_mode() {
+      bail!("Channel {} is in confirm mode, it can't be put in tx mode", self.id);
+    }
+
+    let frame = self.invoke_sync_method(TxSelect {}.into_frame()).await?;
+    let _select_ok = unwrap_frame_variant!(frame, TxSelectOk);
+    self.transactional.store(true, Ordering::Relaxed);
+    info!("channel {} in tx mode", self.id);
+    Ok(())
+  }
+
+  pub fn is_transactional(&self) -> bool {
+    self.transactional.load(Ordering::Relaxed)
+  }
+
+  // everything published and acked on the channel since the last commit or rollback, by any task
+  pub async fn tx_commit(&self) -> Result<()> {
+    if !self.is_transactional() {
+      bail!("Channel {} is not in tx mode", self.id);
+    }
+
+    let frame = self.invoke_sync_method(TxCommit {}.into_frame()).await?;
+    let _commit_ok = unwrap_frame_variant!(frame, TxCommitOk);
+    Ok(())
+  }
+
+  // drops the uncommitted publishes, rolled back acks aren't redelivered until the channel closes
+  pub async fn tx_rollback(&self) -> Result<()> {
+    if !self.is_transactional() {
+      bail!("Channel {} is not in tx mode", self.id);
+    }
+
+    let frame = self.invoke_sync_method(TxRollback {}.into_frame()).await?;
+    let _rollback_ok = unwrap_frame_variant!(frame, TxRollbackOk);
+    Ok(())
+  }
+
+  // puts the channel in the mode publish_guaranteed needs, a no-op when it's in there already
+  pub async fn set_guarantee(&self, mode: GuaranteeMode) -> Result<()> {
+    let current = self.guarantee();
+    if current == mode {
+      return Ok(());
+    }
+
+    match mode {
+      GuaranteeMode::Confirmed => self.confirm_select().await,
+      GuaranteeMode::Transactional => self.tx_select().await,
+      GuaranteeMode::FireAndForget => bail!("Channel {} is {:?}, the mode can't be turned off", self.id, current),
+    }
+  }
+
+  pub fn guarantee(&self) -> GuaranteeMode {
+    if self.is_transactional() {
+      GuaranteeMode::Transactional
+    } else if self.is_confirm_mode() {
+      GuaranteeMode::Confirmed
+    } else {
+      GuaranteeMode::FireAndForget
+    }
+  }
+
+  // returns once the channel's guarantee holds for the message: queued for the socket, acked by the
+  // broker, or committed
+  pub async fn publish_guaranteed(&self, opts: BasicPublishOpts, body: Vec<u8>, properties: BasicProperties) -> Result<()> {
+    match self.guarantee() {
+      GuaranteeMode::FireAndForget => self.publish_with_opts(opts, body, properties).await,
+      GuaranteeMode::Confirmed => self.publish_with_confirm(opts, body, properties).await?.wait().await,
+      GuaranteeMode::Transactional => {
+        self.publish_with_opts(opts, body, properties).await?;
+        self.tx_commit().await
+      },
+    }
+  }
+
+  // fails with PublishQueueFull instead of waiting for the writer to catch up
+  pub async fn try_publish(&self, exchange: &str, routing_key: &str, body: Vec<u8>, properties: BasicProperties) -> Result<()> {
+    let opts = BasicPublishOpts {
       exchange: exchange.into(),
       routing_key: routing_key.into(),
-      flags: 0,
+      ..Default::default()
     };
+    self.send_publishes([(opts, body, properties)], false).await?;
+    Ok(())
+  }
+
+  // all frames of the batch reach the socket with a single write, not interleaved with other frames
+  pub async fn publish_batch<I>(&self, messages: I) -> Result<()>
+    where I: IntoIterator<Item = (BasicPublishOpts, Vec<u8>, BasicProperties)>
+  {
+    self.send_publishes(messages, true).await?;
+    Ok(())
+  }
+
+  // returns the confirms of the publishes when the channel is in confirm mode
+  async fn send_publishes<I>(&self, messages: I, wait: bool) -> Result<Vec<oneshot::Receiver<bool>>>
+    where I: IntoIterator<Item = (BasicPublishOpts, Vec<u8>, BasicProperties)>
+  {
+    self.ensure_open()?;
+    self.wait_flow_active().await?;
+
+    let max_chunk = self.max_body_frame_size();
+    let mut frames = vec![];
+    let mut count = 0;
+    let mut bytes = 0;
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    let compression = *self.compression.lock().unwrap();
+    for (opts, body, mut properties) in messages {
+      self.interceptors.on_publish(&opts, &mut properties)?;
+      #[cfg(any(feature = "gzip", feature = "zstd"))]
+      let body = match &compression {
+        Some(compression) => compression.compress(body, &mut properties)?,
+        None => body,
+      };
+      properties.validate()?;
+      #[cfg(feature = "opentelemetry")]
+      let properties = crate::telemetry::inject(properties);
+      let method: BasicPublish = opts.into();
+      let header = ContentHeader {
+        class_id: BasicPublish::CLASS_ID,
+        body_len: body.len() as Long,
+        prop_list: properties,
+      };
+      frames.push(method.into_frame());
+      frames.push(header.into_frame());
+      // bodies above frame_max get the connection closed with a frame error, an empty body has no frames at all
+      let body = Bytes::from(body);
+      let mut offset = 0;
+      while offset < body.len() {
+        let end = body.len().min(offset + max_chunk);
+        frames.push(ContentBody(body.slice(offset..end)).into_frame());
+        offset = end;
+      }
+      count += 1;
+      bytes += body.len() as u64;
+    }
+
+    if count == 0 {
+      return Ok(vec![]);
+    }
+
+    let limiter = self.publish_limiter.lock().unwrap().clone();
+    if let Some(limiter) = limiter {
+      match wait {
+        true => limiter.acquire(count, bytes).await,
+        false => limiter.try_acquire(count, bytes)?,
+      }
+    }
+
+    match wait {
+      true => self.publish_window.reserve(count).await?,
+      false => self.publish_window.try_reserve(count)?,
+    }
+
+    debug!("Publishing {} messages", count);
+    let _guard = self.publish_lock.lock().await;
+    // sequence numbers follow the order the publishes are queued in
+    self.send_while_open(|| {
+      let acks = self.register_confirms(count);
+      self.outgoing_tx.send((self.id, Frame::Batch(frames)))?;
+      Ok(acks)
+    })
+  }
+
+  fn register_confirms(&self, count: usize) -> Vec<oneshot::Receiver<bool>> {
+    self.confirms.lock().unwrap().as_mut().map(|confirms| confirms.register(count)).unwrap_or_default()
+  }
+
+  // body is read and sent one frame at a time, so it is never held in memory as a whole
+  pub async fn publish_stream<R>(&self, opts: BasicPublishOpts, mut properties: BasicProperties, mut body: R, body_len: u64) -> Result<()>
+    where R: AsyncRead + Unpin
+  {
+    self.ensure_open()?;
+    self.interceptors.on_publish(&opts, &mut properties)?;
+    properties.validate()?;
+    #[cfg(feature = "opentelemetry")]
+    let properties = crate::telemetry::inject(properties);
+    self.wait_flow_active().await?;
+
+    let method: BasicPublish = opts.into();
     let header = ContentHeader {
-      class_id: 60,
-      body_len: body.len() as Long,
+      class_id: BasicPublish::CLASS_ID,
+      body_len: body_len as Long,
       prop_list: properties,
     };
-    let body = ContentBody(body);
-    self.outgoing_tx.send((self.id, method.into_frame())).unwrap();
-    self.outgoing_tx.send((self.id, header.into_frame())).unwrap();
-    self.outgoing_tx.send((self.id, body.into_frame())).unwrap();
 
+    let limiter = self.publish_limiter.lock().unwrap().clone();
+    if let Some(limiter) = limiter {
+      limiter.acquire(1, body_len).await;
+    }
+    self.publish_window.reserve(1).await?;
+    let _guard = self.publish_lock.lock().await;
+    // counted by the broker in confirm mode, nobody waits for it
+    self.send_while_open(|| {
+      self.register_confirms(1);
+      Ok(self.outgoing_tx.send((self.id, Frame::Batch(vec![method.into_frame(), header.into_frame()])))?)
+    })?;
+
+    let max_chunk = self.max_body_frame_size() as u64;
+    let mut remaining = body_len;
+    while remaining > 0 {
+      let mut chunk = vec![0_u8; remaining.min(max_chunk) as usize];
+      if let Err(err) = body.read_exact(&mut chunk).await {
+        // the broker still waits for the rest of the content, any other method on the channel
+        // would fail the whole connection, so the channel is closed instead
+        if let Err(close_err) = self.close_with_reason(ReplyCode::InternalError, "Streamed message body ended early").await {
+          warn!("failed to close channel {} after incomplete streamed message: {}", self.id, close_err);
+        }
+        bail!("Body of streamed message on channel {} ended {} bytes early: {}", self.id, remaining, err);
+      }
+
+      remaining -= chunk.len() as u64;
+      self.send_while_open(|| Ok(self.outgoing_tx.send((self.id, ContentBody(chunk.into()).into_frame()))?))?;
+    }
+
+    info!("Streamed message of {} bytes published", body_len);
+    Ok(())
+  }
+
+  // frame_max covers the 7 byte frame header and the frame end octet as well
+  fn max_body_frame_size(&self) -> usize {
+    if self.frame_max <= 0 {
+      return usize::MAX;
+    }
+
+    (self.frame_max as usize).saturating_sub(8).max(1)
+  }
+
+  // settles by tag count the deliveries they cover as settled, like the deliveries' own acks
+  pub fn ack(&self, delivery_tag: Long, multiple: bool) -> Result<()> {
+    self.backlogs.settled(delivery_tag, multiple);
+    self.invoke_async_method(BasicAck { delivery_tag, multiple }.into_frame())
+  }
+
+  pub fn ack_manager(&self, max_batch: usize, window: Duration) -> AckManager {
+    AckManager::new(self.id, self.outgoing_tx.clone(), max_batch, window)
+  }
+
+  pub fn nack(&self, delivery_tag: Long, multiple: bool, requeue: bool) -> Result<()> {
+    self.backlogs.settled(delivery_tag, multiple);
+    self.invoke_async_method(BasicNack::new(delivery_tag, multiple, requeue).into_frame())
+  }
+
+  pub fn reject(&self, delivery_tag: Long, requeue: bool) -> Result<()> {
+    self.backlogs.settled(delivery_tag, false);
+    self.invoke_async_method(BasicReject { delivery_tag, requeue }.into_frame())
+  }
+
+  pub async fn flow(&self, active: bool) -> Result<bool> {
+    info!("channel {} flow, active: {}", self.id, active);
+    let method = ChannelFlow { active };
+    let frame = self.invoke_sync_method(method.into_frame()).await?;
+    let flow_ok = unwrap_frame_variant!(frame, ChannelFlowOk);
+
+    Ok(flow_ok.active)
+  }
+
+  pub fn is_flow_active(&self) -> bool {
+    *self.flow_rx.borrow()
+  }
+
+  async fn wait_flow_active(&self) -> Result<()> {
+    let mut flow_rx = self.flow_rx.clone();
+    while !*flow_rx.borrow_and_update() {
+      info!("channel {} flow is paused by broker, waiting", self.id);
+      flow_rx.changed().await?;
+    }
+
+    Ok(())
+  }
+
+  pub async fn close(&self) -> Result<()> {
+    self.close_with_reason(ReplyCode::Success, "Normal shutdown").await
+  }
+
+  pub async fn close_with_reason(&self, reply_code: ReplyCode, reply_text: &str) -> Result<()> {
+    info!("closing channel {}", self.id);
+    let method = ChannelClose {
+      reply_code: reply_code.code(),
+      reply_text: reply_text.into(),
+      class_id: 0,
+      method_id: 0,
+    };
 
-    info!("Message was published");
+    // marked first, the channel is gone for the connection as soon as the close-ok arrives
+    self.ensure_open()?;
+    mark_closed(&self.closed_tx, CloseReason::Client { reply_code, reply_text: reply_text.into() });
+    let frame = self.call_within(method.into_frame(), None).await?;
+    let _close_ok = unwrap_frame_variant!(frame, ChannelCloseOk);
+    // closed on purpose, nothing to restore: let consumer receivers end
+    self.consumers.lock().unwrap().clear();
+    self.id_allocator.lock().unwrap().release(self.id);
+    info!("channel {} closed", self.id);
 
     Ok(())
   }
 
-//   pub async fn flow(&self, active: bool) -> Result<()> {
-//     use self::methods::Flow;
-//
-//     debug!("Invoking channel {} Flow", self.id);
-//     if self.active == active {
-//       return Ok(())
-//     }
-//
-//     self.invoke_sync_method(Flow { active: active as u8 }).await?;
-//     // self.active = active;
-//
-//     Ok(())
-//   }
-//
-
-
-//
-//   pub async fn close(&self) -> Result<()> {
-//     use crate::channel::methods::Close;
-//
-//     debug!("Closing channel {}", self.id);
-//     self.invoke_sync_method(Close {
-//       reply_code: 200,
-//       reply_text: "Closed".to_string(),
-//       class_id: 0,
-//       method_id: 0,
-//     }).await?;
-//
-//     Ok(())
-//   }
-//
-//   async fn invoke_sync_method<T: AmqpMethodArgs>(&self, args: T) -> Result<RawFrame> {
-//     let (tx, rx) = oneshot::channel::<RawFrame>();
-//     let sync_waiter_queue = self.sync_waiter_queue.clone();
-//     sync_waiter_queue.lock().unwrap().push(tx);
-//
-//     let request: Frame2<T> = Frame2::new(self.id, args);
-//     {
-//       let mut writer = self.con_writer.lock().await;
-//       writer.send_raw_frame(request.into()).await?;
-//     }
-//
-//     Ok(rx.await?)
-//   }
+  pub fn is_closed(&self) -> bool {
+    self.closed_tx.borrow().is_some()
+  }
+
+  pub fn close_reason(&self) -> Option<CloseReason> {
+    self.closed_tx.borrow().clone()
+  }
+
+  // resolves with the reason once the channel closed, whoever closed it, right away when it's closed
+  // already. Stays valid after the channel is dropped or reopened
+  pub fn on_close(&self) -> impl Future<Output = CloseReason> + Send + 'static {
+    let mut closed_rx = self.closed_tx.subscribe();
+    async move {
+      loop {
+        if let Some(reason) = closed_rx.borrow_and_update().clone() {
+          return reason;
+        }
+        if closed_rx.changed().await.is_err() {
+          return CloseReason::ConnectionClosed;
+        }
+      }
+    }
+  }
+
+  fn ensure_open(&self) -> Result<()> {
+    match self.is_closed() {
+      true => Err(self.closed_error()),
+      false => Ok(()),
+    }
+  }
+
+  // the broker takes a method arriving after the close-ok for a connection error, so frames are
+  // checked and queued under the lock the channel's task replies close-ok with
+  fn send_while_open<T>(&self, send: impl FnOnce() -> Result<T>) -> Result<T> {
+    let closed = self.closed_tx.borrow();
+    if closed.is_none() {
+      return send();
+    }
+    drop(closed);
+    Err(self.closed_error())
+  }
+
+  fn closed_error(&self) -> Error {
+    if let Some(exception) = self.exception.lock().unwrap().clone() {
+      return exception.into();
+    }
+
+    Error::closed(format!("Channel {} is closed", self.id))
+  }
+}
+
+// waits for the reply of a synchronous method, a zero timeout waits forever. Without a reply in time
+// the channel is closed, replies coming in later would be handed to the next calls
+pub(crate) async fn await_reply(
+  channel: ChannelId,
+  (class_id, method_id): (Short, Short),
+  responder_rx: oneshot::Receiver<Frame>,
+  timeout: Duration,
+  command_tx: &UnboundedSender<Command>,
+) -> Result<Frame> {
+  let reply = if timeout.is_zero() {
+    Some(responder_rx.await)
+  } else {
+    runtime::timeout(timeout, responder_rx).await
+  };
+
+  match reply {
+    Some(Ok(frame)) => Ok(frame),
+    Some(Err(_)) => Err(Error::closed(format!("Channel {} closed while waiting for response", channel))),
+    None => {
+      let err = Timeout { channel, class_id, method_id, timeout };
+      warn!("{}", err);
+      invoke_command_async!(command_tx, CommandPayload::CloseChannel(channel, ReplyCode::InternalError, err.to_string()));
+      Err(err.into())
+    }
+  }
+}
+
+async fn handle_delivery<F, Fut>(tag: &str, delivery: Delivery, handler: Arc<F>, on_error: NackPolicy)
+  where F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static
+{
+  let acker = delivery.acker();
+  let redelivered = delivery.get_metadata().is_redelivered();
+  let span = debug_span!("delivery", consumer = tag, channel = acker.channel(), delivery_tag = acker.delivery_tag(), redelivered);
+
+  // a panicking handler counts as failed
+  let result = runtime::spawn(handler(delivery).instrument(span.clone())).await.and_then(|result| result);
+  let _entered = span.enter();
+
+  // the handler is free to settle the delivery on its own
+  if acker.is_processed() {
+    return;
+  }
+
+  let settled = match result {
+    Ok(_) => acker.ack(false),
+    Err(err) => {
+      warn!("consumer {} handler failed: {}", tag, err);
+      acker.nack(false, on_error.requeue(redelivered))
+    }
+  };
+
+  if let Err(err) = settled {
+    warn!("consumer {} failed to settle delivery: {}", tag, err);
+  }
+}
+
+fn settle_confirms(confirms: &Mutex<Option<PendingConfirms>>, channel: ChannelId, delivery_tag: Long, multiple: bool, ack: bool) {
+  match confirms.lock().unwrap().as_mut() {
+    Some(confirms) => confirms.settle(delivery_tag, multiple, ack),
+    None => warn!("publish confirm {} on channel {} not in confirm mode", delivery_tag, channel),
+  }
+}
+
+fn fail_confirms(confirms: &Mutex<Option<PendingConfirms>>) {
+  if let Some(confirms) = confirms.lock().unwrap().as_mut() {
+    confirms.fail_all();
+  }
 }
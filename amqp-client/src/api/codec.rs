@@ -0,0 +1,112 @@
+use bytes::Bytes;
+use crate::Result;
+
+// turns application values into message bodies
+pub trait PayloadEncoder<T: ?Sized>: Send + Sync {
+  // set on messages not carrying a content type of their own
+  fn content_type(&self) -> Option<&str> {
+    None
+  }
+
+  fn encode(&self, payload: &T) -> Result<Vec<u8>>;
+}
+
+// both ways, used by publishers and typed consumers alike so services agree on a payload format
+pub trait MessageCodec<T>: PayloadEncoder<T> {
+  fn decode(&self, body: &[u8]) -> Result<T>;
+
+  // whether bodies of the content type, parameters stripped, can be decoded. Bodies without
+  // a content type are always decoded
+  fn accepts(&self, content_type: &str) -> bool {
+    self.content_type() == Some(content_type)
+  }
+}
+
+// bodies sent as they are
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawPayload;
+
+impl<T: AsRef<[u8]> + ?Sized> PayloadEncoder<T> for RawPayload {
+  fn encode(&self, payload: &T) -> Result<Vec<u8>> {
+    Ok(payload.as_ref().to_vec())
+  }
+}
+
+impl MessageCodec<Vec<u8>> for RawPayload {
+  fn decode(&self, body: &[u8]) -> Result<Vec<u8>> {
+    Ok(body.to_vec())
+  }
+
+  fn accepts(&self, _content_type: &str) -> bool {
+    true
+  }
+}
+
+impl MessageCodec<Bytes> for RawPayload {
+  fn decode(&self, body: &[u8]) -> Result<Bytes> {
+    Ok(Bytes::copy_from_slice(body))
+  }
+
+  fn accepts(&self, _content_type: &str) -> bool {
+    true
+  }
+}
+
+#[cfg(feature = "json")]
+pub(crate) const JSON_CONTENT_TYPE: &str = "application/json";
+
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize + ?Sized> PayloadEncoder<T> for JsonCodec {
+  fn content_type(&self) -> Option<&str> {
+    Some(JSON_CONTENT_TYPE)
+  }
+
+  fn encode(&self, payload: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(payload)?)
+  }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> MessageCodec<T> for JsonCodec {
+  fn decode(&self, body: &[u8]) -> Result<T> {
+    Ok(serde_json::from_slice(body)?)
+  }
+
+  fn accepts(&self, content_type: &str) -> bool {
+    matches!(content_type, JSON_CONTENT_TYPE | "text/json") || content_type.ends_with("+json")
+  }
+}
+
+#[cfg(feature = "msgpack")]
+const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+// structs are encoded as maps, so fields can be added without breaking older consumers
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl<T: serde::Serialize + ?Sized> PayloadEncoder<T> for MsgPackCodec {
+  fn content_type(&self) -> Option<&str> {
+    Some(MSGPACK_CONTENT_TYPE)
+  }
+
+  fn encode(&self, payload: &T) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(payload).map_err(crate::Error::other)
+  }
+}
+
+#[cfg(feature = "msgpack")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> MessageCodec<T> for MsgPackCodec {
+  fn decode(&self, body: &[u8]) -> Result<T> {
+    rmp_serde::from_slice(body).map_err(crate::Error::other)
+  }
+
+  fn accepts(&self, content_type: &str) -> bool {
+    matches!(content_type, MSGPACK_CONTENT_TYPE | "application/x-msgpack")
+  }
+}
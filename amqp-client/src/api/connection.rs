@@ -1,130 +1,365 @@
 use std::collections::HashMap;
-use std::time::{Duration, SystemTime};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use log::{info};
-use tokio::io::{BufReader, BufWriter};
-use tokio::net::TcpStream;
-use tokio::sync::{broadcast, mpsc};
+use tracing::{info, info_span, warn, Instrument};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::sync::{broadcast, mpsc, watch};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
-use crate::protocol::types::{ChannelId, LongStr, Property, ShortStr, PropTable};
-use crate::protocol::frame::{Frame, FrameEnvelope, ConnectionOpen, ConnectionStartOk, ConnectionTuneOk, ContentFrame, ConnectionClose};
+use crate::protocol::types::{ChannelId, Int, Property, Short, PropTable};
+use crate::protocol::frame::{BasicCancel, ChannelClose, Frame, FrameEnvelope, ConnectionOpen, ConnectionStartOk, ConnectionTuneOk, ConnectionClose, ConnectionCloseOk};
 
 use crate::{invoke_command_async, invoke_sync_method, Result, unwrap_frame_variant};
+use crate::error::{ConnectionException, ConnectionFailed, Error};
 use crate::api::channel::AmqChannel;
+use crate::api::pool::ChannelPool;
 use crate::api::connection::options::ConnectionArgs;
 use crate::api::connection::constants::PROTOCOL_HEADER;
+use crate::api::connection::snapshot::ConnectionSnapshot;
+use crate::api::connection::state::{transition, ConnectionState};
+use crate::protocol::reply_code::ReplyCode;
+use crate::protocol::spec::FRAME_MIN_SIZE;
 use crate::api::default_channel::DefaultAmqChannel;
-use crate::building_blocks::{ChannelManager, Command, CommandPayload};
+use crate::building_blocks::{ChannelManager, Command, CommandPayload, ContentBudget, PublishWindow};
 use self::constants::{COPYRIGHT, DEFAULT_AUTH_MECHANISM, DEFAULT_LOCALE, INFORMATION, PLATFORM, PRODUCT};
-use crate::protocol::net::{FrameReader, FrameWriter};
+use crate::protocol::net::{BufferPool, FrameReader, FrameWriter, ReadStream, WireTap, WriteStream};
+use crate::interceptor::Interceptors;
 use crate::utils::IdAllocator;
+use crate::runtime::{self, JoinHandle, TcpStream};
+use crate::protocol::message::UnsettledCount;
 
 pub mod constants;
 pub mod factory;
 pub mod options;
+pub mod snapshot;
+pub mod state;
 pub use self::factory::ConnectionFactory;
 
+const MAX_FRAMES_PER_FLUSH: usize = 256;
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// the limits agreed with the broker in the handshake, the lower of both sides where either set one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+  pub channel_max: Short,
+  // messages are split into body frames of up to this size, frame header and end included
+  pub frame_max: Int,
+  // seconds between heartbeats, zero when they are turned off
+  pub heartbeat: Short,
+}
+
 pub struct Connection {
   arguments: ConnectionArgs,
-  id_allocator: IdAllocator,
+  negotiated: Negotiated,
+  id_allocator: Arc<Mutex<IdAllocator>>,
   message_tx: UnboundedSender<FrameEnvelope>,
   command_tx: UnboundedSender<Command>,
   close_tx: broadcast::Sender<()>,
+  state_tx: Arc<watch::Sender<ConnectionState>>,
+  publish_window: PublishWindow,
+  unsettled: UnsettledCount,
+  // set once the broker closed the connection with an error
+  exception: Arc<Mutex<Option<ConnectionException>>>,
+  tasks: Vec<JoinHandle<()>>,
 }
 
 impl Connection {
   pub async fn open(stream: TcpStream, args: ConnectionArgs) -> Result<Connection> {
-    let stream_parts = stream.into_split();
-    let mut reader = FrameReader::new(BufReader::new(stream_parts.0));
-    let mut writer = FrameWriter::new(BufWriter::new(stream_parts.1));
+    let (read_half, write_half) = runtime::split(stream);
+    Self::open_parts(Box::new(read_half), Box::new(write_half), args).await
+  }
+
+  // over any byte stream instead of a socket, e.g. one end of tokio::io::duplex. The address of
+  // the arguments is only used for the credentials and the vhost
+  pub async fn open_stream<S>(stream: S, args: ConnectionArgs) -> Result<Connection>
+    where S: AsyncRead + AsyncWrite + Send + 'static
+  {
+    let (read_half, write_half) = tokio::io::split(stream);
+    Self::open_parts(Box::new(read_half), Box::new(write_half), args).await
+  }
+
+  async fn open_parts(read_half: ReadStream, write_half: WriteStream, args: ConnectionArgs) -> Result<Connection> {
+    let wiretap = Arc::new(WireTap::new(&args.wire_log, args.wire_log_payload_bytes)?);
+    let interceptors = Interceptors::new(args.interceptors.clone());
+    let mut reader = FrameReader::new(BufReader::new(read_half), FRAME_MIN_SIZE, args.metrics.clone(), wiretap.clone(), interceptors.clone());
+    let buffers = BufferPool::new(args.buffer_pool_size, args.max_pooled_buffer_size);
+    let mut writer = FrameWriter::new(write_half, buffers, FRAME_MIN_SIZE, args.metrics.clone(), wiretap, interceptors);
 
     let (msg_tx, msg_rx) = mpsc::unbounded_channel();
     let (command_tx, command_rx) = mpsc::unbounded_channel();
-    let (close_tx, close_rx) = broadcast::channel::<()>(1);
-
-    let connection = Self {
+    let (close_tx, _) = broadcast::channel::<()>(1);
+    let (state_tx, _) = watch::channel(ConnectionState::Open);
+
+    let publish_window = PublishWindow::new(args.max_pending_publishes);
+    let negotiated = Negotiated {
+      channel_max: args.max_channels,
+      frame_max: args.max_frame_size,
+      heartbeat: args.heartbeat_interval,
+    };
+    let mut connection = Self {
       arguments: args,
-      id_allocator: IdAllocator::new(),
+      negotiated,
+      id_allocator: Arc::new(Mutex::new(IdAllocator::new(0))),
       message_tx: msg_tx,
       command_tx,
-      close_tx
+      close_tx,
+      state_tx: Arc::new(state_tx),
+      publish_window,
+      unsettled: Default::default(),
+      exception: Default::default(),
+      tasks: vec![],
     };
 
-    connection.handshake(&mut reader, &mut writer).await?;
-    connection.spawn_connection_handlers(reader, writer, msg_rx, command_rx);
+    let span = info_span!("handshake", host = %connection.arguments.address.host, vhost = %connection.arguments.address.vhost);
+    connection.handshake(&mut reader, &mut writer).instrument(span).await?;
+    connection.tasks = connection.spawn_connection_handlers(reader, writer, msg_rx, command_rx);
 
     Ok(connection)
   }
 
-  pub async fn create_channel(&mut self) -> Result<AmqChannel> {
-    let id = self.id_allocator.allocate();
-    info!("create channel");
+  pub fn state(&self) -> ConnectionState {
+    self.state_tx.borrow().clone()
+  }
 
-    let (channel_tx, channel_rx) = mpsc::unbounded_channel();
+  pub fn negotiated(&self) -> Negotiated {
+    self.negotiated
+  }
 
-    invoke_command_async!(self.command_tx, CommandPayload::RegisterChannel((id, channel_tx)));
+  // yields every state change, e.g. to reconnect once the connection failed
+  pub fn state_changes(&self) -> watch::Receiver<ConnectionState> {
+    self.state_tx.subscribe()
+  }
 
-    let channel = AmqChannel::open(id, self.message_tx.clone(), channel_rx, self.command_tx.clone()).await?;
+  // open channels with their consumers, unacked deliveries and unconfirmed publishes, read on
+  // the connection's task so the list matches what the broker was told
+  pub async fn snapshot(&self) -> Result<ConnectionSnapshot> {
+    let (channels_tx, channels_rx) = oneshot::channel();
+    invoke_command_async!(self.command_tx, CommandPayload::ListChannels(channels_tx));
+
+    Ok(ConnectionSnapshot {
+      state: self.state(),
+      channels: channels_rx.await?,
+      unacked: self.unsettled.get(),
+      outgoing_queue_depth: self.publish_window.pending(),
+    })
+  }
 
-    info!("channel created");
+  pub async fn create_channel(&self) -> Result<AmqChannel> {
+    self.ensure_open()?;
+    let channel = AmqChannel::create(
+      self.message_tx.clone(),
+      self.command_tx.clone(),
+      self.id_allocator.clone(),
+      self.negotiated.frame_max,
+      self.publish_window.clone(),
+      self.arguments.metrics.clone(),
+      self.arguments.method_timeout,
+    ).await?;
+    channel.set_publish_rate(self.arguments.publish_rate);
     Ok(channel)
   }
 
+  pub fn channel_pool(&self, max_size: usize) -> ChannelPool {
+    ChannelPool::new(
+      self.message_tx.clone(),
+      self.command_tx.clone(),
+      self.id_allocator.clone(),
+      self.negotiated.frame_max,
+      self.publish_window.clone(),
+      self.arguments.metrics.clone(),
+      self.arguments.publish_rate,
+      self.arguments.method_timeout,
+      max_size
+    )
+  }
+
   pub async fn close(self) -> Result<()> {
+    self.request_close()
+  }
+
+  // the close-ok from the broker moves the connection to closed
+  pub(crate) fn request_close(&self) -> Result<()> {
     // todo!("provide reply code and text");
     let method = ConnectionClose {
-      reply_code: 0,
+      reply_code: ReplyCode::Success.code(),
       reply_text: "Connection closed".into(),
       class_id: 0,
       method_id: 0,
     };
     // invoke_sync_method!(0, self.command_tx, self.message_tx, method.into_frame()).await?;
-    self.message_tx.send((0, method.into_frame())).unwrap();
+    self.ensure_open()?;
+    self.message_tx.send((0, method.into_frame()))?;
     Ok(())
   }
 
-  async fn handshake(&self, reader: &mut FrameReader, writer: &mut FrameWriter) -> Result<()> {
+  // cancels consumers, waits for handed out deliveries to be settled, closes the channels and then
+  // the connection. Publishes are refused from the start, frames queued before go out first.
+  // Whatever is still pending once the grace period is over gets cut off
+  pub async fn shutdown(mut self, grace_period: Duration) -> Result<()> {
+    self.ensure_open()?;
+    let deadline = Instant::now() + grace_period;
+    info!("connection shutdown started, grace period: {:?}", grace_period);
+    transition(&self.state_tx, ConnectionState::Closing);
+    self.publish_window.close();
+
+    let (channels_tx, channels_rx) = oneshot::channel();
+    invoke_command_async!(self.command_tx, CommandPayload::ListChannels(channels_tx));
+    let channels = channels_rx.await?;
+
+    for channel in &channels {
+      for tag in &channel.consumer_tags {
+        let method = BasicCancel { consumer_tag: tag.clone().into(), no_wait: false };
+        self.call_until(channel.id, method.into_frame(), deadline).await;
+      }
+    }
+
+    while self.unsettled.get() > 0 && Instant::now() < deadline {
+      runtime::sleep(SHUTDOWN_POLL_INTERVAL.min(remaining(deadline))).await;
+    }
+    if self.unsettled.get() > 0 {
+      warn!("{} deliveries left unsettled on shutdown", self.unsettled.get());
+    }
+
+    for channel in &channels {
+      let method = ChannelClose {
+        reply_code: ReplyCode::Success.code(),
+        reply_text: "Connection shut down".into(),
+        class_id: 0,
+        method_id: 0,
+      };
+      self.call_until(channel.id, method.into_frame(), deadline).await;
+    }
+
+    let method = ConnectionClose {
+      reply_code: ReplyCode::Success.code(),
+      reply_text: "Connection shut down".into(),
+      class_id: 0,
+      method_id: 0,
+    };
+    self.message_tx.send((0, method.into_frame()))?;
+
+    // the close-ok moves the connection to closed
+    let mut state_rx = self.state_tx.subscribe();
+    let closed = runtime::timeout(remaining(deadline), async {
+      while !state_rx.borrow_and_update().is_terminal() {
+        if state_rx.changed().await.is_err() {
+          break;
+        }
+      }
+    }).await;
+    if closed.is_none() {
+      warn!("no close-ok within the grace period, dropping the connection");
+      transition(&self.state_tx, ConnectionState::Closed);
+      let _ = self.close_tx.send(());
+    }
+
+    for task in std::mem::take(&mut self.tasks) {
+      if runtime::timeout(remaining(deadline), task).await.is_none() {
+        warn!("background task still running after the grace period");
+      }
+    }
+
+    info!("connection shut down");
+    match self.failure() {
+      Some(err) => Err(err),
+      None => Ok(()),
+    }
+  }
+
+  // waits for the reply of a shutdown step, a step that fails or runs out of time doesn't stop the others
+  async fn call_until(&self, channel: ChannelId, frame: Frame, deadline: Instant) {
+    let call = async {
+      let responder_rx = invoke_sync_method!(channel, self.command_tx, self.message_tx, frame);
+      responder_rx.await?;
+      Ok::<(), Error>(())
+    };
+
+    match runtime::timeout(remaining(deadline), call).await {
+      Some(Ok(())) => {},
+      Some(Err(err)) => warn!("shutdown step on channel {} failed: {}", channel, err),
+      None => warn!("shutdown step on channel {} timed out", channel),
+    }
+  }
+
+  fn ensure_open(&self) -> Result<()> {
+    if let Some(err) = self.failure() {
+      return Err(err);
+    }
+
+    match &*self.state_tx.borrow() {
+      ConnectionState::Closing => Err(Error::closed("Connection is shutting down")),
+      ConnectionState::Closed => Err(Error::closed("Connection is closed")),
+      _ => Ok(()),
+    }
+  }
+
+  // the broker's close when it closed the connection with an error
+  fn failure(&self) -> Option<Error> {
+    let reason = self.state_tx.borrow().failure()?.to_string();
+    match self.exception.lock().unwrap().clone() {
+      Some(exception) => Some(exception.into()),
+      None => Some(ConnectionFailed { reason }.into()),
+    }
+  }
+
+  async fn handshake(&mut self, reader: &mut FrameReader, writer: &mut FrameWriter) -> Result<()> {
     info!("handshake started");
     writer.write_binary(&PROTOCOL_HEADER).await?;
 
-    let (_, frame) = reader.next_frame().await?;
+    let frame = next_handshake_frame(reader, writer).await?;
     let _start_method = unwrap_frame_variant!(frame, ConnectionStart);
 
-    let client_properties: PropTable = HashMap::from([
+    let mut client_properties: PropTable = HashMap::from([
       ("product".into(), Property::LongStr(PRODUCT.into())),
       ("platform".into(), Property::LongStr(PLATFORM.into())),
       ("copyright".into(), Property::LongStr(COPYRIGHT.into())),
-      ("information".into(), Property::LongStr(INFORMATION.into()))
+      ("information".into(), Property::LongStr(INFORMATION.into())),
+      ("capabilities".into(), Property::Table(HashMap::from([
+        ("consumer_cancel_notify".into(), Property::Bool(true)),
+      ])))
     ]);
-    let start_ok_method = ConnectionStartOk {
-      properties: client_properties,
-      mechanism: ShortStr(DEFAULT_AUTH_MECHANISM.to_string()),
-      response: LongStr(format!("\x00{}\x00{}", self.arguments.address.login.as_str(), self.arguments.address.password)),
-      locale: ShortStr(DEFAULT_LOCALE.to_string()),
-    };
+    if let Some(name) = &self.arguments.connection_name {
+      client_properties.insert("connection_name".into(), Property::LongStr(name.as_str().into()));
+    }
+    let start_ok_method = ConnectionStartOk::builder()
+      .client_properties(client_properties)
+      .mechanism(DEFAULT_AUTH_MECHANISM)
+      .response(format!("\x00{}\x00{}", self.arguments.address.login.as_str(), self.arguments.address.password))
+      .locale(DEFAULT_LOCALE)
+      .build()?;
 
     writer.dispatch(0, start_ok_method.into_frame()).await?;
-    let (_, frame) = reader.next_frame().await?;
-    let _tune_method = unwrap_frame_variant!(frame, ConnectionTune);
+    let frame = next_handshake_frame(reader, writer).await?;
+    let tune_method = unwrap_frame_variant!(frame, ConnectionTune);
 
     let tune_ok_method = ConnectionTuneOk {
-      chan_max: self.arguments.max_channels,
-      frame_max: self.arguments.max_frame_size,
-      heartbeat: self.arguments.heartbeat_interval
+      channel_max: negotiate(self.arguments.max_channels as i32, tune_method.channel_max as u16 as i32, i16::MAX as i32) as Short,
+      frame_max: negotiate(self.arguments.max_frame_size, tune_method.frame_max, i32::MAX),
+      heartbeat: negotiate_heartbeat(self.arguments.heartbeat_interval, tune_method.heartbeat),
     };
+    info!("negotiated channel max: {}, frame max: {}, heartbeat: {}", tune_ok_method.channel_max, tune_ok_method.frame_max, tune_ok_method.heartbeat);
+
+    self.id_allocator = Arc::new(Mutex::new(IdAllocator::new(tune_ok_method.channel_max)));
+    self.negotiated = Negotiated {
+      channel_max: tune_ok_method.channel_max,
+      frame_max: tune_ok_method.frame_max,
+      heartbeat: tune_ok_method.heartbeat,
+    };
+    reader.set_frame_max(tune_ok_method.frame_max);
+    writer.set_frame_max(tune_ok_method.frame_max);
 
     writer.dispatch(0, tune_ok_method.into_frame()).await?;
 
     let open_method = ConnectionOpen {
-      vhost: self.arguments.address.vhost.clone().into(),
-      reserved1: "".into(),
-      reserved2: 0
+      virtual_host: self.arguments.address.vhost.clone().into(),
+      capabilities: "".into(),
+      insist: false
     };
 
     writer.dispatch(0, open_method.into_frame()).await?;
 
-    let (_, frame) = reader.next_frame().await?;
+    let frame = next_handshake_frame(reader, writer).await?;
     let _open_ok_method = unwrap_frame_variant!(frame, ConnectionOpenOk);
 
     Ok(())
@@ -136,28 +371,46 @@ impl Connection {
     mut writer: FrameWriter,
     mut outgoing_rx: UnboundedReceiver<FrameEnvelope>,
     mut command_rx: UnboundedReceiver<Command>
-  ) {
-    let mut channel_manager = ChannelManager::new();
+  ) -> Vec<JoinHandle<()>> {
+    let content_budget = ContentBudget::new(self.arguments.max_content_buffer_size, self.arguments.max_channel_content_size);
+    let mut channel_manager = ChannelManager::new(self.message_tx.clone(), self.unsettled.clone(), content_budget);
 
     let (channel_tx, channel_rx) = mpsc::unbounded_channel();
     let default_channel = DefaultAmqChannel::open(
       self.message_tx.clone(),
       channel_rx,
-      self.close_tx.clone()
-    ).unwrap();
-    channel_manager.register_channel(default_channel.id, channel_tx);
+      self.close_tx.clone(),
+      self.state_tx.clone(),
+      self.exception.clone()
+    );
+    channel_manager.register_channel(default_channel.id, channel_tx, Default::default());
 
-    let mut pending_frames: HashMap<ChannelId, ContentFrame> = HashMap::new();
-    let heartbeat_interval = self.arguments.heartbeat_interval;
+    let heartbeat_interval = self.negotiated.heartbeat;
     let close_tx = self.close_tx.clone();
     let mut close_rx = self.close_tx.subscribe();
+    let state_tx = self.state_tx.clone();
+    let metrics = self.arguments.metrics.clone();
 
     let outgoing_tx = self.message_tx.clone();
 
-    tokio::spawn(async move {
+    // frames are read on a task of their own, the select below only ever drops a queue receive,
+    // never a read that is halfway through a frame
+    let (frames_tx, mut frames_rx) = mpsc::unbounded_channel();
+    let socket_reader = runtime::spawn(async move {
+      loop {
+        let next = reader.next_frame().await;
+        let failed = next.is_err();
+        if frames_tx.send(next).is_err() || failed {
+          break;
+        }
+      }
+      info!("exit socket reader");
+    });
+
+    let reader_loop = runtime::spawn(async move {
       let mut last_heartbeat = SystemTime::now();
       loop {
-        let timeout_delay = tokio::time::sleep(Duration::from_secs(heartbeat_interval as u64));
+        let timeout_delay = runtime::sleep(Duration::from_secs(heartbeat_interval as u64));
 
         tokio::select! {
           Some((payload, acker)) = command_rx.recv() => {
@@ -165,63 +418,136 @@ impl Connection {
               CommandPayload::RegisterResponder((channel, responder)) => {
                 channel_manager.register_responder(channel, responder);
               },
-              CommandPayload::RegisterChannel((id, incoming_tx)) => {
-                channel_manager.register_channel(id, incoming_tx);
+              CommandPayload::RegisterChannel((id, incoming_tx, shared)) => {
+                channel_manager.register_channel(id, incoming_tx, shared);
               },
               CommandPayload::RegisterConsumer(channel, consumer_tag, consumer_tx) => {
                 channel_manager.register_consumer(channel, consumer_tag, consumer_tx);
+              },
+              CommandPayload::ListChannels(channels_tx) => {
+                let _ = channels_tx.send(channel_manager.channels());
+              }
+              CommandPayload::CloseChannel(channel, reply_code, reason) => {
+                channel_manager.close_channel(channel, reply_code, reason);
               }
             }
-            acker.send(()).unwrap();
+            let _ = acker.send(());
           },
-          Ok((channel, frame)) = reader.next_frame() => {
+          Some(next) = frames_rx.recv() => {
+            let (channel, frame) = match next {
+              Ok(next) => next,
+              Err(err) => match frame_error(&err) {
+                Some((reply_code, reason)) => {
+                  // the rest of the stream can't be trusted anymore
+                  close_with_error(&outgoing_tx, &state_tx, &close_tx, reply_code, reason);
+                  break;
+                },
+                None => {
+                  fail(&state_tx, &close_tx, format!("reading frames failed: {}", err));
+                  break;
+                }
+              }
+            };
             last_heartbeat = SystemTime::now();
 
+            // the broker may still be sending for a channel the client closed
+            if channel_manager.is_closing(channel) && !matches!(frame, Frame::ChannelClose(..) | Frame::ChannelCloseOk(..)) {
+              continue;
+            }
+
             match &frame {
               Frame::Heartbeat => {
                 info!("Heartbeat received");
                 // todo!("Do something with heartbeat");
               }
-              Frame::ContentHeader(..) => {
-                let pending_frame = pending_frames.remove(&channel).unwrap();
-                let content_header = unwrap_frame_variant!(frame, ContentHeader);
-                pending_frames.insert(channel, pending_frame.with_content_header(content_header));
-              }
+              Frame::BasicDeliver(..) |
+              Frame::BasicReturn(..) |
+              Frame::BasicGetOk(..) |
+              Frame::ContentHeader(..) |
               Frame::ContentBody(..) => {
-                let mut pending_frame = pending_frames.remove(&channel).unwrap();
-                let content_body = unwrap_frame_variant!(frame, ContentBody);
-                pending_frame = pending_frame.with_body(content_body);
-
-                if pending_frame.is_complete() {
-                  channel_manager.dispatch_content_frame(channel, outgoing_tx.clone(), pending_frame);
-                } else {
-                  pending_frames.insert(channel, pending_frame);
+                if let Frame::BasicDeliver(..) = frame {
+                  metrics.delivery_received(channel);
+                }
+                if let Err(err) = channel_manager.dispatch_content_frame(channel, frame) {
+                  if matches!(err, Error::ContentLimitExceeded(..)) {
+                    channel_manager.close_channel(channel, ReplyCode::ContentTooLarge, err.to_string());
+                    continue;
+                  }
+                  close_with_error(&outgoing_tx, &state_tx, &close_tx, ReplyCode::UnexpectedFrame, err.to_string());
+                  break;
+                }
+              }
+              Frame::ChannelClose(close) if channel != 0 => {
+                // server initiated close: the channel handler replies CloseOk, the pending call gets the exception
+                if let Err(err) = channel_manager.dispatch_channel_frame((channel, Frame::ChannelClose(close.clone()))) {
+                  warn!("{}", err);
+                }
+                let responder = channel_manager.take_responder(channel);
+                channel_manager.unregister_channel(channel);
+
+                if let Some(responder) = responder {
+                  let _ = responder.send(frame);
+                }
+              }
+              Frame::ChannelCloseOk(..) if channel_manager.is_closing(channel) => {
+                // nobody waits on a close the connection sent, the channel handler marks the channel closed
+                if let Err(err) = channel_manager.dispatch_channel_frame((channel, frame)) {
+                  warn!("{}", err);
+                }
+                channel_manager.unregister_channel(channel);
+              }
+              Frame::ChannelCloseOk(..) => {
+                channel_manager.respond(channel, frame);
+                channel_manager.unregister_channel(channel);
+              }
+              Frame::BasicCancelOk(cancel_ok) => {
+                // no deliveries follow the cancel-ok, let the consumer drain what it already got
+                channel_manager.unregister_consumer(channel, cancel_ok.consumer_tag.as_str());
+                channel_manager.respond_after_content(channel, frame);
+              }
+              Frame::BasicAck(..) |
+              Frame::BasicNack(..) if channel != 0 => {
+                // publisher confirms
+                metrics.confirm_received(channel, matches!(frame, Frame::BasicAck(..)));
+                if let Err(err) = channel_manager.dispatch_channel_frame((channel, frame)) {
+                  warn!("{}", err);
+                }
+              }
+              Frame::BasicCancel(cancel) => {
+                // broker side cancel, dropping the consumer sender lets its stream end
+                channel_manager.unregister_consumer(channel, cancel.consumer_tag.as_str());
+                if let Err(err) = channel_manager.dispatch_channel_frame((channel, frame)) {
+                  warn!("{}", err);
                 }
               }
               Frame::ChannelOpenOk(..) |
+              Frame::ChannelFlowOk(..) |
               Frame::ExchangeDeclareOk(..) |
               Frame::QueueDeclareOk(..) |
               Frame::QueueBindOk(..) |
               Frame::QueueUnbindOk(..) |
+              Frame::BasicQosOk(..) |
+              Frame::ConfirmSelectOk(..) |
+              Frame::TxSelectOk(..) |
+              Frame::TxCommitOk(..) |
+              Frame::TxRollbackOk(..) |
               Frame::BasicConsumeOk(..) => {
-                channel_manager.get_responder(channel).send(frame).unwrap();
-              }
-              Frame::BasicDeliver(..) => {
-                pending_frames.insert(channel, ContentFrame::WithMethod(frame));
+                channel_manager.respond(channel, frame);
               }
               _ => {
-                if channel == 0 {
-                  channel_manager.dispatch_channel_frame((channel, frame)).unwrap();
-                } else {
-                  todo!("handle frame {:?}", frame);
+                if let Err(err) = channel_manager.dispatch_channel_frame((channel, frame)) {
+                  warn!("{}", err);
                 }
               }
             }
           },
-          _ = timeout_delay => {
-            if SystemTime::now().duration_since(last_heartbeat).unwrap().as_secs() >  heartbeat_interval as u64  * 2 {
-              println!("Missing heartbeat");
-              close_tx.send(()).unwrap();
+          // zero turns heartbeats off, the broker isn't expected to send any
+          _ = timeout_delay, if heartbeat_interval > 0 => {
+            let silence = SystemTime::now().duration_since(last_heartbeat).unwrap_or_default();
+            if silence.as_secs() > heartbeat_interval as u64 * 2 {
+              metrics.heartbeat_missed();
+              fail(&state_tx, &close_tx, format!("no frames from the broker for {}s, heartbeats missed", silence.as_secs()));
+              break;
             }
           },
           _ = close_rx.recv() => {
@@ -229,29 +555,169 @@ impl Connection {
           }
         }
       }
+      // dropping the channel manager ends channel handlers, consumers and pending sync calls
+      drop(channel_manager);
       info!("exit reader loop");
     });
 
     let mut close_rx = self.close_tx.subscribe();
-    tokio::spawn(async move {
+    let max_flush_delay = self.arguments.max_flush_delay;
+    let publish_window = self.publish_window.clone();
+    let state_tx = self.state_tx.clone();
+    let metrics = self.arguments.metrics.clone();
+    let close_tx = self.close_tx.clone();
+    let writer_loop = runtime::spawn(async move {
       loop {
-        let heartbeat_delay = tokio::time::sleep(Duration::from_secs(heartbeat_interval as u64));
+        let heartbeat_delay = runtime::sleep(Duration::from_secs(heartbeat_interval as u64));
 
         tokio::select! {
+
           Some((channel, frame)) = outgoing_rx.recv() => {
-            writer.dispatch(channel, frame).await.unwrap();
-          },
-          _ = heartbeat_delay => {
-            info!("heartbeat delivered");
-            writer.dispatch(0, Frame::Heartbeat).await.unwrap();
+            publish_window.release(&frame);
+            if let Err(err) = writer.write_frame(channel, frame) {
+              warn!("frame on channel {} not sent: {}", channel, err);
+            }
+            write_queued(&mut writer, &mut outgoing_rx, &publish_window, max_flush_delay).await;
+            if let Err(err) = writer.flush().await {
+              fail(&state_tx, &close_tx, format!("writing frames failed: {}", err));
+              break;
+            }
+            metrics.outgoing_queue_depth(publish_window.pending());
           },
           _ = close_rx.recv() => {
+            // frames queued before the close, e.g. close-ok, still go out
+            while !write_queued(&mut writer, &mut outgoing_rx, &publish_window, Duration::ZERO).await {
+              if writer.flush().await.is_err() {
+                break;
+              }
+            }
+            let _ = writer.flush().await;
             break;
           }
+          _ = heartbeat_delay, if heartbeat_interval > 0 => {
+            if let Err(err) = writer.dispatch(0, Frame::Heartbeat).await {
+              fail(&state_tx, &close_tx, format!("sending heartbeat failed: {}", err));
+              break;
+            }
+            info!("heartbeat delivered");
+          },
         };
       }
 
+      publish_window.close();
       info!("exit writer loop");
     });
+
+    vec![socket_reader, reader_loop, writer_loop]
   }
 }
+
+// drains frames queued meanwhile so a burst goes out with a single flush,
+// the frame and byte caps keep a busy queue from starving the flush. True once the queue ran dry
+async fn write_queued(
+  writer: &mut FrameWriter,
+  outgoing_rx: &mut UnboundedReceiver<FrameEnvelope>,
+  publish_window: &PublishWindow,
+  max_flush_delay: Duration
+) -> bool {
+  let deadline = Instant::now() + max_flush_delay;
+
+  for _ in 0..MAX_FRAMES_PER_FLUSH {
+    if writer.is_full() {
+      return false;
+    }
+
+    let next = match outgoing_rx.try_recv() {
+      Ok(next) => Some(next),
+      Err(_) if max_flush_delay.is_zero() => None,
+      Err(_) => runtime::timeout(deadline.saturating_duration_since(Instant::now()), outgoing_rx.recv()).await.flatten(),
+    };
+
+    match next {
+      Some((channel, frame)) => {
+        publish_window.release(&frame);
+        if let Err(err) = writer.write_frame(channel, frame) {
+          warn!("frame on channel {} not sent: {}", channel, err);
+        }
+      },
+      None => return true,
+    }
+  }
+
+  false
+}
+
+// moves the connection to failed and stops its tasks, a no-op once it left the open state
+fn fail(state_tx: &watch::Sender<ConnectionState>, close_tx: &broadcast::Sender<()>, reason: String) {
+  warn!("{}, closing connection", reason);
+  transition(state_tx, ConnectionState::Failed(reason));
+  let _ = close_tx.send(());
+}
+
+// tells the broker why before failing, the writer sends the close before it stops
+fn close_with_error(
+  outgoing_tx: &UnboundedSender<FrameEnvelope>,
+  state_tx: &watch::Sender<ConnectionState>,
+  close_tx: &broadcast::Sender<()>,
+  reply_code: ReplyCode,
+  reason: String
+) {
+  let close = ConnectionClose {
+    reply_code: reply_code.code(),
+    reply_text: reason.clone().into(),
+    class_id: 0,
+    method_id: 0,
+  };
+  let _ = outgoing_tx.send((0, close.into_frame()));
+  fail(state_tx, close_tx, reason);
+}
+
+// errors after which the broker has to be told the stream is broken
+fn frame_error(err: &Error) -> Option<(ReplyCode, String)> {
+  match err {
+    Error::Protocol { code, context } => Some((*code, context.clone())),
+    _ => None,
+  }
+}
+
+fn remaining(deadline: Instant) -> Duration {
+  deadline.saturating_duration_since(Instant::now())
+}
+
+// the broker refuses a connection with a close, e.g. 530 for an unknown vhost in reply to the open,
+// any other frame is left to the step that waits for it
+async fn next_handshake_frame(reader: &mut FrameReader, writer: &mut FrameWriter) -> Result<Frame> {
+  let (_, frame) = reader.next_frame().await?;
+  if let Frame::ConnectionClose(close) = frame {
+    let exception = ConnectionException::from(close);
+    warn!("{}", exception);
+    let _ = writer.dispatch(0, ConnectionCloseOk {}.into_frame()).await;
+    return Err(exception.into());
+  }
+
+  Ok(frame)
+}
+
+// zero means disabled for heartbeats, not unlimited: the client turns them off with it, and a
+// server without a preference keeps the client's interval
+fn negotiate_heartbeat(client: Short, server: Short) -> Short {
+  // sent as an unsigned short
+  let server = (server as u16).min(Short::MAX as u16) as Short;
+  match (client, server) {
+    (0, _) => 0,
+    (client, 0) => client,
+    (client, server) => client.min(server),
+  }
+}
+
+// zero means "no limit" on either side, otherwise the lower value wins
+fn negotiate(client: i32, server: i32, limit: i32) -> i32 {
+  let value = match (client, server) {
+    (0, 0) => limit,
+    (0, server) => server,
+    (client, 0) => client,
+    (client, server) => client.min(server),
+  };
+
+  value.min(limit)
+}
@@ -1,15 +1,23 @@
-use tokio::net::TcpStream;
+use tracing::debug;
 use crate::api::connection::options::ConnectionArgs;
 use super::{Connection};
-use crate::Result;
+use crate::{runtime, Result};
 
 pub struct ConnectionFactory;
 
 impl ConnectionFactory {
   pub async fn create(uri: &str) -> Result<Connection> {
-    let options = ConnectionArgs::new(uri);
-    println!("Options {:?}", &options);
-    let stream = TcpStream::connect((options.address.host.clone(), options.address.port)).await?;
+    Self::create_with_args(ConnectionArgs::new(uri)?).await
+  }
+
+  // configured by environment variables, see ConnectionArgs::from_env
+  pub async fn from_env() -> Result<Connection> {
+    Self::create_with_args(ConnectionArgs::from_env()?).await
+  }
+
+  pub async fn create_with_args(options: ConnectionArgs) -> Result<Connection> {
+    debug!("connecting to {}:{}", options.address.host, options.address.port);
+    let stream = runtime::connect(&options.address.host, options.address.port).await?;
     let connection = Connection::open(stream, options).await?;
     Ok(connection)
   }
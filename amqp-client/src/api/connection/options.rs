@@ -1,21 +1,98 @@
+use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
+use crate::{bail, Result};
+use crate::metrics::{ClientMetrics, NoopMetrics};
+use crate::protocol::net::WireLog;
+use crate::interceptor::FrameInterceptor;
+use crate::api::basic::PublishRate;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConnectionArgs {
   pub address: ConnectionAddress,
   pub max_channels: i16,
   pub max_frame_size: i32,
   pub heartbeat_interval: i16,
+  // encode buffers kept for reuse by the writer
+  pub buffer_pool_size: usize,
+  pub max_pooled_buffer_size: usize,
+  // how long the writer waits for more frames before flushing, zero flushes as soon as the queue is drained
+  pub max_flush_delay: Duration,
+  // publishes queued for the writer before publish waits and try_publish fails, zero for no limit
+  pub max_pending_publishes: usize,
+  pub metrics: Arc<dyn ClientMetrics>,
+  pub wire_log: WireLog,
+  // payload bytes shown per frame in the wire log, the rest is cut off
+  pub wire_log_payload_bytes: usize,
+  pub interceptors: Vec<Arc<dyn FrameInterceptor>>,
+  // content bytes a channel and the whole connection may hold while reassembling deliveries,
+  // a message above either limit gets its channel closed. Zero for no limit
+  pub max_channel_content_size: usize,
+  pub max_content_buffer_size: usize,
+  // applied to every channel of the connection, channels can change their own
+  pub publish_rate: Option<PublishRate>,
+  // how long synchronous methods like declares or consume wait for the broker's reply, zero waits forever.
+  // Calls can override it through the timeout of their options
+  pub method_timeout: Duration,
+  // shown by the management UI next to the client's address
+  pub connection_name: Option<String>,
 }
 
+const URL_VAR: &str = "AMQP_URL";
+const HEARTBEAT_VAR: &str = "AMQP_HEARTBEAT";
+const CONNECTION_NAME_VAR: &str = "AMQP_CONNECTION_NAME";
+
 impl ConnectionArgs {
-  pub fn new(uri: &str) -> Self {
-    Self {
-      address: ConnectionAddress::from(uri),
+  // fails for URLs that can't be connected to, e.g. without credentials or with another scheme
+  pub fn new(uri: &str) -> Result<Self> {
+    Ok(Self {
+      address: ConnectionAddress::parse(uri)?,
       max_channels: 20,
       max_frame_size: 128*1024,
-      heartbeat_interval: 60
+      heartbeat_interval: 60,
+      buffer_pool_size: 4,
+      max_pooled_buffer_size: 1024 * 1024,
+      max_flush_delay: Duration::ZERO,
+      max_pending_publishes: 1024,
+      metrics: Arc::new(NoopMetrics),
+      wire_log: WireLog::Off,
+      wire_log_payload_bytes: 64,
+      interceptors: vec![],
+      max_channel_content_size: 128 * 1024 * 1024,
+      max_content_buffer_size: 512 * 1024 * 1024,
+      publish_rate: None,
+      method_timeout: Duration::from_secs(30),
+      connection_name: None,
+    })
+  }
+
+  // the URL from AMQP_URL, AMQP_HEARTBEAT in seconds and AMQP_CONNECTION_NAME override the defaults.
+  // amqps URLs are refused, the client has no TLS support to verify them with
+  pub fn from_env() -> Result<Self> {
+    let Some(uri) = env_var(URL_VAR)? else {
+      bail!("{} isn't set", URL_VAR);
+    };
+    let mut args = Self::new(&uri)?;
+
+    if let Some(heartbeat) = env_var(HEARTBEAT_VAR)? {
+      args.heartbeat_interval = match heartbeat.trim().parse() {
+        Ok(heartbeat) => heartbeat,
+        Err(_) => bail!("{} must be a number of seconds up to {}, got {:?}", HEARTBEAT_VAR, i16::MAX, heartbeat),
+      };
     }
+    args.connection_name = env_var(CONNECTION_NAME_VAR)?;
+
+    Ok(args)
+  }
+}
+
+// unset and empty are the same, as an empty value is how most deployment tools unset a variable
+fn env_var(name: &str) -> Result<Option<String>> {
+  match std::env::var(name) {
+    Ok(value) if value.is_empty() => Ok(None),
+    Ok(value) => Ok(Some(value)),
+    Err(std::env::VarError::NotPresent) => Ok(None),
+    Err(std::env::VarError::NotUnicode(_)) => bail!("{} isn't valid unicode", name),
   }
 }
 
@@ -29,27 +106,31 @@ pub struct ConnectionAddress {
   pub vhost: String,
 }
 
-impl From<&str> for ConnectionAddress {
-  fn from(uri: &str) -> Self {
-    let url = Url::parse(uri).unwrap();
-    let host = if url.has_host() {
-      url.host().unwrap().to_string()
-    } else {
-      String::from("localhost")
+impl ConnectionAddress {
+  pub fn parse(uri: &str) -> Result<Self> {
+    let url = Url::parse(uri)?;
+    match url.scheme() {
+      "amqp" => {},
+      "amqps" => bail!("TLS isn't supported, connect with an amqp:// URL"),
+      scheme => bail!("Unsupported connection URL scheme {}, expected amqp", scheme),
+    }
+
+    let host = match url.host() {
+      Some(host) => host.to_string(),
+      None => String::from("localhost"),
     };
-    let port = url.port().unwrap_or_else(|| 5672);
-    let (login, password) = if url.has_authority() {
-      (url.username().to_string(), url.password().unwrap().to_string())
-    } else {
-      panic!("Provide username and password in the connection url");
+    let port = url.port().unwrap_or(5672);
+    let (login, password) = match url.password() {
+      Some(password) if !url.username().is_empty() => (url.username().to_string(), password.to_string()),
+      _ => bail!("Provide username and password in the connection url"),
     };
 
-    Self {
+    Ok(Self {
       host,
       port,
       login,
       password,
-      vhost: url.path()[1..].into(),
-    }
+      vhost: url.path().get(1..).unwrap_or_default().into(),
+    })
   }
 }
@@ -0,0 +1,29 @@
+use crate::api::connection::state::ConnectionState;
+use crate::protocol::types::ChannelId;
+
+// what a connection has in flight at the moment it was taken, e.g. for a diagnostics endpoint
+#[derive(Debug, Clone)]
+pub struct ConnectionSnapshot {
+  pub state: ConnectionState,
+  // open channels by id, the default channel 0 left out
+  pub channels: Vec<ChannelSnapshot>,
+  // deliveries handed out on all channels and not settled yet
+  pub unacked: usize,
+  // publishes queued for the socket and not written yet
+  pub outgoing_queue_depth: usize,
+}
+
+impl ConnectionSnapshot {
+  // publishes waiting for the broker's ack on all channels in confirm mode
+  pub fn unconfirmed(&self) -> usize {
+    self.channels.iter().map(|channel| channel.unconfirmed).sum()
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelSnapshot {
+  pub id: ChannelId,
+  pub consumer_tags: Vec<String>,
+  pub unacked: usize,
+  pub unconfirmed: usize,
+}
@@ -0,0 +1,41 @@
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+  Open,
+  // shutdown in progress, no new work is accepted
+  Closing,
+  // closed on purpose by either side
+  Closed,
+  // the socket broke, heartbeats were missed or the broker closed the connection with an error
+  Failed(String),
+}
+
+impl ConnectionState {
+  pub fn is_open(&self) -> bool {
+    *self == ConnectionState::Open
+  }
+
+  pub fn failure(&self) -> Option<&str> {
+    match self {
+      ConnectionState::Failed(reason) => Some(reason),
+      _ => None,
+    }
+  }
+
+  pub fn is_terminal(&self) -> bool {
+    matches!(self, ConnectionState::Closed | ConnectionState::Failed(..))
+  }
+}
+
+// only the first transition into closed or failed counts, a failure isn't overwritten by the close that follows it
+pub(crate) fn transition(state_tx: &watch::Sender<ConnectionState>, next: ConnectionState) -> bool {
+  state_tx.send_if_modified(|state| {
+    if state.is_terminal() {
+      return false;
+    }
+
+    *state = next;
+    true
+  })
+}
@@ -0,0 +1,322 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use futures_core::Stream;
+use tracing::{debug_span, info, warn, Instrument};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+use crate::api::basic::SlowConsumerOpts;
+use crate::api::channel::{await_reply, Subscriptions};
+use crate::building_blocks::{Command, CommandPayload, ConsumerBacklog, DeliveryReceiver};
+use crate::metrics::ClientMetrics;
+use crate::runtime;
+use crate::error::ChannelException;
+use crate::interceptor::MessageInterceptors;
+use crate::protocol::frame::{BasicCancel, Frame, FrameEnvelope};
+use crate::protocol::message::Delivery;
+use crate::protocol::types::ChannelId;
+use crate::protocol::reply_code::ReplyCode;
+use crate::{invoke_command_async, invoke_sync_method, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsumerState {
+  // subscribed without deliveries so far, e.g. waiting as a standby single active consumer
+  Standby,
+  Active,
+  // cancelled by the broker or the client, or the channel was closed
+  Cancelled,
+}
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// how far a consumer is behind the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsumerLag {
+  // received from the broker, not taken by the application yet
+  pub queued: usize,
+  // handed out and not settled yet, always zero for no_ack consumers
+  pub unsettled: usize,
+  // time since the oldest unsettled delivery was handed out
+  pub oldest_unsettled: Option<Duration>,
+}
+
+impl ConsumerLag {
+  fn of(backlog: &ConsumerBacklog) -> Self {
+    let (unsettled, oldest_unsettled) = backlog.unsettled();
+    Self { queued: backlog.queued(), unsettled, oldest_unsettled }
+  }
+
+  fn exceeds(&self, opts: &SlowConsumerOpts) -> bool {
+    let queued = opts.max_queued > 0 && self.queued >= opts.max_queued;
+    let time_to_ack = !opts.max_time_to_ack.is_zero()
+      && self.oldest_unsettled.is_some_and(|oldest| oldest >= opts.max_time_to_ack);
+    queued || time_to_ack
+  }
+}
+
+// deliveries of a single consumer, ends once the channel is closed or dropped
+pub struct Consumer {
+  tag: String,
+  channel: ChannelId,
+  no_ack: bool,
+  outgoing_tx: UnboundedSender<FrameEnvelope>,
+  command_tx: UnboundedSender<Command>,
+  // handed out deliveries are kept in the backlog until settled so shutdown can wait for them
+  deliveries: DeliveryReceiver,
+  exception: Arc<Mutex<Option<ChannelException>>>,
+  interceptors: MessageInterceptors,
+  subscriptions: Subscriptions,
+  method_timeout: Duration,
+  state: ConsumerState,
+  // stops the task waiting for the cancel signal once the consumer is dropped
+  cancel_guard: Option<oneshot::Sender<()>>,
+}
+
+impl Consumer {
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn new(
+    tag: String,
+    channel: ChannelId,
+    no_ack: bool,
+    outgoing_tx: UnboundedSender<FrameEnvelope>,
+    command_tx: UnboundedSender<Command>,
+    deliveries: DeliveryReceiver,
+    exception: Arc<Mutex<Option<ChannelException>>>,
+    interceptors: MessageInterceptors,
+    subscriptions: Subscriptions,
+    method_timeout: Duration,
+  ) -> Self {
+    Self {
+      tag,
+      channel,
+      no_ack,
+      outgoing_tx,
+      command_tx,
+      deliveries,
+      exception,
+      interceptors,
+      subscriptions,
+      method_timeout,
+      state: ConsumerState::Standby,
+      cancel_guard: None,
+    }
+  }
+
+  pub fn tag(&self) -> &str {
+    &self.tag
+  }
+
+  pub fn state(&self) -> ConsumerState {
+    self.state
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.state == ConsumerState::Active
+  }
+
+  pub fn lag(&self) -> ConsumerLag {
+    ConsumerLag::of(self.deliveries.backlog())
+  }
+
+  pub async fn recv(&mut self) -> Option<Delivery> {
+    loop {
+      match self.deliveries.recv().await {
+        Some(delivery) => {
+          let Some(delivery) = self.interceptors.on_delivery(delivery, self.no_ack) else {
+            continue;
+          };
+          self.track(&delivery);
+          return Some(delivery);
+        },
+        None => {
+          self.state = ConsumerState::Cancelled;
+          return None;
+        }
+      }
+    }
+  }
+
+  // cancels the subscription once the signal completes, e.g. token.cancelled_owned() of a
+  // CancellationToken. Deliveries received until then are still handed out, then the stream ends
+  pub fn cancel_on<S>(&mut self, signal: S)
+    where S: Future<Output = ()> + Send + 'static
+  {
+    let (guard_tx, guard_rx) = oneshot::channel();
+    self.cancel_guard = Some(guard_tx);
+    let subscription = self.subscription();
+
+    runtime::spawn(async move {
+      tokio::select! {
+        _ = signal => {
+          if let Err(err) = subscription.cancel().await {
+            warn!("failed to cancel consumer {} on signal: {}", subscription.tag, err);
+          }
+        },
+        _ = guard_rx => {},
+      }
+    });
+  }
+
+  // cancels the subscription, waits up to grace_period for handed out deliveries to be settled
+  // and requeues the ones still unsettled when requeue is set
+  pub async fn shutdown(mut self, grace_period: Duration, requeue: bool) -> Result<()> {
+    info!("shutting down consumer {}", self.tag);
+    self.cancel().await?;
+
+    // everything still buffered was never seen by the application. The cancel-ok is handed over
+    // after the deliveries that preceded it, none of them arrives after the drain
+    while let Ok(delivery) = self.deliveries.try_recv() {
+      if !self.no_ack {
+        delivery.reject(true)?;
+      }
+    }
+
+    let backlog = self.deliveries.backlog().clone();
+    let deadline = Instant::now() + grace_period;
+    loop {
+      let now = Instant::now();
+      if backlog.unsettled().0 == 0 || now >= deadline {
+        break;
+      }
+
+      runtime::sleep(DRAIN_POLL_INTERVAL.min(deadline - now)).await;
+    }
+
+    let unsettled = backlog.take_unsettled();
+    if !unsettled.is_empty() {
+      warn!("consumer {} left {} deliveries unsettled after {:?}", self.tag, unsettled.len(), grace_period);
+
+      if requeue {
+        for acker in unsettled {
+          // settled concurrently by the application in the meantime
+          let _ = acker.nack(false, true);
+        }
+      }
+    }
+
+    self.state = ConsumerState::Cancelled;
+    info!("consumer {} shut down", self.tag);
+    Ok(())
+  }
+
+  async fn cancel(&mut self) -> Result<()> {
+    self.cancel_guard = None;
+    self.subscription().cancel().await
+  }
+
+  fn subscription(&self) -> Subscription {
+    Subscription {
+      tag: self.tag.clone(),
+      channel: self.channel,
+      outgoing_tx: self.outgoing_tx.clone(),
+      command_tx: self.command_tx.clone(),
+      subscriptions: self.subscriptions.clone(),
+      timeout: self.method_timeout,
+    }
+  }
+
+  fn track(&mut self, delivery: &Delivery) {
+    // brokers deliver to a single active consumer only once it was promoted
+    self.state = ConsumerState::Active;
+    if self.no_ack {
+      return;
+    }
+
+    let acker = delivery.acker();
+    acker.track();
+    self.deliveries.backlog().handed_out(acker);
+  }
+}
+
+// the broker side of a consumer, cancellable from tasks that don't own the consumer
+pub(crate) struct Subscription {
+  pub tag: String,
+  pub channel: ChannelId,
+  pub outgoing_tx: UnboundedSender<FrameEnvelope>,
+  pub command_tx: UnboundedSender<Command>,
+  pub subscriptions: Subscriptions,
+  pub timeout: Duration,
+}
+
+impl Subscription {
+  // the consumer's deliveries end once the broker confirmed, it isn't resubscribed on reopen either
+  pub async fn cancel(&self) -> Result<()> {
+    let method = BasicCancel { consumer_tag: self.tag.clone().into(), no_wait: false };
+    let span = debug_span!("sync_method", channel = self.channel, class_id = BasicCancel::CLASS_ID, method_id = BasicCancel::METHOD_ID);
+    let responder_rx = invoke_sync_method!(self.channel, self.command_tx, self.outgoing_tx, method.into_frame());
+    let reply = await_reply(self.channel, (BasicCancel::CLASS_ID, BasicCancel::METHOD_ID), responder_rx, self.timeout, &self.command_tx);
+
+    match reply.instrument(span).await? {
+      Frame::BasicCancelOk(..) => {
+        self.subscriptions.lock().unwrap().retain(|(opts, _)| opts.tag != self.tag);
+        Ok(())
+      },
+      Frame::ChannelClose(close) => Err(ChannelException::from(close).into()),
+      frame => Err(Error::protocol(ReplyCode::UnexpectedFrame, format!("Unexpected reply to cancel of consumer {}: {:?}", self.tag, frame))),
+    }
+  }
+}
+
+// checks the backlog of a consumer against the thresholds until the consumer is gone,
+// warning once it falls behind and once it caught up again. Holds the backlog only, so
+// the consumer's stream still ends once the broker cancelled it
+pub(crate) fn watch_lag(channel: ChannelId, tag: String, opts: SlowConsumerOpts, backlog: Arc<ConsumerBacklog>, metrics: Arc<dyn ClientMetrics>) {
+  runtime::spawn(async move {
+    let mut lagging = false;
+    loop {
+      runtime::sleep(opts.check_interval).await;
+      // neither the consumer nor a sender of its deliveries is left
+      if Arc::strong_count(&backlog) == 1 {
+        break;
+      }
+
+      let lag = ConsumerLag::of(&backlog);
+      match (lag.exceeds(&opts), lagging) {
+        (true, false) => {
+          warn!(
+            "consumer {} on channel {} is falling behind: {} queued, {} unsettled, oldest unsettled for {:?}",
+            tag, channel, lag.queued, lag.unsettled, lag.oldest_unsettled.unwrap_or_default()
+          );
+          metrics.consumer_lagging(channel, &tag, &lag);
+          lagging = true;
+        },
+        (false, true) => {
+          info!("consumer {} on channel {} caught up", tag, channel);
+          lagging = false;
+        },
+        _ => {}
+      }
+    }
+  });
+}
+
+impl Stream for Consumer {
+  type Item = Result<Delivery>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    if self.state == ConsumerState::Cancelled {
+      return Poll::Ready(None);
+    }
+
+    loop {
+      match self.deliveries.poll_recv(cx) {
+        Poll::Ready(Some(delivery)) => {
+          let Some(delivery) = self.interceptors.on_delivery(delivery, self.no_ack) else {
+            continue;
+          };
+          self.track(&delivery);
+          return Poll::Ready(Some(Ok(delivery)));
+        },
+        Poll::Ready(None) => {
+          self.state = ConsumerState::Cancelled;
+          // report why the broker closed the channel before ending the stream
+          let exception = self.exception.lock().unwrap().clone();
+          return Poll::Ready(exception.map(|exception| Err(exception.into())));
+        },
+        Poll::Pending => return Poll::Pending
+      }
+    }
+  }
+}
@@ -1,11 +1,15 @@
-use log::info;
-use tokio::sync::broadcast;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+use tokio::sync::{broadcast, watch};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 use crate::protocol::types::{ChannelId};
-use crate::{Result};
 use crate::protocol::frame::{FrameEnvelope, Frame};
 use crate::protocol::frame::ConnectionCloseOk;
+use crate::protocol::reply_code::ReplyCode;
+use crate::error::ConnectionException;
+use crate::runtime;
+use crate::api::connection::state::{transition, ConnectionState};
 
 pub struct DefaultAmqChannel {
   pub id: ChannelId,
@@ -17,31 +21,52 @@ impl DefaultAmqChannel {
     outgoing_tx: UnboundedSender<FrameEnvelope>,
     incoming_rx: UnboundedReceiver<FrameEnvelope>,
     close_tx: broadcast::Sender<()>,
-  ) -> Result<Self> {
+    state_tx: Arc<watch::Sender<ConnectionState>>,
+    exception: Arc<Mutex<Option<ConnectionException>>>,
+  ) -> Self {
     let channel = Self { id: 0, outgoing_tx };
-    channel.spawn_incoming_msg_handler(incoming_rx, close_tx);
+    channel.spawn_incoming_msg_handler(incoming_rx, close_tx, state_tx, exception);
 
-    Ok(channel)
+    channel
   }
 
-  fn spawn_incoming_msg_handler(&self, mut incoming_rx: UnboundedReceiver<FrameEnvelope>, close_tx: broadcast::Sender<()>) {
+  fn spawn_incoming_msg_handler(
+    &self,
+    mut incoming_rx: UnboundedReceiver<FrameEnvelope>,
+    close_tx: broadcast::Sender<()>,
+    state_tx: Arc<watch::Sender<ConnectionState>>,
+    exception: Arc<Mutex<Option<ConnectionException>>>
+  ) {
     let outgoing_tx = self.outgoing_tx.clone();
-    tokio::spawn(async move {
+    runtime::spawn(async move {
       while let Some((_, frame)) = incoming_rx.recv().await {
         match frame {
           Frame::ConnectionClose(connection_close) => {
-            info!("Connection closed with code: {}, reason: {}", connection_close.reply_code, connection_close.reply_text.0);
-            outgoing_tx.send((0, ConnectionCloseOk {}.into_frame())).unwrap();
-            close_tx.send(()).unwrap();
+            let reply_code = ReplyCode::from(connection_close.reply_code);
+            let next_state = match reply_code.is_hard_error() {
+              true => {
+                warn!("Connection closed by broker with {}: {}", reply_code, connection_close.reply_text);
+                *exception.lock().unwrap() = Some(ConnectionException::from(connection_close.clone()));
+                ConnectionState::Failed(format!("closed by broker with {}: {}", reply_code, connection_close.reply_text))
+              },
+              false => {
+                info!("Connection closed with code: {}, reason: {}", reply_code, connection_close.reply_text);
+                ConnectionState::Closed
+              },
+            };
+            transition(&state_tx, next_state);
+            let _ = outgoing_tx.send((0, ConnectionCloseOk {}.into_frame()));
+            let _ = close_tx.send(());
             break;
           },
           Frame::ConnectionCloseOk(_) => {
             info!("connection close-ok received");
-            close_tx.send(()).unwrap();
+            transition(&state_tx, ConnectionState::Closed);
+            let _ = close_tx.send(());
             break;
           }
           _ => {
-            todo!("Implement handler")
+            warn!("unhandled frame on channel 0: {:?}", frame);
           }
         }
       }
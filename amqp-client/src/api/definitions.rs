@@ -0,0 +1,215 @@
+use std::time::UNIX_EPOCH;
+use serde_json::{json, Map, Value};
+use tracing::warn;
+use crate::api::exchange::{ExchangeDeclareOpts, ExchangeType};
+use crate::api::queue::{QueueBindOpts, QueueDeclareOpts, QueueType, QUEUE_TYPE_ARG};
+use crate::api::topology::Topology;
+use crate::protocol::types::{Int, PropTable, Property, ShortStr};
+use crate::{bail, Result};
+
+// the queues, exchanges and bindings of the definitions the management plugin exports
+// and imports, other sections like users or policies are left out
+impl Topology {
+  // entities of other vhosts are skipped, as are the ones AMQP can't declare: the default
+  // and amq.* exchanges and bindings to exchanges
+  pub fn from_definitions(definitions: &str, vhost: &str) -> Result<Self> {
+    let definitions: Value = serde_json::from_str(definitions)?;
+    let mut topology = Topology::new();
+
+    for exchange in entries(&definitions, "exchanges", vhost)? {
+      let name = string(exchange, "name")?;
+      if name.is_empty() || name.starts_with("amq.") {
+        continue;
+      }
+
+      topology.exchanges.push(ExchangeDeclareOpts {
+        name: name.into(),
+        ty: ExchangeType::from(string(exchange, "type")?),
+        durable: flag(exchange, "durable", true),
+        auto_delete: flag(exchange, "auto_delete", false),
+        internal: flag(exchange, "internal", false),
+        arguments: arguments(exchange)?,
+        ..Default::default()
+      });
+    }
+
+    for queue in entries(&definitions, "queues", vhost)? {
+      let mut arguments = arguments(queue)?;
+      let queue_type = match arguments.remove(&ShortStr::from(QUEUE_TYPE_ARG)) {
+        None => None,
+        Some(Property::LongStr(queue_type)) => match queue_type.as_str() {
+          Some("classic") => Some(QueueType::Classic),
+          Some("quorum") => Some(QueueType::Quorum),
+          Some("stream") => Some(QueueType::Stream),
+          _ => bail!("Unknown type of queue {}: {:?}", string(queue, "name")?, queue_type),
+        },
+        Some(queue_type) => bail!("Unknown type of queue {}: {:?}", string(queue, "name")?, queue_type),
+      };
+
+      topology.queues.push(QueueDeclareOpts {
+        name: string(queue, "name")?.into(),
+        durable: flag(queue, "durable", true),
+        auto_delete: flag(queue, "auto_delete", false),
+        queue_type,
+        arguments,
+        ..Default::default()
+      });
+    }
+
+    for binding in entries(&definitions, "bindings", vhost)? {
+      let source = string(binding, "source")?;
+      let destination = string(binding, "destination")?;
+      // every queue is bound to the default exchange implicitly
+      if source.is_empty() {
+        continue;
+      }
+      if binding.get("destination_type").and_then(Value::as_str).unwrap_or("queue") != "queue" {
+        warn!("skipped binding of exchange {} to exchange {}, only queue bindings are declared", destination, source);
+        continue;
+      }
+
+      topology.bindings.push(QueueBindOpts {
+        queue: destination.into(),
+        exchange: source.into(),
+        routing_key: binding.get("routing_key").and_then(Value::as_str).unwrap_or_default().into(),
+        arguments: arguments(binding)?,
+        ..Default::default()
+      });
+    }
+
+    Ok(topology)
+  }
+
+  // server named and exclusive queues belong to a connection rather than the broker, they are left out
+  pub fn to_definitions(&self, vhost: &str) -> Result<String> {
+    let exchanges: Vec<Value> = self.exchanges.iter()
+      .map(|exchange| json!({
+        "name": exchange.name,
+        "vhost": vhost,
+        "type": exchange.ty.as_str(),
+        "durable": exchange.durable,
+        "auto_delete": exchange.auto_delete,
+        "internal": exchange.internal,
+        "arguments": table_to_json(&exchange.arguments),
+      }))
+      .collect();
+
+    let queues: Vec<Value> = self.queues.iter()
+      .filter(|queue| !queue.name.is_empty() && !queue.exclusive)
+      .map(|queue| {
+        let mut arguments = table_to_json(&queue.arguments);
+        if let (Some(queue_type), Value::Object(arguments)) = (queue.queue_type, &mut arguments) {
+          arguments.insert(QUEUE_TYPE_ARG.into(), queue_type.as_str().into());
+        }
+
+        json!({
+          "name": queue.name,
+          "vhost": vhost,
+          "durable": queue.durable,
+          "auto_delete": queue.auto_delete,
+          "arguments": arguments,
+        })
+      })
+      .collect();
+
+    let bindings: Vec<Value> = self.bindings.iter()
+      .map(|binding| json!({
+        "source": binding.exchange,
+        "vhost": vhost,
+        "destination": binding.queue,
+        "destination_type": "queue",
+        "routing_key": binding.routing_key,
+        "arguments": table_to_json(&binding.arguments),
+      }))
+      .collect();
+
+    Ok(serde_json::to_string_pretty(&json!({
+      "exchanges": exchanges,
+      "queues": queues,
+      "bindings": bindings,
+    }))?)
+  }
+}
+
+// entries without a vhost, as written by some tools, belong to every vhost
+fn entries<'a>(definitions: &'a Value, section: &str, vhost: &'a str) -> Result<impl Iterator<Item = &'a Value>> {
+  let entries = match definitions.get(section) {
+    None => &[][..],
+    Some(Value::Array(entries)) => entries.as_slice(),
+    Some(_) => bail!("Definitions section {} must be an array", section),
+  };
+
+  Ok(entries.iter().filter(move |entry| entry.get("vhost").and_then(Value::as_str).is_none_or(|entry_vhost| entry_vhost == vhost)))
+}
+
+fn string<'a>(entry: &'a Value, key: &str) -> Result<&'a str> {
+  match entry.get(key).and_then(Value::as_str) {
+    Some(value) => Ok(value),
+    None => bail!("Definition {} has no {}", entry, key),
+  }
+}
+
+fn flag(entry: &Value, key: &str, default: bool) -> bool {
+  entry.get(key).and_then(Value::as_bool).unwrap_or(default)
+}
+
+fn arguments(entry: &Value) -> Result<PropTable> {
+  match entry.get("arguments") {
+    None | Some(Value::Null) => Ok(PropTable::new()),
+    Some(Value::Object(arguments)) => Ok(table_from_json(arguments)),
+    Some(_) => bail!("Arguments of definition {} must be an object", entry),
+  }
+}
+
+fn table_from_json(table: &Map<String, Value>) -> PropTable {
+  table.iter().map(|(key, value)| (key.as_str().into(), property_from_json(value))).collect()
+}
+
+// numbers become the smallest of int and long holding them, which the broker accepts for every numeric argument
+fn property_from_json(value: &Value) -> Property {
+  match value {
+    Value::Null => Property::Void,
+    Value::Bool(value) => Property::Bool(*value),
+    Value::Number(number) => match number.as_i64() {
+      Some(number) => Int::try_from(number).map(Property::Int).unwrap_or(Property::Long(number)),
+      None => number.as_u64().map(Property::ULong).unwrap_or_else(|| Property::Double(number.as_f64().unwrap_or_default())),
+    },
+    Value::String(value) => Property::LongStr(value.as_str().into()),
+    Value::Array(values) => Property::Array(values.iter().map(property_from_json).collect()),
+    Value::Object(table) => Property::Table(table_from_json(table)),
+  }
+}
+
+fn table_to_json(table: &PropTable) -> Value {
+  Value::Object(table.iter().map(|(key, value)| (key.to_string(), property_to_json(value))).collect())
+}
+
+fn property_to_json(value: &Property) -> Value {
+  match value {
+    Property::Bool(value) => (*value).into(),
+    Property::SignedByte(value) => (*value).into(),
+    Property::Byte(value) => (*value).into(),
+    Property::Short(value) => (*value).into(),
+    Property::UShort(value) => (*value).into(),
+    Property::Int(value) => (*value).into(),
+    Property::UInt(value) => (*value).into(),
+    Property::Long(value) => (*value).into(),
+    Property::ULong(value) => (*value).into(),
+    Property::Float(value) => (*value).into(),
+    Property::Double(value) => (*value).into(),
+    Property::Decimal(decimal) => (decimal.value as f64 / 10_f64.powi(decimal.scale as i32)).into(),
+    Property::Timestamp(time) => time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default().into(),
+    Property::ShortStr(value) => value.as_str().into(),
+    Property::LongStr(value) => match value.as_str() {
+      Some(value) => value.into(),
+      None => value.0.to_vec().into(),
+    },
+    Property::Table(table) => table_to_json(table),
+    Property::Array(values) => Value::Array(values.iter().map(property_to_json).collect()),
+    Property::Bytes(bytes) => match std::str::from_utf8(bytes) {
+      Ok(value) => value.into(),
+      Err(_) => bytes.to_vec().into(),
+    },
+    Property::Void => Value::Null,
+  }
+}
@@ -1,13 +1,47 @@
-use std::collections::HashMap;
-use crate::protocol::types::{PropTable, ShortStr};
+use std::time::Duration;
+use crate::protocol::types::{PropTable, Property};
 use crate::protocol::frame::{ExchangeDeclare};
 
+pub const DELAYED_MESSAGE_EXCHANGE_TYPE: &str = "x-delayed-message";
+pub const DELAYED_TYPE_ARG: &str = "x-delayed-type";
+pub const DELAY_HEADER: &str = "x-delay";
+pub const ALTERNATE_EXCHANGE_ARG: &str = "alternate-exchange";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExchangeType {
   Direct,
   Fanout,
-  // todo: exhaustive list
+  Topic,
+  Headers,
+  // plugin provided types, e.g. x-delayed-message or x-consistent-hash
+  Custom(String),
+}
+
+impl ExchangeType {
+  pub fn as_str(&self) -> &str {
+    match self {
+      ExchangeType::Direct => "direct",
+      ExchangeType::Fanout => "fanout",
+      ExchangeType::Topic => "topic",
+      ExchangeType::Headers => "headers",
+      ExchangeType::Custom(ty) => ty.as_str(),
+    }
+  }
+}
+
+impl From<&str> for ExchangeType {
+  fn from(ty: &str) -> Self {
+    match ty {
+      "direct" => ExchangeType::Direct,
+      "fanout" => ExchangeType::Fanout,
+      "topic" => ExchangeType::Topic,
+      "headers" => ExchangeType::Headers,
+      _ => ExchangeType::Custom(ty.into()),
+    }
+  }
 }
 
+#[derive(Debug, Clone)]
 pub struct ExchangeDeclareOpts {
   pub name: String,
   pub ty: ExchangeType,
@@ -16,7 +50,9 @@ pub struct ExchangeDeclareOpts {
   pub auto_delete: bool,
   pub internal: bool,
   pub no_wait: bool,
-  pub props: PropTable
+  pub arguments: PropTable,
+  // overrides the connection's method timeout for this declare
+  pub timeout: Option<Duration>,
 }
 
 impl Default for ExchangeDeclareOpts {
@@ -30,15 +66,23 @@ impl Default for ExchangeDeclareOpts {
       auto_delete: false,
       internal: false,
       no_wait: false,
-      props: HashMap::new()
+      arguments: PropTable::new(),
+      timeout: None,
     }
   }
 }
 
+#[derive(Default)]
 pub struct ExchangeDeclareOptsBuilder {
   opts: ExchangeDeclareOpts
 }
 
+impl From<ExchangeDeclareOpts> for ExchangeDeclareOptsBuilder {
+  fn from(opts: ExchangeDeclareOpts) -> Self {
+    Self { opts }
+  }
+}
+
 impl ExchangeDeclareOptsBuilder {
   pub fn new() -> Self {
     Self {
@@ -78,51 +122,42 @@ impl ExchangeDeclareOptsBuilder {
     self.opts.no_wait = no_wait;
   }
 
-  pub fn props(&mut self, props: PropTable) {
-    self.opts.props = props;
+  pub fn timeout(&mut self, timeout: Duration) {
+    self.opts.timeout = Some(timeout);
   }
-}
-const PASSIVE_MASK: u8 = 0b01;
-const DURABLE_MASK: u8 = 0b10;
-const AUTODELETE_MASK: u8 = 0b100;
-const INTERNAL_MASK: u8 = 0b1000;
-const NOWAIT_MASK: u8 = 0b10000;
-
-impl From<ExchangeDeclareOpts> for ExchangeDeclare {
-  fn from(options: ExchangeDeclareOpts) -> Self {
-    let ty = match options.ty {
-      ExchangeType::Direct => "direct",
-      ExchangeType::Fanout => "fanout"
-    };
-
-    let mut flags = 0;
-
-    if options.passive {
-      flags = flags & PASSIVE_MASK;
-    }
 
-    if options.durable {
-      flags = flags & DURABLE_MASK;
-    }
+  // exchange of the delayed message plugin, routing like delayed_type once the x-delay header expires
+  pub fn delayed(&mut self, delayed_type: ExchangeType) {
+    self.opts.ty = ExchangeType::Custom(DELAYED_MESSAGE_EXCHANGE_TYPE.into());
+    self.argument(DELAYED_TYPE_ARG, Property::LongStr(delayed_type.as_str().into()));
+  }
 
-    if options.auto_delete {
-      flags = flags & AUTODELETE_MASK;
-    }
+  // messages the exchange can't route are republished to the alternate exchange
+  pub fn alternate_exchange(&mut self, exchange: &str) {
+    self.argument(ALTERNATE_EXCHANGE_ARG, Property::LongStr(exchange.into()));
+  }
 
-    if options.internal {
-      flags = flags & INTERNAL_MASK;
-    }
+  pub fn arguments(&mut self, arguments: PropTable) {
+    self.opts.arguments = arguments;
+  }
 
-    if options.no_wait {
-      flags = flags & NOWAIT_MASK;
-    }
+  pub fn argument(&mut self, key: &str, value: Property) {
+    self.opts.arguments.insert(key.into(), value);
+  }
+}
 
+impl From<ExchangeDeclareOpts> for ExchangeDeclare {
+  fn from(options: ExchangeDeclareOpts) -> Self {
     Self {
-      reserved1: 0,
-      name: ShortStr(options.name),
-      ty: ShortStr(ty.into()),
-      flags,
-      props: options.props
+      ticket: 0,
+      exchange: options.name.into(),
+      ty: options.ty.as_str().into(),
+      passive: options.passive,
+      durable: options.durable,
+      auto_delete: options.auto_delete,
+      internal: options.internal,
+      no_wait: options.no_wait,
+      arguments: options.arguments
     }
   }
 }
@@ -0,0 +1,149 @@
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::sync::mpsc::UnboundedSender;
+use crate::api::basic::PublishRate;
+use crate::api::channel::AmqChannel;
+use crate::building_blocks::{Command, PublishWindow};
+use crate::metrics::ClientMetrics;
+use crate::protocol::frame::FrameEnvelope;
+use crate::protocol::types::Int;
+use crate::utils::IdAllocator;
+use crate::{runtime, Result};
+
+struct PoolInner {
+  outgoing_tx: UnboundedSender<FrameEnvelope>,
+  command_tx: UnboundedSender<Command>,
+  id_allocator: Arc<Mutex<IdAllocator>>,
+  frame_max: Int,
+  publish_window: PublishWindow,
+  metrics: Arc<dyn ClientMetrics>,
+  publish_rate: Option<PublishRate>,
+  method_timeout: Duration,
+  idle: Mutex<Vec<AmqChannel>>,
+  permits: Arc<Semaphore>,
+}
+
+// lends channels of a single connection to tasks, at most max_size at a time
+#[derive(Clone)]
+pub struct ChannelPool {
+  inner: Arc<PoolInner>,
+}
+
+impl ChannelPool {
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn new(
+    outgoing_tx: UnboundedSender<FrameEnvelope>,
+    command_tx: UnboundedSender<Command>,
+    id_allocator: Arc<Mutex<IdAllocator>>,
+    frame_max: Int,
+    publish_window: PublishWindow,
+    metrics: Arc<dyn ClientMetrics>,
+    publish_rate: Option<PublishRate>,
+    method_timeout: Duration,
+    max_size: usize,
+  ) -> Self {
+    Self {
+      inner: Arc::new(PoolInner {
+        outgoing_tx,
+        command_tx,
+        id_allocator,
+        frame_max,
+        publish_window,
+        metrics,
+        publish_rate,
+        method_timeout,
+        idle: Mutex::new(vec![]),
+        permits: Arc::new(Semaphore::new(max_size)),
+      })
+    }
+  }
+
+  // waits until a channel is free, channels poisoned by exceptions are replaced with new ones
+  pub async fn get(&self) -> Result<PooledChannel> {
+    let permit = self.inner.permits.clone().acquire_owned().await?;
+
+    let idle = self.take_idle();
+    let channel = match idle {
+      Some(channel) => channel,
+      None => {
+        let channel = AmqChannel::create(
+          self.inner.outgoing_tx.clone(),
+          self.inner.command_tx.clone(),
+          self.inner.id_allocator.clone(),
+          self.inner.frame_max,
+          self.inner.publish_window.clone(),
+          self.inner.metrics.clone(),
+          self.inner.method_timeout,
+        ).await?;
+        channel.set_publish_rate(self.inner.publish_rate);
+        channel
+      }
+    };
+
+    Ok(PooledChannel {
+      channel: Some(channel),
+      pool: self.inner.clone(),
+      _permit: permit,
+    })
+  }
+
+  pub fn idle_count(&self) -> usize {
+    self.inner.idle.lock().unwrap().len()
+  }
+
+  fn take_idle(&self) -> Option<AmqChannel> {
+    let mut idle = self.inner.idle.lock().unwrap();
+    while let Some(channel) = idle.pop() {
+      if !channel.is_closed() {
+        return Some(channel);
+      }
+
+      info!("dropping closed channel {} from pool", channel.id);
+    }
+
+    None
+  }
+}
+
+pub struct PooledChannel {
+  channel: Option<AmqChannel>,
+  pool: Arc<PoolInner>,
+  _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for PooledChannel {
+  type Target = AmqChannel;
+
+  fn deref(&self) -> &Self::Target {
+    self.channel.as_ref().unwrap()
+  }
+}
+
+// the next borrower gets the channel as the pool created it, channels with state that can't be
+// undone are closed instead
+impl Drop for PooledChannel {
+  fn drop(&mut self) {
+    let Some(mut channel) = self.channel.take() else {
+      return;
+    };
+    if channel.is_closed() {
+      return;
+    }
+
+    if !channel.is_reusable() {
+      info!("closing channel {} returned to pool with consumers or in confirm or tx mode", channel.id);
+      runtime::spawn(async move {
+        if let Err(err) = channel.close().await {
+          warn!("failed to close pooled channel {}: {}", channel.id, err);
+        }
+      });
+      return;
+    }
+
+    channel.reset_hooks(self.pool.publish_rate);
+    self.pool.idle.lock().unwrap().push(channel);
+  }
+}
@@ -0,0 +1,192 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::oneshot;
+use crate::api::basic::BasicPublishOpts;
+use crate::api::exchange::DELAY_HEADER;
+use crate::api::channel::AmqChannel;
+use crate::api::codec::PayloadEncoder;
+use crate::protocol::message::{BasicProperties, MessageDeliveryMode};
+use crate::protocol::types::{PropTable, Property};
+use crate::utils::duration_millis;
+use crate::error::{ChannelException, PublishNacked};
+use crate::protocol::types::ChannelId;
+use crate::{Error, Result};
+
+// the broker's answer to publishes on a channel in confirm mode, resolves once all of them are settled
+#[derive(Debug)]
+pub struct Confirmation {
+  channel: ChannelId,
+  acks: Vec<oneshot::Receiver<bool>>,
+  // why the broker closed the channel, e.g. for a publish to a missing exchange
+  exception: Arc<Mutex<Option<ChannelException>>>,
+}
+
+impl Confirmation {
+  pub(crate) fn new(channel: ChannelId, acks: Vec<oneshot::Receiver<bool>>, exception: Arc<Mutex<Option<ChannelException>>>) -> Self {
+    Self { channel, acks, exception }
+  }
+
+  // fails with PublishNacked once any of the publishes is nacked
+  pub async fn wait(self) -> Result<()> {
+    for ack_rx in self.acks {
+      match ack_rx.await {
+        Ok(true) => {},
+        Ok(false) => return Err(PublishNacked { channel: self.channel }.into()),
+        Err(_) => match self.exception.lock().unwrap().clone() {
+          Some(exception) => return Err(exception.into()),
+          None => return Err(Error::closed(format!("Channel {} closed before the broker confirmed the publish", self.channel))),
+        },
+      }
+    }
+
+    Ok(())
+  }
+}
+
+pub struct PublishBuilder<'a> {
+  channel: &'a AmqChannel,
+  opts: BasicPublishOpts,
+  properties: BasicProperties,
+  body: Vec<u8>,
+}
+
+impl<'a> PublishBuilder<'a> {
+  pub(crate) fn new(channel: &'a AmqChannel, exchange: &str, routing_key: &str) -> Self {
+    Self {
+      channel,
+      opts: BasicPublishOpts {
+        exchange: exchange.into(),
+        routing_key: routing_key.into(),
+        ..Default::default()
+      },
+      properties: BasicProperties::new(),
+      body: vec![],
+    }
+  }
+
+  pub fn mandatory(mut self) -> Self {
+    self.opts.mandatory = true;
+    self
+  }
+
+  pub fn immediate(mut self) -> Self {
+    self.opts.immediate = true;
+    self
+  }
+
+  pub fn properties(mut self, properties: BasicProperties) -> Self {
+    self.properties = properties;
+    self
+  }
+
+  pub fn persistent(mut self) -> Self {
+    self.properties.delivery_mode = Some(MessageDeliveryMode::Persistent);
+    self
+  }
+
+  pub fn transient(mut self) -> Self {
+    self.properties.delivery_mode = Some(MessageDeliveryMode::NonPersistent);
+    self
+  }
+
+  pub fn content_type(mut self, content_type: &str) -> Self {
+    self.properties.content_type = Some(content_type.into());
+    self
+  }
+
+  pub fn content_encoding(mut self, content_encoding: &str) -> Self {
+    self.properties.content_encoding = Some(content_encoding.into());
+    self
+  }
+
+  pub fn headers(mut self, headers: PropTable) -> Self {
+    self.properties.headers = Some(headers);
+    self
+  }
+
+  pub fn header(mut self, key: &str, value: Property) -> Self {
+    self.properties.headers
+      .get_or_insert_with(PropTable::new)
+      .insert(key.into(), value);
+    self
+  }
+
+  // honoured by exchanges of the delayed message plugin only
+  pub fn delay(self, delay: Duration) -> Self {
+    self.header(DELAY_HEADER, Property::Int(duration_millis(delay)))
+  }
+
+  pub fn priority(mut self, priority: u8) -> Self {
+    self.properties.priority = Some(priority);
+    self
+  }
+
+  pub fn correlation_id(mut self, correlation_id: &str) -> Self {
+    self.properties.correlation_id = Some(correlation_id.into());
+    self
+  }
+
+  pub fn reply_to(mut self, reply_to: &str) -> Self {
+    self.properties.reply_to = Some(reply_to.into());
+    self
+  }
+
+  pub fn expiration(mut self, expiration: &str) -> Self {
+    self.properties.expiration = Some(expiration.into());
+    self
+  }
+
+  // per message ttl, the broker drops or dead letters the message once it expires in a queue
+  pub fn ttl(mut self, ttl: Duration) -> Self {
+    self.properties.set_expiration(ttl);
+    self
+  }
+
+  pub fn message_id(mut self, message_id: &str) -> Self {
+    self.properties.message_id = Some(message_id.into());
+    self
+  }
+
+  pub fn timestamp(mut self, timestamp: SystemTime) -> Self {
+    self.properties.timestamp = Some(timestamp);
+    self
+  }
+
+  pub fn ty(mut self, ty: &str) -> Self {
+    self.properties.ty = Some(ty.into());
+    self
+  }
+
+  pub fn user_id(mut self, user_id: &str) -> Self {
+    self.properties.user_id = Some(user_id.into());
+    self
+  }
+
+  pub fn app_id(mut self, app_id: &str) -> Self {
+    self.properties.app_id = Some(app_id.into());
+    self
+  }
+
+  pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+    self.body = body.into();
+    self
+  }
+
+  // sets the content type of the encoder as well, unless one was set already
+  pub fn encode<T: ?Sized, E: PayloadEncoder<T>>(mut self, encoder: &E, value: &T) -> Result<Self> {
+    self.body = encoder.encode(value)?;
+    if self.properties.content_type.is_none() {
+      self.properties.content_type = encoder.content_type().map(Into::into);
+    }
+    Ok(self)
+  }
+
+  #[cfg(feature = "json")]
+  pub fn json<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<Self> {
+    self.encode(&crate::api::codec::JsonCodec, value)
+  }
+
+  pub async fn send(self) -> Result<()> {
+    self.channel.publish_with_opts(self.opts, self.body, self.properties).await
+  }
+}
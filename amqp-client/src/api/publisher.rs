@@ -0,0 +1,212 @@
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tracing::warn;
+use crate::api::basic::{BasicPublishOpts, GuaranteeMode, PublishRetry};
+use crate::api::channel::AmqChannel;
+use crate::api::codec::{PayloadEncoder, RawPayload};
+use crate::api::connection::Connection;
+use crate::api::supervisor::Supervisor;
+use crate::error::{ChannelException, Error};
+use crate::protocol::message::BasicProperties;
+use crate::protocol::reply_code::ReplyCode;
+use crate::{bail, runtime, Result};
+
+static MESSAGE_ID_SEQ: AtomicU64 = AtomicU64::new(1);
+static STARTED: OnceLock<u128> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct PublisherOpts {
+  pub exchange: String,
+  // used by send_default
+  pub routing_key: Option<String>,
+  // what send waits for, the publisher's channel is put in the matching mode
+  pub guarantee: GuaranteeMode,
+  pub mandatory: bool,
+  // defaults of every message
+  pub properties: BasicProperties,
+  // None gives up on the first failure
+  pub retry: Option<PublishRetry>,
+  // messages without a message_id get one, the same for every attempt, so consumers can drop
+  // the duplicates a retry after a lost confirm leaves behind
+  pub message_ids: bool,
+}
+
+impl PublisherOpts {
+  pub fn new(exchange: &str) -> Self {
+    Self {
+      exchange: exchange.into(),
+      routing_key: None,
+      guarantee: GuaranteeMode::Confirmed,
+      mandatory: false,
+      properties: BasicProperties::new(),
+      retry: Some(PublishRetry::default()),
+      message_ids: true,
+    }
+  }
+}
+
+// where a closed channel is replaced from
+enum ChannelSource {
+  // reopened on the connection it was created on
+  Connection,
+  // created on the supervisor's current connection, so publishes outlive broker restarts
+  Supervised(watch::Receiver<Arc<Connection>>),
+}
+
+// publishes to a single exchange on a channel of its own, replacing the channel when the broker
+// closed it. Sends may run concurrently, they only wait for each other while queueing
+pub struct Publisher<E = RawPayload> {
+  channel: tokio::sync::Mutex<AmqChannel>,
+  source: ChannelSource,
+  opts: PublisherOpts,
+  encoder: E,
+}
+
+impl Publisher<RawPayload> {
+  pub async fn new(connection: &Connection, opts: PublisherOpts) -> Result<Self> {
+    Self::with_encoder(connection, opts, RawPayload).await
+  }
+}
+
+impl<E> Publisher<E> {
+  pub async fn with_encoder(connection: &Connection, opts: PublisherOpts, encoder: E) -> Result<Self> {
+    let channel = open_channel(connection, opts.guarantee).await?;
+    Ok(Self {
+      channel: tokio::sync::Mutex::new(channel),
+      source: ChannelSource::Connection,
+      opts,
+      encoder,
+    })
+  }
+
+  pub async fn supervised(supervisor: &Supervisor, opts: PublisherOpts, encoder: E) -> Result<Self> {
+    let channel = open_channel(&supervisor.connection(), opts.guarantee).await?;
+    Ok(Self {
+      channel: tokio::sync::Mutex::new(channel),
+      source: ChannelSource::Supervised(supervisor.connections()),
+      opts,
+      encoder,
+    })
+  }
+
+  pub fn exchange(&self) -> &str {
+    &self.opts.exchange
+  }
+
+  pub async fn send<T: ?Sized>(&self, routing_key: &str, payload: &T) -> Result<()>
+    where E: PayloadEncoder<T>
+  {
+    self.send_with(routing_key, payload, self.opts.properties.clone()).await
+  }
+
+  // with properties of its own in place of the ones of the options
+  pub async fn send_with<T: ?Sized>(&self, routing_key: &str, payload: &T, mut properties: BasicProperties) -> Result<()>
+    where E: PayloadEncoder<T>
+  {
+    let body = self.encoder.encode(payload)?;
+    if properties.content_type.is_none() {
+      properties.content_type = self.encoder.content_type().map(Into::into);
+    }
+    if self.opts.message_ids && properties.message_id.is_none() {
+      properties.message_id = Some(next_message_id());
+    }
+    let opts = BasicPublishOpts {
+      exchange: self.opts.exchange.clone(),
+      routing_key: routing_key.into(),
+      mandatory: self.opts.mandatory,
+      immediate: false,
+    };
+
+    let mut attempt = 1;
+    loop {
+      let err = match self.publish(opts.clone(), body.clone(), properties.clone()).await {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+      };
+
+      let Some(retry) = self.opts.retry.as_ref().filter(|retry| attempt < retry.max_attempts && !is_permanent(&err)) else {
+        return Err(err);
+      };
+      // a closed channel, whatever closed it, is replaced by the next attempt
+      if !is_transient(&err, retry) && !self.channel.lock().await.is_closed() {
+        return Err(err);
+      }
+
+      let backoff = retry.initial_backoff.saturating_mul(2_u32.saturating_pow(attempt - 1)).min(retry.max_backoff);
+      warn!("publish to exchange {} failed, attempt {} in {:?}: {}", self.opts.exchange, attempt + 1, backoff, err);
+      attempt += 1;
+      runtime::sleep(backoff).await;
+    }
+  }
+
+  // sends with the routing key of the options
+  pub async fn send_default<T: ?Sized>(&self, payload: &T) -> Result<()>
+    where E: PayloadEncoder<T>
+  {
+    let Some(routing_key) = self.opts.routing_key.as_deref() else {
+      bail!("Publisher to exchange {} has no default routing key", self.opts.exchange);
+    };
+
+    self.send(routing_key, payload).await
+  }
+
+  pub async fn close(self) -> Result<()> {
+    self.channel.into_inner().close().await
+  }
+
+  async fn publish(&self, opts: BasicPublishOpts, body: Vec<u8>, properties: BasicProperties) -> Result<()> {
+    let confirmation = {
+      let mut channel = self.channel.lock().await;
+      if channel.is_closed() {
+        match &self.source {
+          ChannelSource::Connection => channel.reopen().await?,
+          ChannelSource::Supervised(connections) => {
+            let connection = connections.borrow().clone();
+            *channel = open_channel(&connection, self.opts.guarantee).await?;
+          },
+        }
+      }
+
+      match self.opts.guarantee {
+        GuaranteeMode::FireAndForget => return channel.publish_with_opts(opts, body, properties).await,
+        // the commit covers every publish on the channel since the last one, so it's done under the lock
+        GuaranteeMode::Transactional => return channel.publish_guaranteed(opts, body, properties).await,
+        GuaranteeMode::Confirmed => channel.publish_with_confirm(opts, body, properties).await?,
+      }
+    };
+
+    // other sends go ahead while this one waits for the broker
+    confirmation.wait().await
+  }
+}
+
+async fn open_channel(connection: &Connection, guarantee: GuaranteeMode) -> Result<AmqChannel> {
+  let channel = connection.create_channel().await?;
+  channel.set_guarantee(guarantee).await?;
+  Ok(channel)
+}
+
+// the broker refused the publish itself, e.g. for a missing exchange, another attempt fails the same way
+fn is_permanent(err: &Error) -> bool {
+  matches!(err, Error::Channel(ChannelException {
+    reply_code: ReplyCode::NotFound | ReplyCode::AccessRefused | ReplyCode::NotAllowed | ReplyCode::PreconditionFailed,
+    ..
+  }))
+}
+
+// failures of the channel, the connection or the broker's reply rather than of the message itself
+fn is_transient(err: &Error, retry: &PublishRetry) -> bool {
+  match err {
+    Error::PublishNacked(..) => retry.retry_nacks,
+    Error::Channel(..) | Error::Connection(..) | Error::ConnectionFailed(..) | Error::Timeout(..) | Error::PublishQueueFull(..) => true,
+    _ => false,
+  }
+}
+
+// unique across processes for practical purposes: start time, process id and a sequence number
+fn next_message_id() -> String {
+  let started = STARTED.get_or_init(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_micros()).unwrap_or_default());
+  format!("{:x}-{:x}-{:x}", started, std::process::id(), MESSAGE_ID_SEQ.fetch_add(1, Ordering::Relaxed))
+}
@@ -1,7 +1,36 @@
-use std::collections::HashMap;
-use crate::protocol::types::{PropTable};
-use crate::protocol::frame::{QueueDeclare};
+use crate::protocol::types::{Int, PropTable, Property, ShortStr};
+use crate::{bail, Result};
+use crate::utils::duration_millis;
+use std::time::Duration;
+use crate::protocol::frame::{self, QueueBind, QueueDeclare};
 
+pub const QUEUE_TYPE_ARG: &str = "x-queue-type";
+pub const MAX_LENGTH_ARG: &str = "x-max-length";
+pub const DELIVERY_LIMIT_ARG: &str = "x-delivery-limit";
+pub const MAX_AGE_ARG: &str = "x-max-age";
+pub const SINGLE_ACTIVE_CONSUMER_ARG: &str = "x-single-active-consumer";
+pub const MAX_PRIORITY_ARG: &str = "x-max-priority";
+pub const MESSAGE_TTL_ARG: &str = "x-message-ttl";
+pub const EXPIRES_ARG: &str = "x-expires";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueType {
+  Classic,
+  Quorum,
+  Stream,
+}
+
+impl QueueType {
+  pub fn as_str(&self) -> &str {
+    match self {
+      QueueType::Classic => "classic",
+      QueueType::Quorum => "quorum",
+      QueueType::Stream => "stream",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
 pub struct QueueDeclareOpts {
   pub name: String,
   pub passive: bool,
@@ -9,7 +38,10 @@ pub struct QueueDeclareOpts {
   pub exclusive: bool,
   pub auto_delete: bool,
   pub no_wait: bool,
-  pub props: PropTable
+  pub queue_type: Option<QueueType>,
+  pub arguments: PropTable,
+  // overrides the connection's method timeout for this declare
+  pub timeout: Option<Duration>,
 }
 
 impl Default for QueueDeclareOpts {
@@ -22,15 +54,89 @@ impl Default for QueueDeclareOpts {
       exclusive: false,
       auto_delete: false,
       no_wait: false,
-      props: HashMap::new()
+      queue_type: None,
+      arguments: PropTable::new(),
+      timeout: None,
+    }
+  }
+}
+
+impl QueueDeclareOpts {
+  // the broker rejects these combinations with a channel exception, fail before sending
+  pub fn validate(&self) -> Result<()> {
+    // both are milliseconds and have to be numbers, x-expires being positive
+    match self.arguments.get(&ShortStr::from(MESSAGE_TTL_ARG)) {
+      None => {},
+      Some(value) if as_millis(value).is_some_and(|ttl| ttl >= 0) => {},
+      Some(value) => bail!("{} must be a non-negative number of milliseconds, got {:?}", MESSAGE_TTL_ARG, value),
+    }
+    match self.arguments.get(&ShortStr::from(EXPIRES_ARG)) {
+      None => {},
+      Some(value) if as_millis(value).is_some_and(|expires| expires > 0) => {},
+      Some(value) => bail!("{} must be a positive number of milliseconds, got {:?}", EXPIRES_ARG, value),
+    }
+
+    let queue_type = match self.queue_type {
+      Some(QueueType::Classic) | None => {
+        if self.arguments.contains_key(&ShortStr::from(DELIVERY_LIMIT_ARG)) {
+          bail!("{} is supported by quorum queues only", DELIVERY_LIMIT_ARG);
+        }
+        if self.arguments.contains_key(&ShortStr::from(MAX_AGE_ARG)) {
+          bail!("{} is supported by stream queues only", MAX_AGE_ARG);
+        }
+
+        return Ok(());
+      },
+      Some(queue_type) => queue_type,
+    };
+
+    if self.arguments.contains_key(&ShortStr::from(MAX_PRIORITY_ARG)) {
+      bail!("{} is supported by classic queues only", MAX_PRIORITY_ARG);
+    }
+    if !self.durable {
+      bail!("{} queues must be durable", queue_type.as_str());
+    }
+    if self.exclusive {
+      bail!("{} queues can't be exclusive", queue_type.as_str());
+    }
+    if self.auto_delete {
+      bail!("{} queues can't be auto deleted", queue_type.as_str());
+    }
+    if queue_type == QueueType::Stream && self.arguments.contains_key(&ShortStr::from(DELIVERY_LIMIT_ARG)) {
+      bail!("{} is supported by quorum queues only", DELIVERY_LIMIT_ARG);
     }
+    if queue_type == QueueType::Quorum && self.arguments.contains_key(&ShortStr::from(MAX_AGE_ARG)) {
+      bail!("{} is supported by stream queues only", MAX_AGE_ARG);
+    }
+
+    Ok(())
+  }
+}
+
+fn as_millis(value: &Property) -> Option<i64> {
+  match value {
+    Property::SignedByte(v) => Some(*v as i64),
+    Property::Byte(v) => Some(*v as i64),
+    Property::Short(v) => Some(*v as i64),
+    Property::UShort(v) => Some(*v as i64),
+    Property::Int(v) => Some(*v as i64),
+    Property::UInt(v) => Some(*v as i64),
+    Property::Long(v) => Some(*v),
+    _ => None,
   }
 }
 
+#[derive(Default)]
 pub struct QueueDeclareOptsBuilder {
   opts: QueueDeclareOpts
 }
 
+impl From<QueueDeclareOpts> for QueueDeclareOptsBuilder {
+  fn from(opts: QueueDeclareOpts) -> Self {
+    Self { opts }
+  }
+}
+
 impl QueueDeclareOptsBuilder {
   pub fn new() -> Self {
     Self {
@@ -66,45 +172,202 @@ impl QueueDeclareOptsBuilder {
     self.opts.no_wait = no_wait;
   }
 
-  pub fn props(&mut self, props: PropTable) {
-    self.opts.props = props;
+  pub fn timeout(&mut self, timeout: Duration) {
+    self.opts.timeout = Some(timeout);
+  }
+
+  pub fn queue_type(&mut self, queue_type: QueueType) {
+    self.opts.queue_type = Some(queue_type);
+  }
+
+  pub fn max_length(&mut self, max_length: Int) {
+    self.argument(MAX_LENGTH_ARG, Property::Int(max_length));
+  }
+
+  // quorum queues only, deliveries beyond the limit are dropped or dead lettered
+  pub fn delivery_limit(&mut self, delivery_limit: Int) {
+    self.argument(DELIVERY_LIMIT_ARG, Property::Int(delivery_limit));
+  }
+
+  // stream queues only, e.g. "7D", "12h" or "30m"
+  pub fn max_age(&mut self, max_age: &str) {
+    self.argument(MAX_AGE_ARG, Property::LongStr(max_age.into()));
+  }
+
+  // enables message priorities up to max_priority, the broker recommends values up to 10
+  pub fn max_priority(&mut self, max_priority: u8) {
+    self.argument(MAX_PRIORITY_ARG, Property::Int(max_priority as Int));
+  }
+
+  pub fn message_ttl(&mut self, ttl: Duration) {
+    self.argument(MESSAGE_TTL_ARG, Property::Int(duration_millis(ttl)));
+  }
+
+  // the queue is deleted after being unused for the given time
+  pub fn expires(&mut self, expires: Duration) {
+    self.argument(EXPIRES_ARG, Property::Int(duration_millis(expires)));
+  }
+
+  // only one consumer gets deliveries at a time, the others wait on standby
+  pub fn single_active_consumer(&mut self, enabled: bool) {
+    self.argument(SINGLE_ACTIVE_CONSUMER_ARG, Property::Bool(enabled));
+  }
+
+  pub fn arguments(&mut self, arguments: PropTable) {
+    self.opts.arguments = arguments;
+  }
+
+  pub fn argument(&mut self, key: &str, value: Property) {
+    self.opts.arguments.insert(key.into(), value);
   }
 }
-const PASSIVE_MASK: u8 = 0b01;
-const DURABLE_MASK: u8 = 0b10;
-const EXCLUSIVE_MASK: u8 = 0b100;
-const AUTODELETE_MASK: u8 = 0b1000;
-const NOWAIT_MASK: u8 = 0b10000;
 
 impl From<QueueDeclareOpts> for QueueDeclare {
   fn from(options: QueueDeclareOpts) -> Self {
-    let mut flags = 0;
-
-    if options.passive {
-      flags = flags & PASSIVE_MASK;
+    let mut props = options.arguments;
+    if let Some(queue_type) = options.queue_type {
+      props.insert(QUEUE_TYPE_ARG.into(), Property::LongStr(queue_type.as_str().into()));
     }
 
-    if options.durable {
-      flags = flags & DURABLE_MASK;
+    Self {
+      ticket: 0,
+      queue: options.name.into(),
+      passive: options.passive,
+      durable: options.durable,
+      exclusive: options.exclusive,
+      auto_delete: options.auto_delete,
+      no_wait: options.no_wait,
+      arguments: props
     }
+  }
+}
 
-    if options.exclusive {
-      flags = flags & EXCLUSIVE_MASK;
-    }
+#[derive(Debug, Clone)]
+pub struct QueueDeclareOk {
+  pub name: String,
+  pub message_count: u32,
+  pub consumer_count: u32,
+}
 
-    if options.auto_delete {
-      flags = flags & AUTODELETE_MASK;
+impl From<frame::QueueDeclareOk> for QueueDeclareOk {
+  fn from(declare_ok: frame::QueueDeclareOk) -> Self {
+    Self {
+      name: declare_ok.queue.into(),
+      message_count: declare_ok.message_count as u32,
+      consumer_count: declare_ok.consumer_count as u32,
     }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueBindOpts {
+  pub queue: String,
+  pub exchange: String,
+  pub routing_key: String,
+  pub no_wait: bool,
+  pub arguments: PropTable,
+  // overrides the connection's method timeout for this bind
+  pub timeout: Option<Duration>,
+}
+
+#[derive(Default)]
+pub struct QueueBindOptsBuilder {
+  opts: QueueBindOpts
+}
+
+impl From<QueueBindOpts> for QueueBindOptsBuilder {
+  fn from(opts: QueueBindOpts) -> Self {
+    Self { opts }
+  }
+}
 
-    if options.no_wait {
-      flags = flags & NOWAIT_MASK;
+impl QueueBindOptsBuilder {
+  pub fn new() -> Self {
+    Self {
+      opts: QueueBindOpts::default()
     }
+  }
+
+  pub fn build(self) -> QueueBindOpts {
+    self.opts
+  }
+
+  pub fn queue(&mut self, queue: String) {
+    self.opts.queue = queue;
+  }
+
+  pub fn exchange(&mut self, exchange: String) {
+    self.opts.exchange = exchange;
+  }
+
+  pub fn routing_key(&mut self, routing_key: String) {
+    self.opts.routing_key = routing_key;
+  }
+
+  pub fn no_wait(&mut self, no_wait: bool) {
+    self.opts.no_wait = no_wait;
+  }
+
+  pub fn timeout(&mut self, timeout: Duration) {
+    self.opts.timeout = Some(timeout);
+  }
+
+  pub fn arguments(&mut self, arguments: PropTable) {
+    self.opts.arguments = arguments;
+  }
+
+  pub fn argument(&mut self, key: &str, value: Property) {
+    self.opts.arguments.insert(key.into(), value);
+  }
+
+  pub fn header_match(&mut self, header_match: HeaderMatch) {
+    self.opts.arguments.extend(PropTable::from(header_match));
+  }
+}
+
+pub const MATCH_ARG: &str = "x-match";
+
+// binding arguments of a headers exchange
+#[derive(Debug, Clone)]
+pub struct HeaderMatch {
+  mode: &'static str,
+  headers: PropTable,
+}
+
+impl HeaderMatch {
+  // every header has to match
+  pub fn all(headers: PropTable) -> Self {
+    Self { mode: "all", headers }
+  }
+
+  // at least one header has to match
+  pub fn any(headers: PropTable) -> Self {
+    Self { mode: "any", headers }
+  }
+
+  pub fn header(mut self, key: &str, value: Property) -> Self {
+    self.headers.insert(key.into(), value);
+    self
+  }
+}
+
+impl From<HeaderMatch> for PropTable {
+  fn from(header_match: HeaderMatch) -> Self {
+    let mut arguments = header_match.headers;
+    arguments.insert(MATCH_ARG.into(), Property::LongStr(header_match.mode.into()));
+    arguments
+  }
+}
 
+impl From<QueueBindOpts> for QueueBind {
+  fn from(options: QueueBindOpts) -> Self {
     Self {
-      reserved1: 0,
-      name: options.name.into(),
-      flags,
-      props: options.props
+      ticket: 0,
+      queue: options.queue.into(),
+      exchange: options.exchange.into(),
+      routing_key: options.routing_key.into(),
+      no_wait: options.no_wait,
+      arguments: options.arguments
     }
   }
 }
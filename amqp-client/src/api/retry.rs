@@ -0,0 +1,179 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use bytes::Bytes;
+use tracing::warn;
+use crate::api::basic::ConsumeHandlerOpts;
+use crate::api::channel::AmqChannel;
+use crate::api::connection::Connection;
+use crate::api::publisher::{Publisher, PublisherOpts};
+use crate::api::queue::MESSAGE_TTL_ARG;
+use crate::api::topology::{Topology, DEAD_LETTER_EXCHANGE_ARG, DEAD_LETTER_ROUTING_KEY_ARG};
+use crate::protocol::message::{BasicProperties, Delivery};
+use crate::protocol::types::{FromProperty, PropTable, Property, ShortStr};
+use crate::runtime::{self, JoinHandle};
+use crate::utils::duration_millis;
+use crate::{Error, Result};
+
+// failed attempts so far, absent on the first delivery
+pub const RETRIES_HEADER: &str = "x-retries";
+pub const LAST_ERROR_HEADER: &str = "x-last-error";
+
+#[derive(Debug, Clone)]
+pub struct RetryOpts {
+  pub queue: String,
+  // the wait before every retry, a message failing once more than there are delays goes to the
+  // dead letter queue. Each delay gets a queue of its own, "<queue>.retry.<attempt>"
+  pub delays: Vec<Duration>,
+  // defaults to "<queue>.dlq"
+  pub dead_letter_queue: Option<String>,
+  pub durable: bool,
+  // off when the work queue is declared elsewhere, e.g. with arguments of its own
+  pub declare_queue: bool,
+  // on_error applies when a failed message can't be republished, the original is settled by it
+  pub consume: ConsumeHandlerOpts,
+}
+
+impl RetryOpts {
+  pub fn new(queue: &str) -> Self {
+    Self {
+      queue: queue.into(),
+      delays: vec![Duration::from_secs(1), Duration::from_secs(10), Duration::from_secs(60)],
+      dead_letter_queue: None,
+      durable: true,
+      declare_queue: true,
+      consume: ConsumeHandlerOpts::default(),
+    }
+  }
+
+  pub fn retry_queue(&self, attempt: usize) -> String {
+    format!("{}.retry.{}", self.queue, attempt)
+  }
+
+  pub fn dead_letter_queue(&self) -> String {
+    self.dead_letter_queue.clone().unwrap_or_else(|| format!("{}.dlq", self.queue))
+  }
+
+  // retry queues hold messages for their delay, then dead letter them back to the work queue
+  // through the default exchange
+  pub fn topology(&self) -> Topology {
+    let durable = self.durable;
+    let mut topology = Topology::new();
+    if self.declare_queue {
+      topology = topology.queue(|builder| {
+        builder.name(self.queue.clone());
+        builder.durable(durable);
+      });
+    }
+
+    for (attempt, delay) in self.delays.iter().enumerate() {
+      topology = topology.queue(|builder| {
+        builder.name(self.retry_queue(attempt + 1));
+        builder.durable(durable);
+        builder.argument(MESSAGE_TTL_ARG, Property::Int(duration_millis(*delay)));
+        builder.argument(DEAD_LETTER_EXCHANGE_ARG, Property::LongStr("".into()));
+        builder.argument(DEAD_LETTER_ROUTING_KEY_ARG, Property::LongStr(self.queue.clone().into()));
+      });
+    }
+
+    topology.queue(|builder| {
+      builder.name(self.dead_letter_queue());
+      builder.durable(durable);
+    })
+  }
+}
+
+// consumes a queue at least once: a delivery the handler fails on is republished to the next retry
+// queue, or the dead letter queue once the retries are used up, with the attempt count in x-retries.
+// The original is acked only after the broker confirmed the copy
+pub struct RetryConsumer {
+  channel: AmqChannel,
+  publisher: Arc<Publisher>,
+  handle: JoinHandle<()>,
+}
+
+impl RetryConsumer {
+  // declares the retry topology first
+  pub async fn start<F, Fut>(connection: &Connection, opts: RetryOpts, handler: F) -> Result<Self>
+    where F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<()>> + Send + 'static
+  {
+    Self::start_until(connection, opts, handler, std::future::pending()).await
+  }
+
+  // stops consuming once the signal completes, see AmqChannel::basic_consume_until
+  pub async fn start_until<F, Fut, S>(connection: &Connection, opts: RetryOpts, handler: F, signal: S) -> Result<Self>
+    where F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<()>> + Send + 'static,
+          S: Future<Output = ()> + Send + 'static
+  {
+    let channel = connection.create_channel().await?;
+    channel.apply(&opts.topology()).await?;
+
+    // copies keep the message id of the original
+    let mut publisher_opts = PublisherOpts::new("");
+    publisher_opts.message_ids = false;
+    let publisher = Arc::new(Publisher::new(connection, publisher_opts).await?);
+
+    let queue = opts.queue.clone();
+    let consume = opts.consume.clone();
+    let opts = Arc::new(opts);
+    let handler = Arc::new(handler);
+    let republisher = publisher.clone();
+    let handle = channel.basic_consume_until(&queue, consume, move |delivery: Delivery| {
+      let handler = handler.clone();
+      let publisher = republisher.clone();
+      let opts = opts.clone();
+
+      async move {
+        let body = delivery.body_bytes();
+        let properties = delivery.get_properties().clone();
+        let acker = delivery.acker();
+
+        // a panicking handler counts as failed
+        let err = match runtime::spawn(handler(delivery)).await.and_then(|result| result) {
+          Ok(()) => return Ok(()),
+          Err(err) => err,
+        };
+        // settled by the handler itself, nothing left to retry
+        if acker.is_processed() {
+          return Err(err);
+        }
+
+        // the handler's error is logged on republishing already
+        republish(&publisher, &opts, body, properties, &err).await
+      }
+    }, signal).await?;
+
+    Ok(Self { channel, publisher, handle })
+  }
+
+  // waits until consuming stopped and the last delivery was settled, then closes the channels
+  pub async fn join(self) -> Result<()> {
+    self.handle.await?;
+    if let Ok(publisher) = Arc::try_unwrap(self.publisher) {
+      publisher.close().await?;
+    }
+    self.channel.close().await
+  }
+}
+
+async fn republish(publisher: &Publisher, opts: &RetryOpts, body: Bytes, mut properties: BasicProperties, err: &Error) -> Result<()> {
+  let retries = properties.headers.as_ref()
+    .and_then(|headers| headers.get(&ShortStr::from(RETRIES_HEADER)))
+    .and_then(u64::from_property)
+    .unwrap_or(0) as usize;
+
+  let headers = properties.headers.get_or_insert_with(PropTable::new);
+  headers.insert(RETRIES_HEADER.into(), Property::Long(retries as i64 + 1));
+  headers.insert(LAST_ERROR_HEADER.into(), Property::LongStr(err.to_string().into()));
+
+  let target = if retries < opts.delays.len() {
+    opts.retry_queue(retries + 1)
+  } else {
+    opts.dead_letter_queue()
+  };
+  warn!("delivery on {} failed {} times, moving it to {}: {}", opts.queue, retries + 1, target, err);
+
+  publisher.send_with(&target, &body, properties).await
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+use tokio::sync::{oneshot, Semaphore};
+use crate::api::channel::AmqChannel;
+use crate::api::consumer::Consumer;
+use crate::error::RpcTimeout;
+use crate::protocol::message::{BasicProperties, Delivery};
+use crate::protocol::types::ChannelId;
+use crate::{runtime, Error, Result};
+
+pub const DIRECT_REPLY_TO: &str = "amq.rabbitmq.reply-to";
+
+static CORRELATION_ID_SEQ: AtomicU64 = AtomicU64::new(1);
+
+type PendingReplies = Arc<Mutex<HashMap<String, oneshot::Sender<Delivery>>>>;
+
+// correlation bookkeeping shared by the rpc clients
+struct Replies {
+  reply_to: String,
+  pending: PendingReplies,
+}
+
+impl Replies {
+  fn new(reply_to: String, deliveries: Consumer) -> Self {
+    let pending = PendingReplies::default();
+    spawn_reply_router(deliveries, pending.clone());
+
+    Self {
+      reply_to,
+      pending,
+    }
+  }
+
+  async fn call(
+    &self,
+    channel: &AmqChannel,
+    exchange: &str,
+    routing_key: &str,
+    body: Vec<u8>,
+    mut properties: BasicProperties,
+    timeout: Option<Duration>
+  ) -> Result<Delivery> {
+    let correlation_id = next_correlation_id(channel.id);
+    properties.correlation_id = Some(correlation_id.clone());
+    properties.reply_to = Some(self.reply_to.clone());
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    self.pending.lock().unwrap().insert(correlation_id.clone(), reply_tx);
+
+    if let Err(err) = channel.publish(exchange, routing_key, body, properties).await {
+      self.pending.lock().unwrap().remove(&correlation_id);
+      return Err(err);
+    }
+
+    let reply = match timeout {
+      Some(timeout) => match runtime::timeout(timeout, reply_rx).await {
+        Some(reply) => reply,
+        None => {
+          self.pending.lock().unwrap().remove(&correlation_id);
+          return Err(RpcTimeout { correlation_id, timeout }.into());
+        }
+      },
+      None => reply_rx.await
+    };
+
+    match reply {
+      Ok(reply) => Ok(reply),
+      Err(_) => Err(Error::closed(format!("Reply consumer stopped before reply {} arrived", correlation_id)))
+    }
+  }
+
+  fn in_flight(&self) -> usize {
+    self.pending.lock().unwrap().len()
+  }
+}
+
+// rpc over the direct reply-to pseudo queue, no reply queue has to be declared
+pub struct DirectReplyClient {
+  channel: AmqChannel,
+  replies: Replies,
+}
+
+impl DirectReplyClient {
+  pub async fn new(channel: AmqChannel) -> Result<Self> {
+    // broker requires the pseudo queue to be consumed in no-ack mode before the first request is published
+    let deliveries = channel.consume_with_builder(|builder| {
+      builder.queue(DIRECT_REPLY_TO.into());
+      builder.no_ack(true);
+    }).await?;
+
+    Ok(Self {
+      channel,
+      replies: Replies::new(DIRECT_REPLY_TO.into(), deliveries),
+    })
+  }
+
+  pub fn channel(&self) -> &AmqChannel {
+    &self.channel
+  }
+
+  pub async fn call(&self, exchange: &str, routing_key: &str, body: Vec<u8>, properties: BasicProperties) -> Result<Delivery> {
+    self.replies.call(&self.channel, exchange, routing_key, body, properties, None).await
+  }
+}
+
+pub struct RpcClient {
+  channel: AmqChannel,
+  replies: Replies,
+  timeout: Duration,
+}
+
+impl RpcClient {
+  // replies are collected from an exclusive server named queue which lives as long as the channel
+  pub async fn new(channel: AmqChannel) -> Result<Self> {
+    let queue = channel.declare_queue_with_builder(|builder| {
+      builder.durable(false);
+      builder.exclusive(true);
+      builder.auto_delete(true);
+    }).await?;
+    let deliveries = channel.consume_with_builder(|builder| {
+      builder.queue(queue.name.clone());
+      builder.no_ack(true);
+      builder.exclusive(true);
+    }).await?;
+    info!("rpc client replies to {}", queue.name);
+
+    Ok(Self {
+      channel,
+      replies: Replies::new(queue.name, deliveries),
+      timeout: Duration::from_secs(30),
+    })
+  }
+
+  pub fn channel(&self) -> &AmqChannel {
+    &self.channel
+  }
+
+  pub fn reply_queue(&self) -> &str {
+    &self.replies.reply_to
+  }
+
+  pub fn set_timeout(&mut self, timeout: Duration) {
+    self.timeout = timeout;
+  }
+
+  pub fn in_flight(&self) -> usize {
+    self.replies.in_flight()
+  }
+
+  pub async fn call(&self, exchange: &str, routing_key: &str, body: Vec<u8>, properties: BasicProperties) -> Result<Delivery> {
+    self.call_with_timeout(exchange, routing_key, body, properties, self.timeout).await
+  }
+
+  pub async fn call_with_timeout(
+    &self,
+    exchange: &str,
+    routing_key: &str,
+    body: Vec<u8>,
+    properties: BasicProperties,
+    timeout: Duration
+  ) -> Result<Delivery> {
+    self.replies.call(&self.channel, exchange, routing_key, body, properties, Some(timeout)).await
+  }
+}
+
+fn next_correlation_id(channel: ChannelId) -> String {
+  let seq = CORRELATION_ID_SEQ.fetch_add(1, Ordering::Relaxed);
+  format!("rpc-{}-{}", channel, seq)
+}
+
+fn spawn_reply_router(mut replies: Consumer, pending: PendingReplies) {
+  runtime::spawn(async move {
+    while let Some(reply) = replies.recv().await {
+      let correlation_id = reply.get_properties().correlation_id.clone();
+      let waiter = correlation_id
+        .as_ref()
+        .and_then(|correlation_id| pending.lock().unwrap().remove(correlation_id));
+
+      match waiter {
+        Some(waiter) => {
+          let _ = waiter.send(reply);
+        },
+        None => {
+          warn!("dropping reply with unknown correlation id {:?}", correlation_id);
+        }
+      }
+    }
+
+    info!("reply consumer finished");
+    // wake up callers still waiting, their replies will never arrive
+    pending.lock().unwrap().clear();
+  });
+}
+
+#[derive(Debug, Default)]
+pub struct RpcResponse {
+  pub body: Vec<u8>,
+  pub properties: BasicProperties,
+}
+
+impl RpcResponse {
+  pub fn new(body: Vec<u8>) -> Self {
+    Self {
+      body,
+      properties: BasicProperties::new(),
+    }
+  }
+}
+
+pub struct RpcServer {
+  channel: Arc<AmqChannel>,
+  queue: String,
+  concurrency: u16,
+}
+
+impl RpcServer {
+  pub fn new(channel: AmqChannel, queue: &str) -> Self {
+    Self {
+      channel: Arc::new(channel),
+      queue: queue.into(),
+      concurrency: 1,
+    }
+  }
+
+  pub fn set_concurrency(&mut self, concurrency: u16) {
+    self.concurrency = concurrency.max(1);
+  }
+
+  // serves requests until the consumer is cancelled or the channel is closed,
+  // requests are acked once the reply is published and rejected when the handler fails
+  pub async fn run<F, Fut>(self, handler: F) -> Result<()>
+    where F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+          Fut: Future<Output = Result<RpcResponse>> + Send + 'static
+  {
+    self.channel.qos(self.concurrency, false).await?;
+    let mut requests = self.channel.consume(&self.queue).await?;
+    let permits = Arc::new(Semaphore::new(self.concurrency as usize));
+    let handler = Arc::new(handler);
+    info!("rpc server consuming {} with concurrency {}", self.queue, self.concurrency);
+
+    while let Some(request) = requests.recv().await {
+      let permit = permits.clone().acquire_owned().await?;
+      let channel = self.channel.clone();
+      let handler = handler.clone();
+
+      runtime::spawn(async move {
+        let delivery_tag = request.get_metadata().get_delivery_tag();
+        let reply_to = request.get_properties().reply_to.clone();
+        let correlation_id = request.get_properties().correlation_id.clone();
+        // the handler takes the request, its acker settles it afterwards
+        let acker = request.acker();
+
+        let result = match handler(request).await {
+          Ok(response) => reply(&channel, reply_to, correlation_id, response).await,
+          Err(err) => Err(err),
+        };
+
+        let settled = match result {
+          Ok(_) => acker.ack(false),
+          Err(err) => {
+            warn!("rpc request {} failed: {}", delivery_tag, err);
+            acker.reject(false)
+          }
+        };
+        if let Err(err) = settled {
+          warn!("failed to settle rpc request {}: {}", delivery_tag, err);
+        }
+
+        drop(permit);
+      });
+    }
+
+    info!("rpc server for {} finished", self.queue);
+    Ok(())
+  }
+}
+
+async fn reply(channel: &AmqChannel, reply_to: Option<String>, correlation_id: Option<String>, mut response: RpcResponse) -> Result<()> {
+  let reply_to = match reply_to {
+    Some(reply_to) => reply_to,
+    None => {
+      warn!("rpc request without reply_to, dropping response");
+      return Ok(());
+    }
+  };
+
+  response.properties.correlation_id = correlation_id;
+  channel.publish("", &reply_to, response.body, response.properties).await
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use tokio::sync::watch;
+use crate::api::connection::Connection;
+use crate::api::connection::factory::ConnectionFactory;
+use crate::api::connection::options::ConnectionArgs;
+use crate::api::connection::state::ConnectionState;
+use crate::runtime::{self, JoinHandle};
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+  // after failures and after the broker closed the connection on purpose
+  Always,
+  OnFailure,
+  Never,
+}
+
+#[derive(Debug, Clone)]
+pub struct SupervisorOpts {
+  pub policy: RestartPolicy,
+  // the delay doubles with every failed attempt, up to max_backoff
+  pub initial_backoff: Duration,
+  pub max_backoff: Duration,
+  // restarts over the supervisor's lifetime, None for no limit
+  pub max_restarts: Option<u32>,
+}
+
+impl Default for SupervisorOpts {
+  fn default() -> Self {
+    Self {
+      policy: RestartPolicy::OnFailure,
+      initial_backoff: Duration::from_millis(100),
+      max_backoff: Duration::from_secs(30),
+      max_restarts: None,
+    }
+  }
+}
+
+// owns the connection and replaces it once it's closed or failed, the application asks for the
+// current connection instead of holding on to one. Channels and consumers belong to the connection
+// they were created on, connections() tells when they have to be recreated
+pub struct Supervisor {
+  connection_rx: watch::Receiver<Arc<Connection>>,
+  stop_tx: watch::Sender<bool>,
+  task: JoinHandle<()>,
+}
+
+impl Supervisor {
+  // the first connection is made right away, its error is returned as is
+  pub async fn start(args: ConnectionArgs, opts: SupervisorOpts) -> Result<Self> {
+    let connection = Arc::new(ConnectionFactory::create_with_args(args.clone()).await?);
+    let (connection_tx, connection_rx) = watch::channel(connection);
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let task = runtime::spawn(supervise(args, opts, connection_tx, stop_rx));
+
+    Ok(Self {
+      connection_rx,
+      stop_tx,
+      task,
+    })
+  }
+
+  // may be closed or failed while a restart is in progress, or for good once the policy gave up
+  pub fn connection(&self) -> Arc<Connection> {
+    self.connection_rx.borrow().clone()
+  }
+
+  // changes whenever a restarted connection replaces the previous one
+  pub fn connections(&self) -> watch::Receiver<Arc<Connection>> {
+    self.connection_rx.clone()
+  }
+
+  // stops restarting and closes the current connection
+  pub async fn close(self) -> Result<()> {
+    self.stop_tx.send_replace(true);
+    let _ = self.task.await;
+    let connection = self.connection_rx.borrow().clone();
+    match connection.state() {
+      ConnectionState::Open => connection.request_close(),
+      _ => Ok(()),
+    }
+  }
+}
+
+async fn supervise(
+  args: ConnectionArgs,
+  opts: SupervisorOpts,
+  connection_tx: watch::Sender<Arc<Connection>>,
+  mut stop_rx: watch::Receiver<bool>
+) {
+  let mut restarts = 0;
+  loop {
+    let mut state_rx = connection_tx.borrow().state_changes();
+    let state = tokio::select! {
+      state = wait_terminal(&mut state_rx) => state,
+      _ = stopped(&mut stop_rx) => return,
+    };
+
+    let restart = matches!(
+      (&state, opts.policy),
+      (_, RestartPolicy::Always) | (ConnectionState::Failed(..), RestartPolicy::OnFailure)
+    );
+    if !restart {
+      info!("connection {:?}, not restarted by policy {:?}", state, opts.policy);
+      return;
+    }
+
+    warn!("connection {:?}, restarting", state);
+    let mut backoff = opts.initial_backoff;
+    let connection = loop {
+      if opts.max_restarts.is_some_and(|max_restarts| restarts >= max_restarts) {
+        warn!("giving up on the connection after {} restarts", restarts);
+        return;
+      }
+
+      tokio::select! {
+        _ = runtime::sleep(backoff) => {},
+        _ = stopped(&mut stop_rx) => return,
+      }
+
+      restarts += 1;
+      match ConnectionFactory::create_with_args(args.clone()).await {
+        Ok(connection) => break connection,
+        Err(err) => {
+          warn!("restart {} failed: {}", restarts, err);
+          backoff = (backoff * 2).min(opts.max_backoff);
+        }
+      }
+    };
+
+    info!("connection restarted after {} restarts", restarts);
+    args.metrics.reconnected();
+    connection_tx.send_replace(Arc::new(connection));
+  }
+}
+
+async fn wait_terminal(state_rx: &mut watch::Receiver<ConnectionState>) -> ConnectionState {
+  loop {
+    let state = state_rx.borrow_and_update().clone();
+    // a dropped sender means the connection tasks are gone
+    if state.is_terminal() || state_rx.changed().await.is_err() {
+      return state;
+    }
+  }
+}
+
+async fn stopped(stop_rx: &mut watch::Receiver<bool>) {
+  // the sender goes away together with the supervisor, that stops it as well
+  while !*stop_rx.borrow_and_update() {
+    if stop_rx.changed().await.is_err() {
+      return;
+    }
+  }
+}
@@ -0,0 +1,223 @@
+use std::fmt::{Display, Formatter};
+use crate::api::exchange::{ExchangeDeclareOpts, ExchangeDeclareOptsBuilder};
+use crate::api::queue::{QueueBindOpts, QueueBindOptsBuilder, QueueDeclareOk, QueueDeclareOpts, QueueDeclareOptsBuilder, MESSAGE_TTL_ARG};
+use crate::protocol::types::{Int, PropTable, Property};
+use crate::error::{Error, TopologyError};
+
+pub const DEAD_LETTER_EXCHANGE_ARG: &str = "x-dead-letter-exchange";
+pub const DEAD_LETTER_ROUTING_KEY_ARG: &str = "x-dead-letter-routing-key";
+
+#[derive(Debug)]
+pub struct DeadLetterOpts {
+  pub queue: String,
+  // default to "<queue>.dlx", "<queue>.dlq" and the queue name as routing key
+  pub dead_letter_exchange: Option<String>,
+  pub dead_letter_queue: Option<String>,
+  pub dead_letter_routing_key: Option<String>,
+  pub message_ttl: Option<u32>,
+  pub durable: bool,
+  pub arguments: PropTable,
+}
+
+impl Default for DeadLetterOpts {
+  fn default() -> Self {
+    Self {
+      queue: "".to_string(),
+      dead_letter_exchange: None,
+      dead_letter_queue: None,
+      dead_letter_routing_key: None,
+      message_ttl: None,
+      durable: true,
+      arguments: PropTable::new(),
+    }
+  }
+}
+
+impl DeadLetterOpts {
+  pub(crate) fn dead_letter_exchange(&self) -> String {
+    self.dead_letter_exchange.clone().unwrap_or_else(|| format!("{}.dlx", self.queue))
+  }
+
+  pub(crate) fn dead_letter_queue(&self) -> String {
+    self.dead_letter_queue.clone().unwrap_or_else(|| format!("{}.dlq", self.queue))
+  }
+
+  pub(crate) fn dead_letter_routing_key(&self) -> String {
+    self.dead_letter_routing_key.clone().unwrap_or_else(|| self.queue.clone())
+  }
+
+  // arguments of the work queue pointing rejected and expired messages to the dead letter exchange
+  pub(crate) fn queue_arguments(&self) -> PropTable {
+    let mut arguments = self.arguments.clone();
+    arguments.insert(DEAD_LETTER_EXCHANGE_ARG.into(), Property::LongStr(self.dead_letter_exchange().into()));
+    arguments.insert(DEAD_LETTER_ROUTING_KEY_ARG.into(), Property::LongStr(self.dead_letter_routing_key().into()));
+
+    if let Some(ttl) = self.message_ttl {
+      arguments.insert(MESSAGE_TTL_ARG.into(), Property::Int(ttl.min(Int::MAX as u32) as Int));
+    }
+
+    arguments
+  }
+}
+
+#[derive(Default)]
+pub struct DeadLetterOptsBuilder {
+  opts: DeadLetterOpts
+}
+
+impl DeadLetterOptsBuilder {
+  pub fn new() -> Self {
+    Self {
+      opts: DeadLetterOpts::default()
+    }
+  }
+
+  pub fn build(self) -> DeadLetterOpts {
+    self.opts
+  }
+
+  pub fn queue(&mut self, queue: String) {
+    self.opts.queue = queue;
+  }
+
+  pub fn dead_letter_exchange(&mut self, exchange: String) {
+    self.opts.dead_letter_exchange = Some(exchange);
+  }
+
+  pub fn dead_letter_queue(&mut self, queue: String) {
+    self.opts.dead_letter_queue = Some(queue);
+  }
+
+  pub fn dead_letter_routing_key(&mut self, routing_key: String) {
+    self.opts.dead_letter_routing_key = Some(routing_key);
+  }
+
+  pub fn message_ttl(&mut self, ttl: u32) {
+    self.opts.message_ttl = Some(ttl);
+  }
+
+  pub fn durable(&mut self, durable: bool) {
+    self.opts.durable = durable;
+  }
+
+  pub fn arguments(&mut self, arguments: PropTable) {
+    self.opts.arguments = arguments;
+  }
+
+  pub fn argument(&mut self, key: &str, value: Property) {
+    self.opts.arguments.insert(key.into(), value);
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeadLetterTopology {
+  pub queue: QueueDeclareOk,
+  pub dead_letter_exchange: String,
+  pub dead_letter_queue: QueueDeclareOk,
+}
+
+// exchanges, queues and bindings of an application, declared by AmqChannel::apply in that order.
+// Declaring an entity that exists with the same options is a no-op, so it can be applied on every start
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+  pub exchanges: Vec<ExchangeDeclareOpts>,
+  pub queues: Vec<QueueDeclareOpts>,
+  pub bindings: Vec<QueueBindOpts>,
+}
+
+impl Topology {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn exchange<F>(mut self, configure: F) -> Self
+    where F: FnOnce(&mut ExchangeDeclareOptsBuilder)
+  {
+    let mut builder = ExchangeDeclareOptsBuilder::new();
+    configure(&mut builder);
+    self.exchanges.push(builder.build());
+    self
+  }
+
+  pub fn queue<F>(mut self, configure: F) -> Self
+    where F: FnOnce(&mut QueueDeclareOptsBuilder)
+  {
+    let mut builder = QueueDeclareOptsBuilder::new();
+    configure(&mut builder);
+    self.queues.push(builder.build());
+    self
+  }
+
+  pub fn binding<F>(mut self, configure: F) -> Self
+    where F: FnOnce(&mut QueueBindOptsBuilder)
+  {
+    let mut builder = QueueBindOptsBuilder::new();
+    configure(&mut builder);
+    self.bindings.push(builder.build());
+    self
+  }
+
+  pub fn bind(self, queue: &str, exchange: &str, routing_key: &str) -> Self {
+    self.binding(|builder| {
+      builder.queue(queue.into());
+      builder.exchange(exchange.into());
+      builder.routing_key(routing_key.into());
+    })
+  }
+
+  // checks what can be checked without the broker, so a bad entity doesn't leave the topology half declared
+  pub(crate) fn validate(&self) -> Result<(), TopologyError> {
+    for opts in &self.exchanges {
+      if opts.name.is_empty() {
+        return Err(TopologyError::new(TopologyEntity::Exchange(opts.name.clone()), Error::Invalid("Exchange name is required".into())));
+      }
+    }
+
+    for opts in &self.queues {
+      if let Err(err) = opts.validate() {
+        return Err(TopologyError::new(TopologyEntity::Queue(opts.name.clone()), err));
+      }
+    }
+
+    for opts in &self.bindings {
+      if opts.queue.is_empty() {
+        return Err(TopologyError::new(TopologyEntity::binding(opts), Error::Invalid("Queue name is required for a binding".into())));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyEntity {
+  Exchange(String),
+  Queue(String),
+  Binding {
+    queue: String,
+    exchange: String,
+    routing_key: String,
+  },
+}
+
+impl TopologyEntity {
+  pub(crate) fn binding(opts: &QueueBindOpts) -> Self {
+    TopologyEntity::Binding {
+      queue: opts.queue.clone(),
+      exchange: opts.exchange.clone(),
+      routing_key: opts.routing_key.clone(),
+    }
+  }
+}
+
+impl Display for TopologyEntity {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TopologyEntity::Exchange(name) => write!(f, "exchange {}", name),
+      TopologyEntity::Queue(name) => write!(f, "queue {}", name),
+      TopologyEntity::Binding { queue, exchange, routing_key } => {
+        write!(f, "binding of queue {} to exchange {} with key {}", queue, exchange, routing_key)
+      },
+    }
+  }
+}
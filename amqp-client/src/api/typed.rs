@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures_core::Stream;
+use crate::api::codec::{JsonCodec, MessageCodec};
+use crate::api::consumer::Consumer;
+use crate::error::UndecodableDelivery;
+use crate::protocol::message::Delivery;
+use crate::Result;
+
+// a delivery together with its deserialized body, settled like the delivery itself
+#[derive(Debug)]
+pub struct TypedDelivery<T> {
+  payload: T,
+  delivery: Delivery,
+}
+
+impl<T> TypedDelivery<T> {
+  pub fn payload(&self) -> &T {
+    &self.payload
+  }
+
+  pub fn into_payload(self) -> T {
+    self.payload
+  }
+
+  pub fn delivery(&self) -> &Delivery {
+    &self.delivery
+  }
+
+  pub fn into_parts(self) -> (T, Delivery) {
+    (self.payload, self.delivery)
+  }
+
+  pub fn ack(&self, multiple: bool) -> Result<()> {
+    self.delivery.ack(multiple)
+  }
+
+  pub fn nack(&self, multiple: bool, requeue: bool) -> Result<()> {
+    self.delivery.nack(multiple, requeue)
+  }
+
+  pub fn reject(&self, requeue: bool) -> Result<()> {
+    self.delivery.reject(requeue)
+  }
+}
+
+// a body that doesn't deserialize ends up as an UndecodableDelivery error carrying the delivery,
+// the stream goes on with the next one
+pub struct TypedConsumer<T, C = JsonCodec> {
+  consumer: Consumer,
+  codec: C,
+  _payload: PhantomData<fn() -> T>,
+}
+
+impl<T, C: MessageCodec<T>> TypedConsumer<T, C> {
+  pub(crate) fn new(consumer: Consumer, codec: C) -> Self {
+    Self {
+      consumer,
+      codec,
+      _payload: PhantomData,
+    }
+  }
+
+  pub fn tag(&self) -> &str {
+    self.consumer.tag()
+  }
+
+  pub fn consumer(&self) -> &Consumer {
+    &self.consumer
+  }
+
+  pub fn cancel_on<S>(&mut self, signal: S)
+    where S: Future<Output = ()> + Send + 'static
+  {
+    self.consumer.cancel_on(signal)
+  }
+
+  pub async fn recv(&mut self) -> Option<Result<TypedDelivery<T>>> {
+    let delivery = self.consumer.recv().await?;
+    Some(decode(&self.codec, delivery))
+  }
+
+  pub async fn shutdown(self, grace_period: Duration, requeue: bool) -> Result<()> {
+    self.consumer.shutdown(grace_period, requeue).await
+  }
+}
+
+impl<T, C: MessageCodec<T> + Unpin> Stream for TypedConsumer<T, C> {
+  type Item = Result<TypedDelivery<T>>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    let this = &mut *self;
+    Pin::new(&mut this.consumer)
+      .poll_next(cx)
+      .map(|next| next.map(|delivery| delivery.and_then(|delivery| decode(&this.codec, delivery))))
+  }
+}
+
+// checks the content type first, parameters like the charset are ignored. Bodies without a content type
+// are left to the codec
+fn decode<T, C: MessageCodec<T>>(codec: &C, delivery: Delivery) -> Result<TypedDelivery<T>> {
+  let mime = delivery.get_properties().content_type.as_deref()
+    .map(|content_type| content_type.split(';').next().unwrap_or_default().trim());
+
+  let payload = match mime {
+    Some(mime) if !codec.accepts(mime) => Err(format!("unsupported content type {}", mime)),
+    _ => codec.decode(delivery.get_body()).map_err(|err| err.to_string()),
+  };
+
+  match payload {
+    Ok(payload) => Ok(TypedDelivery { payload, delivery }),
+    Err(reason) => Err(UndecodableDelivery { delivery, reason }.into()),
+  }
+}
@@ -1,6 +1,16 @@
+mod channel_dispatcher;
 mod channel_manager;
+mod confirms;
+mod consumer_backlog;
 mod macros;
 mod command;
+mod publish_rate;
+mod publish_window;
 
-pub(crate) use channel_manager::ChannelManager;
+pub(crate) use channel_dispatcher::ContentBudget;
+pub(crate) use channel_manager::{mark_closed, ChannelShared, ChannelManager};
+pub(crate) use confirms::PendingConfirms;
+pub(crate) use consumer_backlog::{delivery_channel, ChannelBacklogs, ConsumerBacklog, DeliveryReceiver, DeliverySender};
 pub(crate) use command::{Command, CommandPayload};
+pub(crate) use publish_rate::PublishLimiter;
+pub(crate) use publish_window::PublishWindow;
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use bytes::Bytes;
+use tracing::warn;
+use tokio::sync::oneshot;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use crate::building_blocks::{ChannelBacklogs, DeliverySender};
+use crate::error::{ContentLimitExceeded, UnexpectedFrame};
+use crate::protocol::frame::{ContentBody, ContentFrame, Frame, FrameEnvelope};
+use crate::protocol::message::{Delivery, DeliveryMetadata, UnsettledCount};
+use crate::protocol::types::{ChannelId, Long};
+use crate::protocol::reply_code::ReplyCode;
+use crate::{runtime, Result};
+
+#[allow(clippy::large_enum_variant)]
+enum DispatchEvent {
+  Content(Frame),
+  RegisterConsumer(String, DeliverySender),
+  UnregisterConsumer(String),
+  // a sync reply held back until the content queued before it reached the consumers
+  Reply(oneshot::Sender<Frame>, Frame),
+}
+
+// content of a channel is reassembled and handed to its consumers by a task of its own,
+// so a channel with large or slowly drained deliveries doesn't hold up the socket reader.
+// Consumer (un)registration goes through the same queue to stay ordered with the deliveries
+pub(crate) struct ChannelDispatcher {
+  channel: ChannelId,
+  events_tx: UnboundedSender<DispatchEvent>,
+  expected: Expected,
+  budget: ChannelBudget,
+}
+
+// content bytes being reassembled, reserved once a header announces the body size and released
+// once the message is handed to its consumer or dropped. Zero limits don't limit anything
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ContentBudget {
+  buffered: Arc<AtomicUsize>,
+  max_buffered: usize,
+  max_per_channel: usize,
+}
+
+impl ContentBudget {
+  pub fn new(max_buffered: usize, max_per_channel: usize) -> Self {
+    Self {
+      buffered: Default::default(),
+      max_buffered,
+      max_per_channel,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct ChannelBudget {
+  channel: ChannelId,
+  buffered: Arc<AtomicUsize>,
+  connection: ContentBudget,
+}
+
+impl ChannelBudget {
+  fn reserve(&self, size: Long) -> Result<()> {
+    let exceeded = |scope, limit| ContentLimitExceeded { channel: self.channel, size: size as u64, scope, limit };
+    let size = size as usize;
+    let max_per_channel = self.connection.max_per_channel;
+    if max_per_channel > 0 && self.buffered.load(Ordering::Acquire) + size > max_per_channel {
+      return Err(exceeded("channel", max_per_channel).into());
+    }
+
+    let max_buffered = self.connection.max_buffered;
+    let buffered = self.connection.buffered.fetch_add(size, Ordering::AcqRel) + size;
+    if max_buffered > 0 && buffered > max_buffered {
+      self.connection.buffered.fetch_sub(size, Ordering::AcqRel);
+      return Err(exceeded("connection", max_buffered).into());
+    }
+
+    self.buffered.fetch_add(size, Ordering::AcqRel);
+    Ok(())
+  }
+
+  fn release(&self, size: Long) {
+    self.buffered.fetch_sub(size as usize, Ordering::AcqRel);
+    self.connection.buffered.fetch_sub(size as usize, Ordering::AcqRel);
+  }
+}
+
+// the next content frame the channel may receive, tracked on the socket reader so a broken
+// sequence fails the connection before anything reaches the dispatch task
+enum Expected {
+  Method,
+  Header,
+  Body { remaining: Long },
+}
+
+impl ChannelDispatcher {
+  // the task ends once the dispatcher is dropped and the queued content is handed over
+  pub fn spawn(channel: ChannelId, outgoing_tx: UnboundedSender<FrameEnvelope>, unsettled: UnsettledCount, channel_unsettled: UnsettledCount, backlogs: ChannelBacklogs, budget: ContentBudget) -> Self {
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let budget = ChannelBudget { channel, buffered: Default::default(), connection: budget };
+    runtime::spawn(dispatch(channel, outgoing_tx, unsettled, channel_unsettled, backlogs, budget.clone(), events_rx));
+
+    Self {
+      channel,
+      events_tx,
+      expected: Expected::Method,
+      budget,
+    }
+  }
+
+  pub fn dispatch_content(&mut self, frame: Frame) -> Result<()> {
+    self.expected = match (&self.expected, &frame) {
+      (Expected::Method, Frame::BasicDeliver(..) | Frame::BasicReturn(..) | Frame::BasicGetOk(..)) => Expected::Header,
+      (Expected::Method, Frame::ContentHeader(..)) => return self.unexpected("content header without a content method"),
+      (Expected::Method, Frame::ContentBody(..)) => return self.unexpected("content body without a content header"),
+      (Expected::Method, _) => return self.unexpected("method doesn't carry content"),
+      // an empty body has no frames at all
+      (Expected::Header, Frame::ContentHeader(header)) if header.body_len == 0 => Expected::Method,
+      (Expected::Header, Frame::ContentHeader(header)) => {
+        self.budget.reserve(header.body_len)?;
+        Expected::Body { remaining: header.body_len }
+      },
+      (Expected::Header, _) => return self.unexpected("content header expected"),
+      (Expected::Body { remaining }, Frame::ContentBody(body)) => {
+        match remaining.checked_sub(body.0.len() as Long) {
+          Some(0) => Expected::Method,
+          Some(remaining) => Expected::Body { remaining },
+          None => return self.unexpected("content body larger than announced by the header"),
+        }
+      },
+      (Expected::Body { .. }, _) => return self.unexpected("content body expected"),
+    };
+
+    let _ = self.events_tx.send(DispatchEvent::Content(frame));
+    Ok(())
+  }
+
+  fn unexpected(&self, reason: &'static str) -> Result<()> {
+    Err(UnexpectedFrame { channel: self.channel, reason }.into())
+  }
+
+  pub fn register_consumer(&self, tag: String, consumer_tx: DeliverySender) {
+    let _ = self.events_tx.send(DispatchEvent::RegisterConsumer(tag, consumer_tx));
+  }
+
+  pub fn unregister_consumer(&self, tag: &str) {
+    let _ = self.events_tx.send(DispatchEvent::UnregisterConsumer(tag.into()));
+  }
+
+  pub fn reply(&self, responder: oneshot::Sender<Frame>, frame: Frame) {
+    let _ = self.events_tx.send(DispatchEvent::Reply(responder, frame));
+  }
+}
+
+async fn dispatch(
+  channel: ChannelId,
+  outgoing_tx: UnboundedSender<FrameEnvelope>,
+  unsettled: UnsettledCount,
+  channel_unsettled: UnsettledCount,
+  backlogs: ChannelBacklogs,
+  budget: ChannelBudget,
+  mut events_rx: UnboundedReceiver<DispatchEvent>
+) {
+  let mut consumers: HashMap<String, DeliverySender> = HashMap::new();
+  let mut pending: Option<ContentFrame> = None;
+  // body size of the pending content, reserved by the dispatcher
+  let mut reserved: Long = 0;
+
+  while let Some(event) = events_rx.recv().await {
+    let frame = match event {
+      DispatchEvent::RegisterConsumer(tag, consumer_tx) => {
+        consumers.insert(tag, consumer_tx);
+        continue;
+      },
+      DispatchEvent::UnregisterConsumer(tag) => {
+        consumers.remove(&tag);
+        continue;
+      },
+      DispatchEvent::Reply(responder, frame) => {
+        let _ = responder.send(frame);
+        continue;
+      },
+      DispatchEvent::Content(frame) => frame,
+    };
+
+    // the sequence was checked by the dispatcher already
+    let content = match (pending.take(), frame) {
+      (Some(content), Frame::ContentHeader(header)) => {
+        reserved = header.body_len;
+        match header.body_len {
+          0 => content.with_content_header(header).and_then(|content| content.with_body(ContentBody(Bytes::new()))),
+          _ => content.with_content_header(header),
+        }
+      },
+      (Some(content), Frame::ContentBody(body)) => content.with_body(body),
+      (_, frame) => Ok(ContentFrame::WithMethod(frame)),
+    };
+    let content = match content {
+      Ok(content) => content,
+      Err(err) => {
+        warn!("dropped content on channel {}: {}", channel, err);
+        budget.release(std::mem::take(&mut reserved));
+        continue;
+      }
+    };
+
+    if !content.is_complete() {
+      pending = Some(content);
+      continue;
+    }
+
+    let ContentFrame::WithBody((method, header, body)) = content else {
+      continue;
+    };
+    // handed over from here on, the consumer owns the memory
+    budget.release(std::mem::take(&mut reserved));
+
+    match method {
+      Frame::BasicDeliver(deliver) => {
+        let Some(consumer) = consumers.get(deliver.consumer_tag.as_str()) else {
+          warn!("delivery for unknown consumer {} on channel {}", deliver.consumer_tag, channel);
+          continue;
+        };
+
+        let metadata = DeliveryMetadata::new(
+          deliver.consumer_tag.into(),
+          deliver.delivery_tag,
+          deliver.redelivered,
+          deliver.exchange.into(),
+          deliver.routing_key.into()
+        );
+        let message = Delivery::new(channel, outgoing_tx.clone(), header.prop_list, metadata, body.0)
+          .with_unsettled(unsettled.clone(), channel_unsettled.clone(), backlogs.clone());
+        // a consumer dropped meanwhile leaves the delivery unacked, the broker requeues it on cancel or close
+        consumer.send(message);
+      },
+      Frame::BasicReturn(returned) => {
+        // published as mandatory or immediate but not routable
+        warn!(
+          "message to exchange {:?} with routing key {:?} returned on channel {}: {} {}",
+          returned.exchange, returned.routing_key, channel, ReplyCode::from(returned.reply_code), returned.reply_text
+        );
+      },
+      method => {
+        warn!("unhandled content on channel {}: {:?}, {} bytes", channel, method, body.0.len());
+      }
+    }
+  }
+
+  // the channel went away halfway through a message
+  budget.release(reserved);
+}
@@ -1,84 +1,214 @@
-use std::collections::{HashMap, VecDeque};
-use tokio::sync::{oneshot};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use tokio::sync::{oneshot, watch};
 use tokio::sync::mpsc::{UnboundedSender};
+use crate::building_blocks::channel_dispatcher::{ChannelDispatcher, ContentBudget};
 use crate::protocol::types::{ChannelId};
-use crate::protocol::frame::{FrameEnvelope, Frame, ContentFrame};
-use crate::protocol::message::{Message, MessageMetadata};
-use crate::Result;
+use crate::protocol::frame::{ChannelClose, FrameEnvelope, Frame};
+use crate::protocol::reply_code::ReplyCode;
+use crate::building_blocks::{ChannelBacklogs, DeliverySender, PendingConfirms};
+use crate::api::channel::CloseReason;
+use crate::api::connection::snapshot::ChannelSnapshot;
+use crate::protocol::message::UnsettledCount;
+use crate::{Error, Result};
+
+// shared by a channel with the connection, so a snapshot reads them and a close by the connection
+// is seen without asking the channel
+#[derive(Debug, Clone)]
+pub(crate) struct ChannelShared {
+  pub unsettled: UnsettledCount,
+  pub confirms: Arc<Mutex<Option<PendingConfirms>>>,
+  pub backlogs: ChannelBacklogs,
+  pub closed: Arc<watch::Sender<Option<CloseReason>>>,
+}
+
+impl Default for ChannelShared {
+  fn default() -> Self {
+    Self {
+      unsettled: Default::default(),
+      confirms: Default::default(),
+      backlogs: Default::default(),
+      closed: Arc::new(watch::channel(None).0),
+    }
+  }
+}
+
+// the first reason sticks, e.g. a client close isn't overwritten by the connection loss that follows
+pub(crate) fn mark_closed(closed_tx: &watch::Sender<Option<CloseReason>>, reason: CloseReason) {
+  closed_tx.send_if_modified(|closed| {
+    if closed.is_some() {
+      return false;
+    }
+    *closed = Some(reason);
+    true
+  });
+}
 
 pub (crate) struct ChannelManager {
+  outgoing_tx: UnboundedSender<FrameEnvelope>,
   sync_waiters: HashMap<ChannelId, VecDeque<oneshot::Sender<Frame>>>,
   channel_dispatchers: HashMap<ChannelId, UnboundedSender<FrameEnvelope>>,
-  consumers: HashMap<ChannelId, HashMap<String, UnboundedSender<Message>>>,
+  content_dispatchers: HashMap<ChannelId, ChannelDispatcher>,
+  // tags of the registered consumers, a shutdown cancels them
+  consumer_tags: HashMap<ChannelId, Vec<String>>,
+  shared: HashMap<ChannelId, ChannelShared>,
+  unsettled: UnsettledCount,
+  content_budget: ContentBudget,
+  // closed by the client, waiting for the broker to confirm
+  closing: HashSet<ChannelId>,
 }
 
 impl ChannelManager {
-  pub fn new() -> Self {
-
+  pub fn new(outgoing_tx: UnboundedSender<FrameEnvelope>, unsettled: UnsettledCount, content_budget: ContentBudget) -> Self {
     Self {
+      outgoing_tx,
       sync_waiters: Default::default(),
-      consumers: Default::default(),
-      channel_dispatchers: Default::default()
+      channel_dispatchers: Default::default(),
+      content_dispatchers: Default::default(),
+      consumer_tags: Default::default(),
+      shared: Default::default(),
+      unsettled,
+      content_budget,
+      closing: Default::default(),
     }
   }
 
-  pub fn get_responder(&mut self, channel: ChannelId) -> oneshot::Sender<Frame> {
-    self.sync_waiters.get_mut(&channel).unwrap().pop_front().unwrap()
+  // hands a reply to the oldest pending sync call of the channel
+  pub fn respond(&mut self, channel: ChannelId, frame: Frame) {
+    match self.take_responder(channel) {
+      // the caller may have stopped waiting meanwhile
+      Some(responder) => {
+        let _ = responder.send(frame);
+      },
+      None => warn!("reply without a pending call on channel {}: {:?}", channel, frame),
+    }
   }
 
-  pub fn register_responder(&mut self, channel: ChannelId, responder: oneshot::Sender<Frame>) {
-    if self.sync_waiters.contains_key(&channel) {
-      let channel_waiters = self.sync_waiters.get_mut(&channel).unwrap();
-      channel_waiters.push_back(responder);
-    } else {
-      self.sync_waiters.insert(channel, VecDeque::from([responder]));
+  // for replies that must not overtake deliveries, e.g. a cancel-ok, so the caller finds every
+  // delivery sent before it in the consumer's queue
+  pub fn respond_after_content(&mut self, channel: ChannelId, frame: Frame) {
+    let Some(responder) = self.take_responder(channel) else {
+      warn!("reply without a pending call on channel {}: {:?}", channel, frame);
+      return;
+    };
+
+    match self.content_dispatchers.get(&channel) {
+      Some(dispatcher) => dispatcher.reply(responder, frame),
+      None => {
+        let _ = responder.send(frame);
+      },
     }
   }
 
-  pub fn register_channel(&mut self, channel: ChannelId, incoming_tx: UnboundedSender<FrameEnvelope>) {
+  pub fn take_responder(&mut self, channel: ChannelId) -> Option<oneshot::Sender<Frame>> {
+    self.sync_waiters.get_mut(&channel)?.pop_front()
+  }
+
+  pub fn register_responder(&mut self, channel: ChannelId, responder: oneshot::Sender<Frame>) {
+    self.sync_waiters.entry(channel).or_default().push_back(responder);
+  }
+
+  // ids are reused, a fresh channel must not inherit calls left without reply on the previous one
+  pub fn register_channel(&mut self, channel: ChannelId, incoming_tx: UnboundedSender<FrameEnvelope>, shared: ChannelShared) {
+    self.sync_waiters.remove(&channel);
+    self.closing.remove(&channel);
     self.channel_dispatchers.insert(channel, incoming_tx);
+    let dispatcher = ChannelDispatcher::spawn(
+      channel,
+      self.outgoing_tx.clone(),
+      self.unsettled.clone(),
+      shared.unsettled.clone(),
+      shared.backlogs.clone(),
+      self.content_budget.clone()
+    );
+    self.content_dispatchers.insert(channel, dispatcher);
+    self.shared.insert(channel, shared);
+  }
+
+  // drops everything bound to the channel, so pending sync waiters and consumers observe the close
+  pub fn unregister_channel(&mut self, channel: ChannelId) {
+    self.sync_waiters.remove(&channel);
+    self.channel_dispatchers.remove(&channel);
+    self.content_dispatchers.remove(&channel);
+    self.consumer_tags.remove(&channel);
+    self.shared.remove(&channel);
+    self.closing.remove(&channel);
   }
 
-  pub fn register_consumer(&mut self, channel: ChannelId, tag: String, consumer_tx: UnboundedSender<Message>) {
-    if !self.consumers.contains_key(&channel) {
-      self.consumers.insert(channel, Default::default());
+  // closes the channel on the client's side, content being reassembled is dropped right away
+  // and frames still arriving for the channel are discarded until the broker sends close-ok
+  pub fn close_channel(&mut self, channel: ChannelId, reply_code: ReplyCode, reason: String) {
+    warn!("closing channel {}: {}", channel, reason);
+    let close = ChannelClose {
+      reply_code: reply_code.code(),
+      reply_text: reason.clone().into(),
+      class_id: 0,
+      method_id: 0,
+    };
+    let _ = self.outgoing_tx.send((channel, close.into_frame()));
+    if let Some(shared) = self.shared.get(&channel) {
+      mark_closed(&shared.closed, CloseReason::Client { reply_code, reply_text: reason });
     }
+    self.content_dispatchers.remove(&channel);
+    self.consumer_tags.remove(&channel);
+    self.closing.insert(channel);
+  }
 
-    let channel_consumers = self.consumers.get_mut(&channel).unwrap();
-    channel_consumers.insert(tag, consumer_tx);
+  pub fn is_closing(&self, channel: ChannelId) -> bool {
+    self.closing.contains(&channel)
   }
 
-  pub fn dispatch_content_frame(&mut self, channel: ChannelId, outgoing_tx: UnboundedSender<FrameEnvelope>, frame: ContentFrame) {
-    if let ContentFrame::WithBody((frame, header, body)) = frame {
-      let channel_consumers = self.consumers.get_mut(&channel).unwrap();
-
-      match frame {
-        Frame::BasicDeliver(deliver) => {
-          let consumer = channel_consumers.get_mut(&deliver.consumer_tag.0).unwrap();
-          // todo: add metadata to the message
-          let metadata = MessageMetadata::new(
-            deliver.deliver_tag,
-            deliver.redelivered,
-            deliver.exchange.0,
-            deliver.routing_key.0
-          );
-
-          let message = Message::new(channel, outgoing_tx, header.prop_list, metadata, body.0);
-
-          consumer.send(message).unwrap();
-        },
-        _ => {
-          todo!("to be implemented")
+  // open channels besides the default one by id, each with the tags of its consumers
+  pub fn channels(&self) -> Vec<ChannelSnapshot> {
+    let mut channels: Vec<_> = self.channel_dispatchers.keys()
+      .filter(|channel| **channel != 0)
+      .map(|channel| {
+        let shared = self.shared.get(channel).cloned().unwrap_or_default();
+        let unconfirmed = shared.confirms.lock().unwrap().as_ref().map_or(0, |confirms| confirms.pending());
+        ChannelSnapshot {
+          id: *channel,
+          consumer_tags: self.consumer_tags.get(channel).cloned().unwrap_or_default(),
+          unacked: shared.unsettled.get(),
+          unconfirmed,
         }
-      }
+      })
+      .collect();
+    channels.sort_by_key(|channel| channel.id);
+    channels
+  }
 
-    } else {
-      panic!("Invalid frame variant")
+  pub fn register_consumer(&mut self, channel: ChannelId, tag: String, consumer_tx: DeliverySender) {
+    if let Some(dispatcher) = self.content_dispatchers.get(&channel) {
+      self.consumer_tags.entry(channel).or_default().push(tag.clone());
+      dispatcher.register_consumer(tag, consumer_tx);
+    }
+  }
+
+  pub fn unregister_consumer(&mut self, channel: ChannelId, tag: &str) {
+    if let Some(tags) = self.consumer_tags.get_mut(&channel) {
+      tags.retain(|registered| registered != tag);
+    }
+    if let Some(dispatcher) = self.content_dispatchers.get(&channel) {
+      dispatcher.unregister_consumer(tag);
+    }
+  }
+
+  // content methods, headers and bodies, reassembled on the channel's own task
+  pub fn dispatch_content_frame(&mut self, channel: ChannelId, frame: Frame) -> Result<()> {
+    match self.content_dispatchers.get_mut(&channel) {
+      Some(dispatcher) => dispatcher.dispatch_content(frame),
+      None => {
+        warn!("content for unknown channel {}: {:?}", channel, frame);
+        Ok(())
+      }
     }
   }
 
   pub fn dispatch_channel_frame(&self, frame: FrameEnvelope) -> Result<()> {
-    let dispatcher = self.channel_dispatchers.get(&frame.0).unwrap();
+    let Some(dispatcher) = self.channel_dispatchers.get(&frame.0) else {
+      return Err(Error::protocol(ReplyCode::ChannelError, format!("Frame for unknown channel {}", frame.0)));
+    };
     dispatcher.send(frame)?;
     Ok(())
   }
@@ -1,14 +1,21 @@
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
 use crate::protocol::frame::{FrameEnvelope, Frame};
-use crate::protocol::message::Message;
+use crate::api::connection::snapshot::ChannelSnapshot;
+use crate::building_blocks::{ChannelShared, DeliverySender};
+use crate::protocol::reply_code::ReplyCode;
 use crate::protocol::types::ChannelId;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum CommandPayload {
   RegisterResponder((ChannelId, oneshot::Sender<Frame>)),
-  RegisterChannel((ChannelId, UnboundedSender<FrameEnvelope>)),
-  RegisterConsumer(ChannelId, String, UnboundedSender<Message>),
+  RegisterChannel((ChannelId, UnboundedSender<FrameEnvelope>, ChannelShared)),
+  RegisterConsumer(ChannelId, String, DeliverySender),
+  // open channels with their consumer tags and counters
+  ListChannels(oneshot::Sender<Vec<ChannelSnapshot>>),
+  // closed by the client without waiting, e.g. after the broker failed to reply in time
+  CloseChannel(ChannelId, ReplyCode, String),
 }
 
 pub type Command = (CommandPayload, oneshot::Sender<()>);
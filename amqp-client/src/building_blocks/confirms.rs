@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use tokio::sync::oneshot;
+use crate::protocol::types::Long;
+
+// publishes of a channel in confirm mode waiting for the broker's ack or nack, keyed by the
+// sequence number the broker counts from 1 for every publish after confirm.select
+#[derive(Debug)]
+pub(crate) struct PendingConfirms {
+  next_tag: Long,
+  waiting: BTreeMap<Long, oneshot::Sender<bool>>,
+}
+
+impl Default for PendingConfirms {
+  fn default() -> Self {
+    Self {
+      next_tag: 1,
+      waiting: BTreeMap::new(),
+    }
+  }
+}
+
+impl PendingConfirms {
+  // called in publish order, right before the publishes are queued
+  pub fn register(&mut self, count: usize) -> Vec<oneshot::Receiver<bool>> {
+    (0..count)
+      .map(|_| {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        self.waiting.insert(self.next_tag, ack_tx);
+        self.next_tag += 1;
+        ack_rx
+      })
+      .collect()
+  }
+
+  pub fn settle(&mut self, delivery_tag: Long, multiple: bool, ack: bool) {
+    if !multiple {
+      if let Some(ack_tx) = self.waiting.remove(&delivery_tag) {
+        let _ = ack_tx.send(ack);
+      }
+      return;
+    }
+
+    let rest = self.waiting.split_off(&(delivery_tag + 1));
+    for (_, ack_tx) in std::mem::replace(&mut self.waiting, rest) {
+      let _ = ack_tx.send(ack);
+    }
+  }
+
+  pub fn pending(&self) -> usize {
+    self.waiting.len()
+  }
+
+  // the channel is gone, waiting publishers get an error
+  pub fn fail_all(&mut self) {
+    self.waiting.clear();
+  }
+}
@@ -0,0 +1,140 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::error::TryRecvError;
+use crate::protocol::message::{Acker, Delivery};
+use crate::protocol::types::Long;
+
+// deliveries of a single consumer on their way to the application: queued ones came from the
+// broker but weren't taken yet, unsettled ones were handed out and wait for the ack
+#[derive(Debug, Default)]
+pub(crate) struct ConsumerBacklog {
+  queued: AtomicUsize,
+  // in the order they were handed out
+  unsettled: Mutex<Vec<(Instant, Acker)>>,
+}
+
+impl ConsumerBacklog {
+  pub fn queued(&self) -> usize {
+    self.queued.load(Ordering::Acquire)
+  }
+
+  pub fn handed_out(&self, acker: Acker) {
+    let mut unsettled = self.unsettled.lock().unwrap();
+    unsettled.retain(|(_, acker)| !acker.is_processed());
+    unsettled.push((Instant::now(), acker));
+  }
+
+  // unsettled deliveries and how long the oldest of them waits for its ack
+  pub fn unsettled(&self) -> (usize, Option<Duration>) {
+    let mut unsettled = self.unsettled.lock().unwrap();
+    unsettled.retain(|(_, acker)| !acker.is_processed());
+    let oldest = unsettled.first().map(|(handed_out, _)| handed_out.elapsed());
+    (unsettled.len(), oldest)
+  }
+
+  // a settle by tag covers the delivery of the tag, a multiple one all handed out before it as well,
+  // tag zero standing for all of them
+  pub fn settled(&self, delivery_tag: Long, multiple: bool) {
+    let mut unsettled = self.unsettled.lock().unwrap();
+    unsettled.retain(|(_, acker)| {
+      let tag = acker.delivery_tag();
+      if tag == delivery_tag || multiple && (delivery_tag == 0 || tag < delivery_tag) {
+        let _ = acker.mark_processed();
+      }
+      !acker.is_processed()
+    });
+  }
+
+  pub fn take_unsettled(&self) -> Vec<Acker> {
+    let mut unsettled = self.unsettled.lock().unwrap();
+    unsettled.drain(..).map(|(_, acker)| acker).filter(|acker| !acker.is_processed()).collect()
+  }
+}
+
+// backlogs of the consumers of one channel, delivery tags and so settles by tag span all of them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChannelBacklogs(Arc<Mutex<Vec<Weak<ConsumerBacklog>>>>);
+
+impl ChannelBacklogs {
+  pub fn register(&self, backlog: &Arc<ConsumerBacklog>) {
+    let mut backlogs = self.0.lock().unwrap();
+    backlogs.retain(|backlog| backlog.strong_count() > 0);
+    backlogs.push(Arc::downgrade(backlog));
+  }
+
+  pub fn settled(&self, delivery_tag: Long, multiple: bool) {
+    let backlogs: Vec<_> = self.0.lock().unwrap().iter().filter_map(Weak::upgrade).collect();
+    for backlog in backlogs {
+      backlog.settled(delivery_tag, multiple);
+    }
+  }
+}
+
+pub(crate) fn delivery_channel() -> (DeliverySender, DeliveryReceiver) {
+  let (tx, rx) = mpsc::unbounded_channel();
+  let backlog = Arc::new(ConsumerBacklog::default());
+  (DeliverySender { tx, backlog: backlog.clone() }, DeliveryReceiver { rx, backlog })
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DeliverySender {
+  tx: UnboundedSender<Delivery>,
+  backlog: Arc<ConsumerBacklog>,
+}
+
+impl DeliverySender {
+  // false once the consumer is gone
+  pub fn send(&self, delivery: Delivery) -> bool {
+    self.backlog.queued.fetch_add(1, Ordering::AcqRel);
+    if self.tx.send(delivery).is_err() {
+      self.backlog.queued.fetch_sub(1, Ordering::AcqRel);
+      return false;
+    }
+    true
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.tx.is_closed()
+  }
+
+  pub fn backlog(&self) -> &Arc<ConsumerBacklog> {
+    &self.backlog
+  }
+}
+
+#[derive(Debug)]
+pub(crate) struct DeliveryReceiver {
+  rx: UnboundedReceiver<Delivery>,
+  backlog: Arc<ConsumerBacklog>,
+}
+
+impl DeliveryReceiver {
+  pub async fn recv(&mut self) -> Option<Delivery> {
+    let delivery = self.rx.recv().await;
+    self.taken(delivery)
+  }
+
+  pub fn try_recv(&mut self) -> Result<Delivery, TryRecvError> {
+    let delivery = self.rx.try_recv()?;
+    self.backlog.queued.fetch_sub(1, Ordering::AcqRel);
+    Ok(delivery)
+  }
+
+  pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Delivery>> {
+    self.rx.poll_recv(cx).map(|delivery| self.taken(delivery))
+  }
+
+  pub fn backlog(&self) -> &Arc<ConsumerBacklog> {
+    &self.backlog
+  }
+
+  fn taken(&self, delivery: Option<Delivery>) -> Option<Delivery> {
+    if delivery.is_some() {
+      self.backlog.queued.fetch_sub(1, Ordering::AcqRel);
+    }
+    delivery
+  }
+}
@@ -0,0 +1,87 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::api::basic::PublishRate;
+use crate::error::PublishRateLimited;
+use crate::{runtime, Result};
+
+// token buckets of a channel, one for messages and one for bytes, refilled continuously up to
+// a second worth of the rate. A message larger than a bucket waits for it to be full and leaves
+// it in debt, so it still gets through
+#[derive(Debug)]
+pub(crate) struct PublishLimiter {
+  rate: PublishRate,
+  buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+  messages: f64,
+  bytes: f64,
+  refilled: Instant,
+}
+
+impl PublishLimiter {
+  pub fn new(rate: PublishRate) -> Self {
+    Self {
+      buckets: Mutex::new(Buckets {
+        messages: rate.messages_per_sec as f64,
+        bytes: rate.bytes_per_sec as f64,
+        refilled: Instant::now(),
+      }),
+      rate,
+    }
+  }
+
+  pub fn rate(&self) -> PublishRate {
+    self.rate
+  }
+
+  pub async fn acquire(&self, messages: usize, bytes: u64) {
+    while let Some(wait) = self.take(messages, bytes) {
+      runtime::sleep(wait).await;
+    }
+  }
+
+  pub fn try_acquire(&self, messages: usize, bytes: u64) -> Result<()> {
+    match self.take(messages, bytes) {
+      Some(retry_after) => Err(PublishRateLimited { retry_after }.into()),
+      None => Ok(()),
+    }
+  }
+
+  // takes the tokens or returns how long until they are available
+  fn take(&self, messages: usize, bytes: u64) -> Option<Duration> {
+    let mut buckets = self.buckets.lock().unwrap();
+    let now = Instant::now();
+    let elapsed = now.duration_since(buckets.refilled).as_secs_f64();
+    buckets.refilled = now;
+
+    let messages_rate = self.rate.messages_per_sec as f64;
+    let bytes_rate = self.rate.bytes_per_sec as f64;
+    buckets.messages = (buckets.messages + elapsed * messages_rate).min(messages_rate);
+    buckets.bytes = (buckets.bytes + elapsed * bytes_rate).min(bytes_rate);
+
+    let wait = missing(buckets.messages, messages as f64, messages_rate)
+      .max(missing(buckets.bytes, bytes as f64, bytes_rate));
+    if wait > 0.0 {
+      return Some(Duration::from_secs_f64(wait));
+    }
+
+    if messages_rate > 0.0 {
+      buckets.messages -= messages as f64;
+    }
+    if bytes_rate > 0.0 {
+      buckets.bytes -= bytes as f64;
+    }
+    None
+  }
+}
+
+// seconds until the bucket holds the tokens, capped at a full bucket. Zero rates don't limit
+fn missing(tokens: f64, needed: f64, rate: f64) -> f64 {
+  if rate <= 0.0 {
+    return 0.0;
+  }
+
+  (needed.min(rate) - tokens).max(0.0) / rate
+}
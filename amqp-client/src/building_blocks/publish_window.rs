@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{Semaphore, TryAcquireError};
+use crate::error::PublishQueueFull;
+use crate::protocol::frame::Frame;
+use crate::{bail, Error, Result};
+
+// bounds the publishes queued for the writer, like a bounded channel a message takes a slot when queued
+// and frees it once the writer picks it up. Other frames are never held back, so acks and replies
+// can still be sent from sync code
+#[derive(Clone)]
+pub(crate) struct PublishWindow {
+  permits: Arc<Semaphore>,
+  capacity: usize,
+  // an unbounded window has no semaphore to close
+  closed: Arc<AtomicBool>,
+}
+
+impl PublishWindow {
+  // zero disables the limit
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      permits: Arc::new(Semaphore::new(capacity)),
+      capacity,
+      closed: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  pub async fn reserve(&self, count: usize) -> Result<()> {
+    self.check_open()?;
+    if !self.is_bounded() {
+      return Ok(());
+    }
+
+    self.check_fits(count)?;
+    match self.permits.acquire_many(count as u32).await {
+      Ok(permits) => {
+        permits.forget();
+        Ok(())
+      },
+      Err(_) => Err(Error::closed("Connection closed")),
+    }
+  }
+
+  pub fn try_reserve(&self, count: usize) -> Result<()> {
+    self.check_open()?;
+    if !self.is_bounded() {
+      return Ok(());
+    }
+
+    self.check_fits(count)?;
+    match self.permits.try_acquire_many(count as u32) {
+      Ok(permits) => {
+        permits.forget();
+        Ok(())
+      },
+      Err(TryAcquireError::NoPermits) => Err(PublishQueueFull { capacity: self.capacity }.into()),
+      Err(TryAcquireError::Closed) => Err(Error::closed("Connection closed")),
+    }
+  }
+
+  // called by the writer for every frame it takes off the queue
+  pub fn release(&self, frame: &Frame) {
+    let published = match frame {
+      Frame::BasicPublish(_) => 1,
+      Frame::Batch(frames) => frames.iter().filter(|frame| matches!(frame, Frame::BasicPublish(_))).count(),
+      _ => 0,
+    };
+
+    if self.is_bounded() && published > 0 {
+      self.permits.add_permits(published);
+    }
+  }
+
+  // wakes up publishers still waiting for room once the writer is gone, or the connection shuts down
+  pub fn close(&self) {
+    self.closed.store(true, Ordering::Release);
+    self.permits.close();
+  }
+
+  fn check_open(&self) -> Result<()> {
+    if self.closed.load(Ordering::Acquire) {
+      return Err(Error::closed("Connection closed"));
+    }
+
+    Ok(())
+  }
+
+  // publishes queued and not picked up by the writer yet
+  pub fn pending(&self) -> usize {
+    match self.is_bounded() {
+      true => self.capacity.saturating_sub(self.permits.available_permits()),
+      false => 0,
+    }
+  }
+
+  fn is_bounded(&self) -> bool {
+    self.capacity > 0
+  }
+
+  fn check_fits(&self, count: usize) -> Result<()> {
+    if count > self.capacity {
+      bail!("Batch of {} messages exceeds the publish queue capacity of {}", count, self.capacity);
+    }
+
+    Ok(())
+  }
+}
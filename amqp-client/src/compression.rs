@@ -0,0 +1,103 @@
+use std::io::Read;
+use crate::protocol::message::{BasicProperties, Delivery};
+use crate::{bail, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+  #[cfg(feature = "gzip")]
+  Gzip,
+  #[cfg(feature = "zstd")]
+  Zstd,
+}
+
+impl CompressionAlgorithm {
+  // the content encoding of compressed bodies
+  pub fn encoding(&self) -> &'static str {
+    match self {
+      #[cfg(feature = "gzip")]
+      CompressionAlgorithm::Gzip => "gzip",
+      #[cfg(feature = "zstd")]
+      CompressionAlgorithm::Zstd => "zstd",
+    }
+  }
+
+  fn from_encoding(encoding: &str) -> Option<Self> {
+    match encoding {
+      #[cfg(feature = "gzip")]
+      "gzip" => Some(CompressionAlgorithm::Gzip),
+      #[cfg(feature = "zstd")]
+      "zstd" => Some(CompressionAlgorithm::Zstd),
+      _ => None,
+    }
+  }
+
+  fn compress(&self, body: &[u8]) -> Result<Vec<u8>> {
+    match self {
+      #[cfg(feature = "gzip")]
+      CompressionAlgorithm::Gzip => {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(body.len() / 2), flate2::Compression::default());
+        encoder.write_all(body)?;
+        Ok(encoder.finish()?)
+      },
+      #[cfg(feature = "zstd")]
+      CompressionAlgorithm::Zstd => Ok(zstd::encode_all(body, 0)?),
+    }
+  }
+
+  fn decompress(&self, body: &[u8]) -> Result<Vec<u8>> {
+    let mut decompressed = vec![];
+    match self {
+      #[cfg(feature = "gzip")]
+      CompressionAlgorithm::Gzip => {
+        flate2::read::GzDecoder::new(body).read_to_end(&mut decompressed)?;
+      },
+      #[cfg(feature = "zstd")]
+      CompressionAlgorithm::Zstd => {
+        zstd::stream::read::Decoder::new(body)?.read_to_end(&mut decompressed)?;
+      },
+    }
+    Ok(decompressed)
+  }
+}
+
+// bodies of min_size bytes and up are compressed on publish, unless the message already has a content encoding
+#[derive(Debug, Clone, Copy)]
+pub struct Compression {
+  pub algorithm: CompressionAlgorithm,
+  pub min_size: usize,
+}
+
+impl Compression {
+  pub fn new(algorithm: CompressionAlgorithm) -> Self {
+    Self { algorithm, min_size: 1024 }
+  }
+
+  pub(crate) fn compress(&self, body: Vec<u8>, properties: &mut BasicProperties) -> Result<Vec<u8>> {
+    if body.len() < self.min_size || properties.content_encoding.is_some() {
+      return Ok(body);
+    }
+
+    properties.content_encoding = Some(self.algorithm.encoding().into());
+    self.algorithm.compress(&body)
+  }
+}
+
+// bodies with a content encoding of a compiled in algorithm are replaced by the decompressed body
+// and lose the encoding, others are left alone
+pub(crate) fn decompress(delivery: &mut Delivery) -> Result<()> {
+  let Some(encoding) = delivery.get_properties().content_encoding.as_deref() else {
+    return Ok(());
+  };
+  let Some(algorithm) = CompressionAlgorithm::from_encoding(encoding) else {
+    return Ok(());
+  };
+
+  let body = match algorithm.decompress(delivery.get_body()) {
+    Ok(body) => body,
+    Err(err) => bail!("Failed to decompress {} body: {}", algorithm.encoding(), err),
+  };
+  delivery.set_body(body);
+  delivery.properties_mut().content_encoding = None;
+  Ok(())
+}
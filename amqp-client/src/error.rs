@@ -0,0 +1,317 @@
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch, AcquireError};
+use crate::api::topology::TopologyEntity;
+use crate::protocol::frame::{ChannelClose, ConnectionClose};
+use crate::protocol::message::Delivery;
+use crate::protocol::reply_code::ReplyCode;
+use crate::protocol::types::{ChannelId, Short};
+
+pub use amqp_protocol::error::{DecodeError, FrameTooLarge, InvalidShortStr, MalformedFrame, UnexpectedFrame};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// every failure of the client by category, so callers match on the variant instead of the message
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  // the socket or a file failed
+  #[error("I/O error: {0}")]
+  Io(#[from] std::io::Error),
+  // frames that can't be made sense of, e.g. malformed or out of order, with the reply code the spec
+  // gives for them
+  #[error("Protocol error {code}: {context}")]
+  Protocol { code: ReplyCode, context: String },
+  #[error(transparent)]
+  Channel(#[from] ChannelException),
+  #[error(transparent)]
+  Connection(#[from] ConnectionException),
+  #[error(transparent)]
+  Timeout(#[from] Timeout),
+  #[error(transparent)]
+  RpcTimeout(#[from] RpcTimeout),
+  // the channel or the connection was closed on purpose, or closed while the call was waiting
+  #[error("{0}")]
+  Closed(String),
+  #[error(transparent)]
+  ConnectionFailed(#[from] ConnectionFailed),
+  #[error(transparent)]
+  ChannelLimitReached(#[from] ChannelLimitReached),
+  #[error(transparent)]
+  ContentLimitExceeded(#[from] ContentLimitExceeded),
+  #[error(transparent)]
+  PublishQueueFull(#[from] PublishQueueFull),
+  #[error(transparent)]
+  PublishRateLimited(#[from] PublishRateLimited),
+  #[error(transparent)]
+  PublishNacked(#[from] PublishNacked),
+  #[error(transparent)]
+  Topology(Box<TopologyError>),
+  #[error(transparent)]
+  UndecodableDelivery(Box<UndecodableDelivery>),
+  #[error(transparent)]
+  Cancelled(#[from] Cancelled),
+  // arguments or a state the call can't work with, e.g. a queue type without its required options
+  #[error("{0}")]
+  Invalid(String),
+  // failures of application code, e.g. a consume handler, or of codecs
+  #[error(transparent)]
+  Other(anyhow::Error),
+}
+
+impl Error {
+  pub fn other(err: impl Into<anyhow::Error>) -> Self {
+    Error::Other(err.into())
+  }
+
+  pub(crate) fn closed(reason: impl Into<String>) -> Self {
+    Error::Closed(reason.into())
+  }
+
+  pub(crate) fn protocol(code: ReplyCode, context: impl Into<String>) -> Self {
+    Error::Protocol { code, context: context.into() }
+  }
+
+  // the reply code of a broker's close, or the one the client closes the connection with
+  pub fn reply_code(&self) -> Option<ReplyCode> {
+    match self {
+      Error::Protocol { code, .. } => Some(*code),
+      Error::Channel(exception) => Some(exception.reply_code),
+      Error::Connection(exception) => Some(exception.reply_code),
+      _ => None,
+    }
+  }
+}
+
+impl From<TopologyError> for Error {
+  fn from(err: TopologyError) -> Self {
+    Error::Topology(Box::new(err))
+  }
+}
+
+impl From<UndecodableDelivery> for Error {
+  fn from(err: UndecodableDelivery) -> Self {
+    Error::UndecodableDelivery(Box::new(err))
+  }
+}
+
+// the protocol crate reports through anyhow, its typed errors are sorted into their categories
+impl From<anyhow::Error> for Error {
+  fn from(err: anyhow::Error) -> Self {
+    let err = match err.downcast::<Error>() {
+      Ok(err) => return err,
+      Err(err) => err,
+    };
+    let code = if err.is::<FrameTooLarge>() || err.is::<MalformedFrame>() {
+      ReplyCode::FrameError
+    } else if err.is::<DecodeError>() || err.is::<InvalidShortStr>() {
+      ReplyCode::SyntaxError
+    } else if err.is::<UnexpectedFrame>() {
+      ReplyCode::UnexpectedFrame
+    } else {
+      return match err.downcast::<std::io::Error>() {
+        Ok(err) => Error::Io(err),
+        Err(err) => Error::Other(err),
+      };
+    };
+
+    Error::protocol(code, err.to_string())
+  }
+}
+
+impl From<FrameTooLarge> for Error {
+  fn from(err: FrameTooLarge) -> Self {
+    Error::protocol(ReplyCode::FrameError, err.to_string())
+  }
+}
+
+impl From<MalformedFrame> for Error {
+  fn from(err: MalformedFrame) -> Self {
+    Error::protocol(ReplyCode::FrameError, err.to_string())
+  }
+}
+
+impl From<DecodeError> for Error {
+  fn from(err: DecodeError) -> Self {
+    Error::protocol(ReplyCode::SyntaxError, err.to_string())
+  }
+}
+
+impl From<UnexpectedFrame> for Error {
+  fn from(err: UnexpectedFrame) -> Self {
+    Error::protocol(ReplyCode::UnexpectedFrame, err.to_string())
+  }
+}
+
+// the task on the other end is gone, which happens only once the connection or the channel closed
+impl<T> From<mpsc::error::SendError<T>> for Error {
+  fn from(_: mpsc::error::SendError<T>) -> Self {
+    Error::closed("Connection closed")
+  }
+}
+
+impl From<oneshot::error::RecvError> for Error {
+  fn from(_: oneshot::error::RecvError) -> Self {
+    Error::closed("Connection closed while waiting for a reply")
+  }
+}
+
+impl From<watch::error::RecvError> for Error {
+  fn from(_: watch::error::RecvError) -> Self {
+    Error::closed("Channel closed")
+  }
+}
+
+// semaphores are closed together with what they guard
+impl From<AcquireError> for Error {
+  fn from(_: AcquireError) -> Self {
+    Error::closed("Closed while waiting for a permit")
+  }
+}
+
+impl From<url::ParseError> for Error {
+  fn from(err: url::ParseError) -> Self {
+    Error::Invalid(format!("Invalid connection URL: {}", err))
+  }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+  fn from(err: serde_json::Error) -> Self {
+    Error::other(err)
+  }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Channel closed by broker with code {reply_code}: {reply_text} (class: {class_id}, method: {method_id})")]
+pub struct ChannelException {
+  pub reply_code: ReplyCode,
+  pub reply_text: String,
+  pub class_id: Short,
+  pub method_id: Short,
+}
+
+impl From<ChannelClose> for ChannelException {
+  fn from(close: ChannelClose) -> Self {
+    Self {
+      reply_code: close.reply_code.into(),
+      reply_text: close.reply_text.into(),
+      class_id: close.class_id,
+      method_id: close.method_id,
+    }
+  }
+}
+
+// the broker closed the connection with an error, the connection is failed from then on
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Connection closed by broker with code {reply_code}: {reply_text} (class: {class_id}, method: {method_id})")]
+pub struct ConnectionException {
+  pub reply_code: ReplyCode,
+  pub reply_text: String,
+  pub class_id: Short,
+  pub method_id: Short,
+}
+
+impl From<ConnectionClose> for ConnectionException {
+  fn from(close: ConnectionClose) -> Self {
+    Self {
+      reply_code: close.reply_code.into(),
+      reply_text: close.reply_text.into(),
+      class_id: close.class_id,
+      method_id: close.method_id,
+    }
+  }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("All {channel_max} channels allowed by the broker are in use")]
+pub struct ChannelLimitReached {
+  pub channel_max: Short,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Publish queue is full, {capacity} messages are waiting for the writer")]
+pub struct PublishQueueFull {
+  pub capacity: usize,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Publish rate limit reached, retry after {retry_after:?}")]
+pub struct PublishRateLimited {
+  pub retry_after: Duration,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Publish on channel {channel} was nacked by the broker")]
+pub struct PublishNacked {
+  pub channel: ChannelId,
+}
+
+// the delivery is handed back to be settled, it's neither acked nor rejected yet
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to decode delivery {}: {reason}", delivery.get_metadata().get_delivery_tag())]
+pub struct UndecodableDelivery {
+  pub delivery: Delivery,
+  pub reason: String,
+}
+
+// the socket broke, heartbeats were missed or the client gave up on the stream
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Connection failed: {reason}")]
+pub struct ConnectionFailed {
+  pub reason: String,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Content of {size} bytes on channel {channel} exceeds the {scope} limit of {limit} buffered bytes")]
+pub struct ContentLimitExceeded {
+  pub channel: ChannelId,
+  pub size: u64,
+  // the limit that was hit, "channel" or "connection"
+  pub scope: &'static str,
+  pub limit: usize,
+}
+
+// the entity of a topology the broker refused, the channel is usually closed by then
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to declare {entity}: {source}")]
+pub struct TopologyError {
+  pub entity: TopologyEntity,
+  pub source: Error,
+}
+
+impl TopologyError {
+  pub(crate) fn new(entity: TopologyEntity, source: Error) -> Self {
+    Self { entity, source }
+  }
+}
+
+// the operation was given up on because its cancellation signal completed first
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Operation cancelled")]
+pub struct Cancelled;
+
+// the broker didn't reply to a synchronous method in time, the channel was closed since
+// replies arriving later would be taken for the ones of the next calls
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("No reply to method {class_id}.{method_id} on channel {channel} within {timeout:?}")]
+pub struct Timeout {
+  pub channel: ChannelId,
+  pub class_id: Short,
+  pub method_id: Short,
+  pub timeout: Duration,
+}
+
+// the request is forgotten, a reply arriving later is dropped
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Reply {correlation_id} didn't arrive within {timeout:?}")]
+pub struct RpcTimeout {
+  pub correlation_id: String,
+  pub timeout: Duration,
+}
+
+// bails with Error::Invalid, for arguments or a state the call can't work with
+#[macro_export]
+macro_rules! bail {
+  ($($arg:tt)*) => {
+    return Err($crate::Error::Invalid(format!($($arg)*)))
+  };
+}
@@ -0,0 +1,128 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+use crate::api::basic::BasicPublishOpts;
+use crate::protocol::frame::Frame;
+use crate::protocol::message::{BasicProperties, Delivery};
+use crate::protocol::types::ChannelId;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameAction {
+  Forward,
+  Drop,
+}
+
+// sees every frame right after it's decoded and right before it's encoded, e.g. for auditing or
+// chaos testing. Handshake frames included and heartbeats as well. A dropped frame is gone for
+// good, dropping a method someone waits on leaves the call hanging until it times out
+pub trait FrameInterceptor: Send + Sync {
+  fn inbound(&self, _channel: ChannelId, _frame: &mut Frame) -> FrameAction {
+    FrameAction::Forward
+  }
+
+  fn outbound(&self, _channel: ChannelId, _frame: &mut Frame) -> FrameAction {
+    FrameAction::Forward
+  }
+}
+
+impl Debug for dyn FrameInterceptor {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("FrameInterceptor")
+  }
+}
+
+// run in registration order, the first one dropping the frame stops the rest
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Interceptors(Arc<Vec<Arc<dyn FrameInterceptor>>>);
+
+impl Interceptors {
+  pub(crate) fn new(interceptors: Vec<Arc<dyn FrameInterceptor>>) -> Self {
+    Self(Arc::new(interceptors))
+  }
+
+  // true when the frame should go on
+  pub(crate) fn inbound(&self, channel: ChannelId, frame: &mut Frame) -> bool {
+    self.0.iter().all(|interceptor| interceptor.inbound(channel, frame) == FrameAction::Forward)
+  }
+
+  pub(crate) fn outbound(&self, channel: ChannelId, frame: &mut Frame) -> bool {
+    self.0.iter().all(|interceptor| interceptor.outbound(channel, frame) == FrameAction::Forward)
+  }
+}
+
+// message level hooks of a channel, publish interceptors see the properties before they're
+// validated, e.g. to add app_id or tenant headers. An error fails the publish
+pub trait PublishInterceptor: Send + Sync {
+  fn on_publish(&self, opts: &BasicPublishOpts, properties: &mut BasicProperties) -> Result<()>;
+}
+
+// runs before a consumer of the channel sees the delivery, e.g. to decompress the body or check
+// who sent it. An error rejects the delivery without requeue, it's dropped for no_ack consumers
+pub trait DeliveryInterceptor: Send + Sync {
+  fn on_delivery(&self, delivery: &mut Delivery) -> Result<()>;
+}
+
+impl Debug for dyn PublishInterceptor {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("PublishInterceptor")
+  }
+}
+
+impl Debug for dyn DeliveryInterceptor {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("DeliveryInterceptor")
+  }
+}
+
+// shared by a channel and its consumers, so interceptors added later apply to existing consumers too
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MessageInterceptors {
+  publish: Arc<RwLock<Vec<Arc<dyn PublishInterceptor>>>>,
+  delivery: Arc<RwLock<Vec<Arc<dyn DeliveryInterceptor>>>>,
+}
+
+impl MessageInterceptors {
+  pub(crate) fn add_publish(&self, interceptor: Arc<dyn PublishInterceptor>) {
+    self.publish.write().unwrap().push(interceptor);
+  }
+
+  pub(crate) fn add_delivery(&self, interceptor: Arc<dyn DeliveryInterceptor>) {
+    self.delivery.write().unwrap().push(interceptor);
+  }
+
+  pub(crate) fn on_publish(&self, opts: &BasicPublishOpts, properties: &mut BasicProperties) -> Result<()> {
+    for interceptor in self.publish.read().unwrap().iter() {
+      interceptor.on_publish(opts, properties)?;
+    }
+    Ok(())
+  }
+
+  // None once an interceptor refused the delivery
+  pub(crate) fn on_delivery(&self, mut delivery: Delivery, no_ack: bool) -> Option<Delivery> {
+    // interceptors see the body as published
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if let Err(err) = crate::compression::decompress(&mut delivery) {
+      warn!("delivery {} refused: {}", delivery.get_metadata().get_delivery_tag(), err);
+      if !no_ack {
+        let _ = delivery.reject(false);
+      }
+      return None;
+    }
+
+    let interceptors = self.delivery.read().unwrap();
+    for interceptor in interceptors.iter() {
+      let Err(err) = interceptor.on_delivery(&mut delivery) else {
+        continue;
+      };
+
+      warn!("delivery {} refused by interceptor: {}", delivery.get_metadata().get_delivery_tag(), err);
+      if !no_ack {
+        let _ = delivery.reject(false);
+      }
+      return None;
+    }
+
+    Some(delivery)
+  }
+}
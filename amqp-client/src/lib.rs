@@ -1,9 +1,58 @@
 pub(crate) mod protocol;
 pub(crate) mod utils;
-pub(crate) mod default_channel;
 pub(crate) mod api;
 pub(crate) mod building_blocks;
-pub use crate::api::connection::{Connection, ConnectionFactory};
-pub use anyhow::{Result,Error,bail};
-pub use crate ::api::exchange::ExchangeType;
-pub use crate::protocol::message::{Message, MessageProperties};
+pub(crate) mod error;
+pub(crate) mod runtime;
+pub(crate) mod metrics;
+pub(crate) mod interceptor;
+#[cfg(feature = "opentelemetry")]
+pub(crate) mod telemetry;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub(crate) mod compression;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub use crate::api::connection::{Connection, ConnectionFactory, Negotiated};
+pub use crate::api::connection::state::ConnectionState;
+pub use crate::api::connection::snapshot::{ChannelSnapshot, ConnectionSnapshot};
+pub use crate::runtime::JoinHandle;
+pub use crate::metrics::{ClientMetrics, NoopMetrics, Settlement};
+pub use crate::interceptor::{DeliveryInterceptor, FrameAction, FrameInterceptor, PublishInterceptor};
+#[cfg(feature = "prometheus")]
+pub use crate::metrics::PrometheusMetrics;
+pub use crate::api::connection::options::{ConnectionAddress, ConnectionArgs};
+pub use crate::protocol::net::WireLog;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use crate::compression::{Compression, CompressionAlgorithm};
+pub use crate::api::channel::{AmqChannel, CloseReason};
+pub use crate::api::cancel::cancellable;
+pub use crate::api::pool::{ChannelPool, PooledChannel};
+pub use crate::api::supervisor::{RestartPolicy, Supervisor, SupervisorOpts};
+pub use crate::error::{Error, Result};
+pub use crate::error::{Cancelled, ChannelException, ChannelLimitReached, ConnectionException, ConnectionFailed, ContentLimitExceeded, DecodeError, FrameTooLarge, InvalidShortStr, MalformedFrame, PublishNacked, PublishQueueFull, PublishRateLimited, RpcTimeout, Timeout, TopologyError, UndecodableDelivery, UnexpectedFrame};
+pub use crate::api::exchange::{ExchangeType, ExchangeDeclareOpts, ExchangeDeclareOptsBuilder};
+pub use crate::api::queue::{HeaderMatch, QueueBindOpts, QueueBindOptsBuilder, QueueDeclareOk, QueueDeclareOpts, QueueDeclareOptsBuilder, QueueType};
+pub use crate::api::basic::{BasicConsumeOpts, BasicConsumeOptsBuilder, BasicPublishOpts, ConsumeHandlerOpts, GuaranteeMode, NackPolicy, PublishRate, PublishRetry, SlowConsumerOpts};
+pub use crate::api::publish::{Confirmation, PublishBuilder};
+#[cfg(feature = "json")]
+pub use crate::api::typed::{TypedConsumer, TypedDelivery};
+pub use crate::api::publisher::{Publisher, PublisherOpts};
+pub use crate::api::retry::{RetryConsumer, RetryOpts, LAST_ERROR_HEADER, RETRIES_HEADER};
+pub use crate::api::codec::{MessageCodec, PayloadEncoder, RawPayload};
+#[cfg(feature = "json")]
+pub use crate::api::codec::JsonCodec;
+#[cfg(feature = "msgpack")]
+pub use crate::api::codec::MsgPackCodec;
+pub use crate::api::consumer::{Consumer, ConsumerLag, ConsumerState};
+pub use crate::api::ack::AckManager;
+pub use crate::api::topology::{DeadLetterOpts, DeadLetterOptsBuilder, DeadLetterTopology, Topology, TopologyEntity};
+pub use crate::api::rpc::{DirectReplyClient, RpcClient, RpcResponse, RpcServer, DIRECT_REPLY_TO};
+pub use crate::protocol::frame::{Frame, RawMethod};
+pub use crate::protocol::spec;
+pub use crate::protocol::reply_code::ReplyCode;
+pub use crate::protocol::types::{Decimal, FromProperty, PropTable, Property, ShortStr, LongStr};
+pub use crate::protocol::message::{Delivery, DeliveryMetadata, BasicProperties, MessageDeliveryMode};
+
+pub mod blocking {
+  pub use crate::api::blocking::{BlockingChannel, BlockingConnection, BlockingConsumer};
+}
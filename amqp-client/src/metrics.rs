@@ -0,0 +1,72 @@
+use std::fmt::{Debug, Formatter};
+use crate::protocol::frame::Frame;
+use crate::protocol::types::ChannelId;
+use crate::api::consumer::ConsumerLag;
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+#[cfg(feature = "prometheus")]
+pub use self::prometheus::PrometheusMetrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Settlement {
+  Ack,
+  Nack,
+  Reject,
+}
+
+// hooks called by the socket reader and writer and the connection loops, every hook defaults
+// to doing nothing. Calls happen on the hot path, implementations should only bump counters
+pub trait ClientMetrics: Send + Sync {
+  // sizes are the whole frame on the wire, header and frame end included
+  fn frame_sent(&self, _channel: ChannelId, _size: usize) {}
+  fn frame_received(&self, _channel: ChannelId, _size: usize) {}
+  fn message_published(&self, _channel: ChannelId) {}
+  fn confirm_received(&self, _channel: ChannelId, _ack: bool) {}
+  fn delivery_received(&self, _channel: ChannelId) {}
+  fn delivery_settled(&self, _channel: ChannelId, _settlement: Settlement) {}
+  // the client doesn't reconnect on its own, meant for wrappers that do
+  fn reconnected(&self) {}
+  fn heartbeat_missed(&self) {}
+  // publishes queued for the writer, always zero without a publish limit
+  fn outgoing_queue_depth(&self, _depth: usize) {}
+  // a consumer with slow consumer detection reached one of its thresholds, called once until it catches up
+  fn consumer_lagging(&self, _channel: ChannelId, _tag: &str, _lag: &ConsumerLag) {}
+}
+
+impl Debug for dyn ClientMetrics {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("ClientMetrics")
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct NoopMetrics;
+
+impl ClientMetrics for NoopMetrics {}
+
+// meaning of an outgoing frame, taken before the writer encodes it away and recorded once it's written
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum OutgoingEvent {
+  Published,
+  Settled(Settlement),
+}
+
+impl OutgoingEvent {
+  pub(crate) fn of(frame: &Frame) -> Option<Self> {
+    match frame {
+      Frame::BasicPublish(..) => Some(OutgoingEvent::Published),
+      Frame::BasicAck(..) => Some(OutgoingEvent::Settled(Settlement::Ack)),
+      Frame::BasicNack(..) => Some(OutgoingEvent::Settled(Settlement::Nack)),
+      Frame::BasicReject(..) => Some(OutgoingEvent::Settled(Settlement::Reject)),
+      _ => None,
+    }
+  }
+
+  pub(crate) fn record(self, metrics: &dyn ClientMetrics, channel: ChannelId) {
+    match self {
+      OutgoingEvent::Published => metrics.message_published(channel),
+      OutgoingEvent::Settled(settlement) => metrics.delivery_settled(channel, settlement),
+    }
+  }
+}
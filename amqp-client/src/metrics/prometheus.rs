@@ -0,0 +1,102 @@
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+use crate::protocol::types::ChannelId;
+use crate::api::consumer::ConsumerLag;
+use super::{ClientMetrics, Settlement};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct OutcomeLabels {
+  outcome: &'static str,
+}
+
+// counters registered under the amqp_client prefix, channels aren't used as labels to keep the cardinality low
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+  frames_sent: Counter,
+  frames_received: Counter,
+  bytes_sent: Counter,
+  bytes_received: Counter,
+  publishes: Counter,
+  confirms: Family<OutcomeLabels, Counter>,
+  deliveries: Counter,
+  settlements: Family<OutcomeLabels, Counter>,
+  reconnects: Counter,
+  heartbeat_misses: Counter,
+  outgoing_queue_depth: Gauge,
+  lagging_consumers: Counter,
+}
+
+impl PrometheusMetrics {
+  pub fn new(registry: &mut Registry) -> Self {
+    let metrics = Self::default();
+    let registry = registry.sub_registry_with_prefix("amqp_client");
+
+    registry.register("frames_sent", "Frames written to the socket", metrics.frames_sent.clone());
+    registry.register("frames_received", "Frames read from the socket", metrics.frames_received.clone());
+    registry.register("bytes_sent", "Bytes of frames written to the socket", metrics.bytes_sent.clone());
+    registry.register("bytes_received", "Bytes of frames read from the socket", metrics.bytes_received.clone());
+    registry.register("publishes", "Messages published", metrics.publishes.clone());
+    registry.register("confirms", "Publisher confirms by outcome", metrics.confirms.clone());
+    registry.register("deliveries", "Messages delivered to consumers", metrics.deliveries.clone());
+    registry.register("settlements", "Deliveries settled by outcome", metrics.settlements.clone());
+    registry.register("reconnects", "Connections re-established", metrics.reconnects.clone());
+    registry.register("heartbeat_misses", "Connections dropped for missed heartbeats", metrics.heartbeat_misses.clone());
+    registry.register("outgoing_queue_depth", "Publishes queued for the writer", metrics.outgoing_queue_depth.clone());
+    registry.register("lagging_consumers", "Consumers falling behind their slow consumer thresholds", metrics.lagging_consumers.clone());
+
+    metrics
+  }
+}
+
+impl ClientMetrics for PrometheusMetrics {
+  fn frame_sent(&self, _channel: ChannelId, size: usize) {
+    self.frames_sent.inc();
+    self.bytes_sent.inc_by(size as u64);
+  }
+
+  fn frame_received(&self, _channel: ChannelId, size: usize) {
+    self.frames_received.inc();
+    self.bytes_received.inc_by(size as u64);
+  }
+
+  fn message_published(&self, _channel: ChannelId) {
+    self.publishes.inc();
+  }
+
+  fn confirm_received(&self, _channel: ChannelId, ack: bool) {
+    let outcome = if ack { "ack" } else { "nack" };
+    self.confirms.get_or_create(&OutcomeLabels { outcome }).inc();
+  }
+
+  fn delivery_received(&self, _channel: ChannelId) {
+    self.deliveries.inc();
+  }
+
+  fn delivery_settled(&self, _channel: ChannelId, settlement: Settlement) {
+    let outcome = match settlement {
+      Settlement::Ack => "ack",
+      Settlement::Nack => "nack",
+      Settlement::Reject => "reject",
+    };
+    self.settlements.get_or_create(&OutcomeLabels { outcome }).inc();
+  }
+
+  fn reconnected(&self) {
+    self.reconnects.inc();
+  }
+
+  fn heartbeat_missed(&self) {
+    self.heartbeat_misses.inc();
+  }
+
+  fn outgoing_queue_depth(&self, depth: usize) {
+    self.outgoing_queue_depth.set(depth as i64);
+  }
+
+  fn consumer_lagging(&self, _channel: ChannelId, _tag: &str, _lag: &ConsumerLag) {
+    self.lagging_consumers.inc();
+  }
+}
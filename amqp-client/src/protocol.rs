@@ -1,6 +1,4 @@
-pub(crate) mod enc;
-pub(crate) mod dec;
-pub(crate) mod types;
-pub(crate) mod frame;
+pub(crate) use amqp_protocol::{enc, types, frame};
+pub use amqp_protocol::{reply_code, spec};
 pub(crate) mod message;
 pub(crate) mod net;
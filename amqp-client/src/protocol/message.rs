@@ -1,280 +1,282 @@
-use std::cell::Cell;
-use std::io::Cursor;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
-use anyhow::bail;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::bail;
+use bytes::Bytes;
 use tokio::sync::mpsc::UnboundedSender;
-use crate::protocol::dec::Decode;
-use crate::protocol::enc::Encode;
-use crate::protocol::frame::{BasicAck, BasicReject, FrameEnvelope};
-use crate::protocol::types::{ChannelId, PropTable};
+use crate::protocol::frame::{BasicAck, BasicNack, BasicReject, Frame, FrameEnvelope};
+use crate::protocol::types::{ChannelId, FromProperty, Long, PropTable, ShortStr};
+use crate::building_blocks::ChannelBacklogs;
 use crate::Result;
 
+pub use amqp_protocol::properties::{BasicProperties, MessageDeliveryMode};
+
 #[derive(Debug)]
-pub struct MessageMetadata {
+pub struct DeliveryMetadata {
+  consumer_tag: String,
   delivery_tag: i64,
   redelivered: bool,
   exchange: String,
   routing_key: String,
 }
 
-impl MessageMetadata {
+impl DeliveryMetadata {
   pub fn new(
+    consumer_tag: String,
     delivery_tag: i64,
     redelivered: bool,
     exchange: String,
     routing_key: String
   ) -> Self {
     Self {
+      consumer_tag,
       delivery_tag,
       redelivered,
       exchange,
       routing_key
     }
   }
-}
-
-#[derive(Debug)]
-pub struct Message {
-  channel: ChannelId,
-  outgoing_tx: UnboundedSender<FrameEnvelope>,
-  properties: MessageProperties,
-  metadata: MessageMetadata,
-  body: Vec<u8>,
-  is_processed: Cell<bool>
-}
 
-impl Message {
-  pub fn new(
-    channel: ChannelId,
-    outgoing_tx: UnboundedSender<FrameEnvelope>,
-    properties: MessageProperties,
-    metadata: MessageMetadata,
-    body: Vec<u8>
-  ) -> Self {
-    Self {
-      channel,
-      outgoing_tx,
-      properties,
-      metadata,
-      body,
-      is_processed: Cell::new(false)
-    }
+  pub fn get_consumer_tag(&self) -> &str {
+    &self.consumer_tag
   }
 
-  pub fn get_body(&self) -> &[u8] {
-    self.body.as_slice()
+  pub fn get_delivery_tag(&self) -> i64 {
+    self.delivery_tag
   }
 
-  pub fn get_properties(&self) -> &MessageProperties {
-    &self.properties
+  pub fn is_redelivered(&self) -> bool {
+    self.redelivered
   }
 
-  pub fn ack(&self, multiple: bool) -> Result<()> {
-    if self.is_processed.get() {
-      bail!("Already processed")
-    }
-
-    let method = BasicAck { delivery_tag: self.metadata.delivery_tag, multiple };
-    self.outgoing_tx.send((self.channel, method.into_frame()))?;
-    self.is_processed.set(true);
-    Ok(())
+  pub fn get_exchange(&self) -> &str {
+    &self.exchange
   }
 
-  pub fn reject(&self, requeue: bool) -> Result<()> {
-    if self.is_processed.get() {
-      bail!("Already processed")
-    }
-
-    let method = BasicReject { delivery_tag: self.metadata.delivery_tag, requeue };
-    self.outgoing_tx.send((self.channel, method.into_frame()))?;
-    self.is_processed.set(true);
-    Ok(())
+  pub fn get_routing_key(&self) -> &str {
+    &self.routing_key
   }
 }
 
-#[derive(Debug)]
-pub enum MessageDeliveryMode {
-  Persistent,
-  NonPersistent
+// deliveries of a connection, or of one of its channels, handed to the application and not settled yet
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UnsettledCount(Arc<AtomicUsize>);
+
+impl UnsettledCount {
+  pub(crate) fn get(&self) -> usize {
+    self.0.load(Ordering::Acquire)
+  }
 }
 
-#[derive(Default, Debug)]
-pub struct MessageProperties {
-  pub content_type: Option<String>,
-  pub content_encoding: Option<String>,
-  pub headers: Option<PropTable>,
-  pub delivery_mode: Option<MessageDeliveryMode>,
-  pub priority: Option<u8>,
-  pub correlation_id: Option<String>,
-  pub reply_to: Option<String>,
-  pub expiration: Option<String>,
-  pub message_id: Option<String>,
-  pub timestamp: Option<Duration>,
-  pub ty: Option<String>,
-  pub user_id: Option<String>,
-  pub app_id: Option<String>,
-  reserved: String
+// settles a single delivery, shared with tasks that outlive the delivery itself
+#[derive(Debug, Clone)]
+pub(crate) struct Acker {
+  channel: ChannelId,
+  outgoing_tx: UnboundedSender<FrameEnvelope>,
+  delivery_tag: Long,
+  is_processed: Arc<AtomicBool>,
+  unsettled: UnsettledCount,
+  channel_unsettled: UnsettledCount,
+  is_tracked: Arc<AtomicBool>,
+  // a multiple settle covers the deliveries handed out before on the channel
+  backlogs: ChannelBacklogs,
 }
 
-impl MessageProperties {
-  pub fn new() -> Self {
-    Default::default()
+impl Acker {
+  pub(crate) fn is_processed(&self) -> bool {
+    self.is_processed.load(Ordering::Acquire)
   }
-}
 
-impl Into<Vec<u8>> for MessageProperties {
-  fn into(self) -> Vec<u8> {
-    let mut result = vec![];
-    let mut flag = 0_u16;
-    let mut value = vec![];
+  pub(crate) fn channel(&self) -> ChannelId {
+    self.channel
+  }
 
-    if let Some(content_type) = self.content_type {
-      flag = flag | 0b1000_0000_0000_0000;
-      value.write_shortstr(content_type.into()).unwrap();
-    }
+  pub(crate) fn delivery_tag(&self) -> Long {
+    self.delivery_tag
+  }
 
-    if let Some(content_encoding) = self.content_encoding {
-      flag = flag | 0b100_0000_0000_0000;
-      value.write_shortstr(content_encoding.into()).unwrap();
+  // counts the delivery as unsettled until it's settled, for deliveries the application has to settle
+  pub(crate) fn track(&self) {
+    if !self.is_tracked.swap(true, Ordering::AcqRel) {
+      self.unsettled.0.fetch_add(1, Ordering::AcqRel);
+      self.channel_unsettled.0.fetch_add(1, Ordering::AcqRel);
     }
+  }
 
-    if let Some(headers) = self.headers {
-      flag = flag | 0b10_0000_0000_0000;
-      value.write_proptable(headers).unwrap();
+  // settled later as part of a multiple ack
+  pub(crate) fn mark_processed(&self) -> Result<()> {
+    if self.is_processed.swap(true, Ordering::AcqRel) {
+      bail!("Already processed")
     }
 
-    if let Some(delivery_mode) = self.delivery_mode {
-      flag = flag | 0b1_0000_0000_0000;
-      match delivery_mode {
-        MessageDeliveryMode::NonPersistent => {
-          value.write_byte(1).unwrap();
-        }
-        MessageDeliveryMode::Persistent => {
-          value.write_byte(2).unwrap();
-        }
-      }
-    }
+    self.untrack();
+    Ok(())
+  }
 
-    if let Some(priority) = self.priority {
-      flag = flag | 0b1000_0000_0000;
-      value.write_byte(priority).unwrap();
-    }
+  pub(crate) fn ack(&self, multiple: bool) -> Result<()> {
+    self.settle(BasicAck { delivery_tag: self.delivery_tag, multiple }.into_frame(), multiple)
+  }
 
-    if let Some(correlation_id) = self.correlation_id {
-      flag = flag | 0b100_0000_0000;
-      value.write_shortstr(correlation_id.into()).unwrap();
-    }
+  pub(crate) fn nack(&self, multiple: bool, requeue: bool) -> Result<()> {
+    self.settle(BasicNack::new(self.delivery_tag, multiple, requeue).into_frame(), multiple)
+  }
 
-    if let Some(reply_to) = self.reply_to {
-      flag = flag | 0b10_0000_0000;
-      value.write_shortstr(reply_to.into()).unwrap();
-    }
+  pub(crate) fn reject(&self, requeue: bool) -> Result<()> {
+    self.settle(BasicReject { delivery_tag: self.delivery_tag, requeue }.into_frame(), false)
+  }
 
-    if let Some(expiration) = self.expiration {
-      flag = flag | 0b1_0000_0000;
-      value.write_shortstr(expiration.into()).unwrap();
+  fn settle(&self, frame: Frame, multiple: bool) -> Result<()> {
+    if self.is_processed.swap(true, Ordering::AcqRel) {
+      bail!("Already processed")
     }
 
-    if let Some(message_id) = self.message_id {
-      flag = flag | 0b1000_0000;
-      value.write_shortstr(message_id.into()).unwrap();
+    self.untrack();
+    if multiple {
+      self.backlogs.settled(self.delivery_tag, true);
     }
+    self.outgoing_tx.send((self.channel, frame))?;
+    Ok(())
+  }
 
-    if let Some(timestamp) = self.timestamp {
-      flag = flag | 0b100_0000;
-      value.write_ulong(timestamp.as_secs()).unwrap();
+  fn untrack(&self) {
+    if self.is_tracked.load(Ordering::Acquire) {
+      self.unsettled.0.fetch_sub(1, Ordering::AcqRel);
+      self.channel_unsettled.0.fetch_sub(1, Ordering::AcqRel);
     }
+  }
+}
 
-    if let Some(ty) = self.ty {
-      flag = flag | 0b10_0000;
-      value.write_shortstr(ty.into()).unwrap();
-    }
+#[derive(Debug)]
+pub struct Delivery {
+  properties: BasicProperties,
+  metadata: DeliveryMetadata,
+  body: Bytes,
+  acker: Acker,
+  #[cfg(feature = "opentelemetry")]
+  trace_context: opentelemetry::Context,
+}
 
+impl Delivery {
+  pub fn new(
+    channel: ChannelId,
+    outgoing_tx: UnboundedSender<FrameEnvelope>,
+    properties: BasicProperties,
+    metadata: DeliveryMetadata,
+    body: Bytes
+  ) -> Self {
+    let acker = Acker {
+      channel,
+      outgoing_tx,
+      delivery_tag: metadata.delivery_tag,
+      is_processed: Arc::new(AtomicBool::new(false)),
+      unsettled: Default::default(),
+      channel_unsettled: Default::default(),
+      is_tracked: Arc::new(AtomicBool::new(false)),
+      backlogs: Default::default(),
+    };
 
-    if let Some(user_id) = self.user_id {
-      flag = flag | 0b1_0000;
-      value.write_shortstr(user_id.into()).unwrap();
+    Self {
+      #[cfg(feature = "opentelemetry")]
+      trace_context: crate::telemetry::extract(&properties),
+      properties,
+      metadata,
+      body,
+      acker,
     }
+  }
 
-    if let Some(app_id) = self.app_id {
-      flag = flag | 0b1000;
-      value.write_shortstr(app_id.into()).unwrap();
-    }
+  // counted together with the other deliveries of the connection and of the channel once handed out
+  pub(crate) fn with_unsettled(mut self, unsettled: UnsettledCount, channel_unsettled: UnsettledCount, backlogs: ChannelBacklogs) -> Self {
+    self.acker.unsettled = unsettled;
+    self.acker.channel_unsettled = channel_unsettled;
+    self.acker.backlogs = backlogs;
+    self
+  }
 
-    result.write_ushort(flag).unwrap();
-    result.append(&mut value);
+  pub fn get_body(&self) -> &[u8] {
+    &self.body
+  }
 
-    result
+  // shares the memory of the body
+  pub(crate) fn body_bytes(&self) -> Bytes {
+    self.body.clone()
   }
-}
 
-impl From<Vec<u8>> for MessageProperties {
-  fn from(mut data: Vec<u8>) -> Self {
-    let mut cursor = Cursor::new(data);
-    let flag = cursor.read_ushort().unwrap();
-    let mut fields = MessageProperties::new();
+  pub fn get_properties(&self) -> &BasicProperties {
+    &self.properties
+  }
 
-    if (flag & 0b1000_0000_0000_0000 ) != 0 {
-      fields.content_type = Some(cursor.read_shortstr().unwrap().0);
-    }
+  // for delivery interceptors, e.g. replacing a compressed body
+  pub fn set_body(&mut self, body: impl Into<Bytes>) {
+    self.body = body.into();
+  }
 
-    if (flag & 0b100_0000_0000_0000 ) != 0 {
-      fields.content_encoding = Some(cursor.read_shortstr().unwrap().0);
-    }
+  pub fn properties_mut(&mut self) -> &mut BasicProperties {
+    &mut self.properties
+  }
 
-    if (flag & 0b10_0000_0000_0000 ) != 0 {
-      fields.headers = Some(cursor.read_proptable().unwrap());
-    }
+  // the body as JSON, whatever the content type says
+  #[cfg(feature = "json")]
+  pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+    Ok(serde_json::from_slice(&self.body)?)
+  }
 
-    if (flag & 0b1_0000_0000_0000 ) != 0 {
-      let mode = cursor.read_byte().unwrap();
+  pub fn get_metadata(&self) -> &DeliveryMetadata {
+    &self.metadata
+  }
 
-      fields.delivery_mode = Some(if mode == 2 {
-        MessageDeliveryMode::Persistent
-      } else {
-        MessageDeliveryMode::NonPersistent
-      });
+  pub fn get_consumer_tag(&self) -> &str {
+    self.metadata.get_consumer_tag()
+  }
 
-      fields.headers = Some(cursor.read_proptable().unwrap());
-    }
+  pub fn get_delivery_tag(&self) -> i64 {
+    self.metadata.get_delivery_tag()
+  }
 
-    if (flag & 0b1000_0000_0000 ) != 0 {
-      fields.priority = Some(cursor.read_byte().unwrap());
-    }
+  pub fn is_redelivered(&self) -> bool {
+    self.metadata.is_redelivered()
+  }
 
-    if (flag & 0b100_0000_0000 ) != 0 {
-      fields.correlation_id = Some(cursor.read_shortstr().unwrap().0);
-    }
+  pub fn get_exchange(&self) -> &str {
+    self.metadata.get_exchange()
+  }
 
-    if (flag & 0b10_0000_0000 ) != 0 {
-      fields.reply_to = Some(cursor.read_shortstr().unwrap().0);
-    }
+  pub fn get_routing_key(&self) -> &str {
+    self.metadata.get_routing_key()
+  }
 
-    if (flag & 0b1_0000_0000 ) != 0 {
-      fields.expiration = Some(cursor.read_shortstr().unwrap().0);
-    }
+  pub fn get_headers(&self) -> Option<&PropTable> {
+    self.properties.headers.as_ref()
+  }
 
-    if (flag & 0b1000_0000 ) != 0 {
-      fields.message_id = Some(cursor.read_shortstr().unwrap().0);
-    }
+  // None when the header is missing or holds a value of another type, e.g. delivery.header::<i64>("x-retries")
+  pub fn header<T: FromProperty>(&self, name: &str) -> Option<T> {
+    self.get_headers()?.get(&ShortStr::from(name)).and_then(T::from_property)
+  }
 
-    if (flag & 0b100_0000 ) != 0 {
-      fields.timestamp = Some(Duration::from_secs(cursor.read_ulong().unwrap()));
-    }
+  // context of the publisher's trace, empty when the message carried none
+  #[cfg(feature = "opentelemetry")]
+  pub fn trace_context(&self) -> &opentelemetry::Context {
+    &self.trace_context
+  }
 
-    if (flag & 0b10_0000 ) != 0 {
-      fields.ty = Some(cursor.read_shortstr().unwrap().0);
-    }
+  // zero unless published with a priority
+  pub fn get_priority(&self) -> u8 {
+    self.properties.priority.unwrap_or(0)
+  }
 
-    if (flag & 0b1_0000 ) != 0 {
-      fields.user_id = Some(cursor.read_shortstr().unwrap().0);
-    }
+  pub(crate) fn acker(&self) -> Acker {
+    self.acker.clone()
+  }
 
-    if (flag & 0b1000 ) != 0 {
-      fields.app_id = Some(cursor.read_shortstr().unwrap().0);
-    }
+  pub fn ack(&self, multiple: bool) -> Result<()> {
+    self.acker.ack(multiple)
+  }
 
-    fields
+  pub fn nack(&self, multiple: bool, requeue: bool) -> Result<()> {
+    self.acker.nack(multiple, requeue)
+  }
+
+  pub fn reject(&self, requeue: bool) -> Result<()> {
+    self.acker.reject(requeue)
   }
 }
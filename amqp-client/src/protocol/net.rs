@@ -1,4 +1,16 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+mod buffers;
 mod reader;
 mod writer;
+mod wiretap;
+
+pub(crate) use buffers::BufferPool;
 pub(crate) use reader::FrameReader;
 pub(crate) use writer::FrameWriter;
+pub(crate) use wiretap::{Direction, FrameInfo, WireTap};
+pub use wiretap::WireLog;
+
+// a connection runs over anything carrying bytes both ways, a socket as well as an in-memory pipe
+pub(crate) type ReadStream = Box<dyn AsyncRead + Send + Unpin>;
+pub(crate) type WriteStream = Box<dyn AsyncWrite + Send + Unpin>;
@@ -0,0 +1,36 @@
+use std::sync::{Arc, Mutex};
+
+// encode buffers are handed back after each write so steady traffic doesn't allocate per frame,
+// buffers grown past max_buffer_size by a large message are dropped instead of being kept around
+#[derive(Clone)]
+pub struct BufferPool {
+  buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+  max_buffers: usize,
+  max_buffer_size: usize,
+}
+
+impl BufferPool {
+  pub fn new(max_buffers: usize, max_buffer_size: usize) -> Self {
+    Self {
+      buffers: Arc::new(Mutex::new(Vec::with_capacity(max_buffers))),
+      max_buffers,
+      max_buffer_size,
+    }
+  }
+
+  pub fn take(&self) -> Vec<u8> {
+    self.buffers.lock().unwrap().pop().unwrap_or_default()
+  }
+
+  pub fn put(&self, mut buf: Vec<u8>) {
+    if buf.capacity() > self.max_buffer_size {
+      return;
+    }
+
+    let mut buffers = self.buffers.lock().unwrap();
+    if buffers.len() < self.max_buffers {
+      buf.clear();
+      buffers.push(buf);
+    }
+  }
+}
@@ -0,0 +1,123 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+use crate::protocol::frame::Frame;
+use crate::protocol::types::{ChannelId, Short};
+use crate::Result;
+
+// debug aid, describes every frame going through the socket in one line:
+// direction, channel, frame name with class and method id, size on the wire and the payload in hex
+#[derive(Debug, Clone, Default)]
+pub enum WireLog {
+  #[default]
+  Off,
+  // debug records with the amqp_client::wire target
+  Trace,
+  // appended to the file, written from the socket tasks so only meant for debugging
+  File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Direction {
+  Inbound,
+  Outbound,
+}
+
+// taken before the writer encodes the frame away
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameInfo {
+  name: &'static str,
+  method_ids: Option<(Short, Short)>,
+}
+
+impl FrameInfo {
+  pub(crate) fn of(frame: &Frame) -> Self {
+    Self {
+      name: frame.name(),
+      method_ids: frame.method_ids(),
+    }
+  }
+}
+
+enum Sink {
+  Off,
+  Trace,
+  File(Mutex<LineWriter<File>>),
+}
+
+// shared by the reader and the writer of a connection
+pub(crate) struct WireTap {
+  sink: Sink,
+  payload_bytes: usize,
+}
+
+impl WireTap {
+  pub(crate) fn new(log: &WireLog, payload_bytes: usize) -> Result<Self> {
+    let sink = match log {
+      WireLog::Off => Sink::Off,
+      WireLog::Trace => Sink::Trace,
+      WireLog::File(path) => {
+        let file = OpenOptions::new()
+          .create(true)
+          .append(true)
+          .open(path)
+          .map_err(|err| io::Error::new(err.kind(), format!("Failed to open wire capture file {}: {}", path.display(), err)))?;
+        Sink::File(Mutex::new(LineWriter::new(file)))
+      }
+    };
+
+    Ok(Self { sink, payload_bytes })
+  }
+
+  // lets callers skip collecting the payload
+  pub(crate) fn is_enabled(&self) -> bool {
+    !matches!(self.sink, Sink::Off)
+  }
+
+  pub(crate) fn record(&self, direction: Direction, channel: ChannelId, frame: FrameInfo, size: usize, payload: &[u8]) {
+    if !self.is_enabled() {
+      return;
+    }
+
+    let line = self.describe(direction, channel, frame, size, payload);
+    match &self.sink {
+      Sink::Off => {},
+      Sink::Trace => debug!(target: "amqp_client::wire", "{}", line),
+      Sink::File(file) => {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut file = file.lock().unwrap();
+        if let Err(err) = writeln!(file, "{}.{:03} {}", now.as_secs(), now.subsec_millis(), line) {
+          warn!("Failed to write wire capture: {}", err);
+        }
+      }
+    }
+  }
+
+  fn describe(&self, direction: Direction, channel: ChannelId, frame: FrameInfo, size: usize, payload: &[u8]) -> String {
+    let arrow = match direction {
+      Direction::Inbound => "<",
+      Direction::Outbound => ">",
+    };
+    let mut line = format!("{} channel={} {}", arrow, channel, frame.name);
+    if let Some((class_id, method_id)) = frame.method_ids {
+      let _ = write!(line, "({}.{})", class_id, method_id);
+    }
+    let _ = write!(line, " size={}", size);
+
+    if !payload.is_empty() && self.payload_bytes > 0 {
+      line.push_str(" payload=");
+      for byte in payload.iter().take(self.payload_bytes) {
+        let _ = write!(line, "{:02x}", byte);
+      }
+      if payload.len() > self.payload_bytes {
+        let _ = write!(line, "..(+{} bytes)", payload.len() - self.payload_bytes);
+      }
+    }
+
+    line
+  }
+}
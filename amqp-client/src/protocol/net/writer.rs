@@ -1,44 +1,235 @@
-use tokio::io::{AsyncWriteExt, BufWriter};
-use tokio::net::tcp::{OwnedWriteHalf};
-use crate::protocol::types::{ChannelId};
+use std::io::IoSlice;
+use std::ops::Range;
+use std::sync::Arc;
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use crate::protocol::types::{ChannelId, Int};
+use crate::error::FrameTooLarge;
+use amqp_protocol::codec::{encode_frame, FRAME_END_SIZE, FRAME_HEADER_SIZE};
+use crate::protocol::spec::{FRAME_BODY, FRAME_END};
 use crate::protocol::frame::{Frame};
-use crate::{Result};
+use crate::{Error, Result};
 use crate::protocol::enc::Encode;
+use crate::metrics::{ClientMetrics, OutgoingEvent};
+use crate::interceptor::Interceptors;
+use crate::protocol::net::{BufferPool, Direction, FrameInfo, WireTap, WriteStream};
 
+// pending bytes after which the writer should be flushed before more frames are queued
+const WRITE_THRESHOLD: usize = 128 * 1024;
+// slices handed to a single vectored write, a longer list is written in several
+const MAX_IO_SLICES: usize = 64;
+// smaller bodies are cheaper to copy than to write as slices of their own
+const MAX_INLINE_BODY: usize = 4 * 1024;
+
+// outgoing bytes in wire order, content bodies are written straight from the message buffer
+enum Segment {
+  Encoded(Range<usize>),
+  Body(Bytes),
+}
+
+// frames are encoded into a single buffer that is written to the socket as is, so bytes aren't
+// copied into another buffering layer on the way out. Large bodies are only referenced
 pub struct FrameWriter {
-  inner: BufWriter<OwnedWriteHalf>
+  inner: WriteStream,
+  buffers: BufferPool,
+  // frames encoded since the last write
+  buf: Vec<u8>,
+  segments: Vec<Segment>,
+  encoded_from: usize,
+  pending: usize,
+  frame_max: Int,
+  metrics: Arc<dyn ClientMetrics>,
+  // frames of the pending bytes, reported once they made it to the socket
+  written: Vec<(ChannelId, usize, Option<OutgoingEvent>)>,
+  wiretap: Arc<WireTap>,
+  interceptors: Interceptors,
 }
 
 impl FrameWriter {
-  pub fn new(inner: BufWriter<OwnedWriteHalf>) -> Self {
-    Self { inner }
+  pub fn new(inner: WriteStream, buffers: BufferPool, frame_max: Int, metrics: Arc<dyn ClientMetrics>, wiretap: Arc<WireTap>, interceptors: Interceptors) -> Self {
+    Self {
+      inner,
+      buf: buffers.take(),
+      buffers,
+      segments: vec![],
+      encoded_from: 0,
+      pending: 0,
+      frame_max,
+      metrics,
+      written: vec![],
+      wiretap,
+      interceptors,
+    }
+  }
+
+  pub fn set_frame_max(&mut self, frame_max: Int) {
+    self.frame_max = frame_max;
   }
 
   pub async fn dispatch(&mut self, channel: ChannelId, frame: Frame) -> Result<()> {
-    let frame_ty = match &frame {
-      Frame::ContentHeader(..) => 2,
-      Frame::ContentBody(..) => 3,
-      Frame::Heartbeat => 8,
-      _ => 1,
-    };
+    self.write_frame(channel, frame)?;
+    self.flush().await
+  }
 
-    let mut payload = frame.to_raw_repr();
-    let mut frame_buff = vec![];
+  // only encodes, nothing reaches the socket before the flush. An envelope that fails to encode
+  // leaves nothing behind
+  pub fn write_frame(&mut self, channel: ChannelId, frame: Frame) -> Result<()> {
+    let rollback = (self.buf.len(), self.segments.len(), self.encoded_from, self.pending, self.written.len());
 
-    frame_buff.write_byte(frame_ty).unwrap();
-    frame_buff.write_short(channel).unwrap();
-    frame_buff.write_uint(payload.len() as u32).unwrap();
-    frame_buff.append(&mut payload);
-    frame_buff.write_byte(0xCE).unwrap();
+    let encoded = match frame {
+      Frame::Batch(frames) => frames.into_iter().try_for_each(|frame| self.encode(channel, frame)),
+      frame => self.encode(channel, frame),
+    };
+    if let Err(err) = encoded {
+      let (buf_len, segments_len, encoded_from, pending, written_len) = rollback;
+      self.buf.truncate(buf_len);
+      self.segments.truncate(segments_len);
+      self.encoded_from = encoded_from;
+      self.pending = pending;
+      self.written.truncate(written_len);
+      return Err(err);
+    }
 
-    self.write_binary(&frame_buff).await?;
+    Ok(())
+  }
 
+  pub fn is_full(&self) -> bool {
+    self.pending >= WRITE_THRESHOLD
+  }
+
+  pub async fn flush(&mut self) -> Result<()> {
+    self.write_pending().await?;
+    self.inner.flush().await?;
     Ok(())
   }
 
   pub async fn write_binary<'a>(&'a mut self, buf: &'a [u8]) -> Result<()> {
+    self.write_pending().await?;
     self.inner.write_all(buf).await?;
     self.inner.flush().await?;
     Ok(())
   }
+
+  fn encode(&mut self, channel: ChannelId, mut frame: Frame) -> Result<()> {
+    if !self.interceptors.outbound(channel, &mut frame) {
+      return Ok(());
+    }
+
+    let event = OutgoingEvent::of(&frame);
+    let capture = self.wiretap.is_enabled().then(|| Capture::of(&frame, self.buf.len()));
+    let size = encode(channel, frame, &mut self.buf, &mut self.segments, &mut self.encoded_from)?;
+
+    // nothing of an envelope with an oversized frame is written, the broker would close the connection
+    if self.frame_max > 0 && size > self.frame_max as usize {
+      return Err(FrameTooLarge { size, frame_max: self.frame_max }.into());
+    }
+
+    if let Some(capture) = capture {
+      capture.record(&self.wiretap, channel, size, &self.buf);
+    }
+    self.pending += size;
+    self.written.push((channel, size, event));
+    Ok(())
+  }
+
+  async fn write_pending(&mut self) -> Result<()> {
+    if self.pending == 0 {
+      return Ok(());
+    }
+
+    self.segments.push(Segment::Encoded(self.encoded_from..self.buf.len()));
+    let written = write_segments(&mut self.inner, &self.buf, &self.segments).await;
+
+    self.segments.clear();
+    self.encoded_from = 0;
+    self.pending = 0;
+    // the pool drops a buffer grown too large by a burst
+    self.buffers.put(std::mem::take(&mut self.buf));
+    self.buf = self.buffers.take();
+
+    if written.is_ok() {
+      for (channel, size, event) in self.written.drain(..) {
+        self.metrics.frame_sent(channel, size);
+        if let Some(event) = event {
+          event.record(self.metrics.as_ref(), channel);
+        }
+      }
+    }
+    self.written.clear();
+    written
+  }
+}
+
+async fn write_segments(inner: &mut WriteStream, buf: &[u8], segments: &[Segment]) -> Result<()> {
+  let mut next = 0;
+  while next < segments.len() {
+    let mut slices = [IoSlice::new(&[]); MAX_IO_SLICES];
+    let mut count = 0;
+    while next < segments.len() && count < MAX_IO_SLICES {
+      let slice = match &segments[next] {
+        Segment::Encoded(range) => &buf[range.clone()],
+        Segment::Body(body) => &body[..],
+      };
+      next += 1;
+      if !slice.is_empty() {
+        slices[count] = IoSlice::new(slice);
+        count += 1;
+      }
+    }
+
+    let mut slices = &mut slices[..count];
+    while !slices.is_empty() {
+      let written = inner.write_vectored(slices).await?;
+      if written == 0 {
+        return Err(Error::closed("Failed to write. Connection closed"));
+      }
+
+      IoSlice::advance_slices(&mut slices, written);
+    }
+  }
+
+  Ok(())
+}
+
+// what the wire tap needs of a frame that is encoded away, bodies aren't copied into the encode buffer
+struct Capture {
+  info: FrameInfo,
+  frame_start: usize,
+  body: Option<Bytes>,
+}
+
+impl Capture {
+  fn of(frame: &Frame, frame_start: usize) -> Self {
+    let body = match frame {
+      Frame::ContentBody(body) => Some(body.0.clone()),
+      _ => None,
+    };
+    Self { info: FrameInfo::of(frame), frame_start, body }
+  }
+
+  fn record(self, wiretap: &WireTap, channel: ChannelId, size: usize, frame_buff: &[u8]) {
+    let payload = match &self.body {
+      Some(body) => &body[..],
+      None => &frame_buff[self.frame_start + FRAME_HEADER_SIZE..frame_buff.len() - FRAME_END_SIZE],
+    };
+    wiretap.record(Direction::Outbound, channel, self.info, size, payload);
+  }
+}
+
+// returns the size of the frame on the wire
+fn encode(channel: ChannelId, frame: Frame, frame_buff: &mut Vec<u8>, segments: &mut Vec<Segment>, encoded_from: &mut usize) -> Result<usize> {
+  match frame {
+    Frame::ContentBody(body) if body.0.len() > MAX_INLINE_BODY => {
+      let size = body.0.len();
+      frame_buff.write_byte(FRAME_BODY)?;
+      frame_buff.write_short(channel)?;
+      frame_buff.write_uint(size as u32)?;
+      segments.push(Segment::Encoded(*encoded_from..frame_buff.len()));
+      segments.push(Segment::Body(body.0));
+      *encoded_from = frame_buff.len();
+      frame_buff.write_byte(FRAME_END)?;
+      Ok(FRAME_HEADER_SIZE + size + FRAME_END_SIZE)
+    },
+    frame => Ok(encode_frame(channel, frame, frame_buff)?),
+  }
 }
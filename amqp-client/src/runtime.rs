@@ -0,0 +1,84 @@
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use anyhow::anyhow;
+use crate::{Error, Result};
+
+#[cfg(feature = "tokio-runtime")]
+mod tokio_runtime;
+#[cfg(feature = "tokio-runtime")]
+use self::tokio_runtime as backend;
+
+#[cfg(all(feature = "async-std-runtime", not(feature = "tokio-runtime")))]
+mod async_std_runtime;
+#[cfg(all(feature = "async-std-runtime", not(feature = "tokio-runtime")))]
+use self::async_std_runtime as backend;
+
+#[cfg(all(feature = "smol-runtime", not(any(feature = "tokio-runtime", feature = "async-std-runtime"))))]
+mod smol_runtime;
+#[cfg(all(feature = "smol-runtime", not(any(feature = "tokio-runtime", feature = "async-std-runtime"))))]
+use self::smol_runtime as backend;
+
+#[cfg(all(any(feature = "async-std-runtime", feature = "smol-runtime"), not(feature = "tokio-runtime")))]
+mod compat;
+
+#[cfg(not(any(feature = "tokio-runtime", feature = "async-std-runtime", feature = "smol-runtime")))]
+compile_error!("one of the tokio-runtime, async-std-runtime or smol-runtime features has to be enabled");
+
+pub use self::backend::TcpStream;
+pub(crate) use self::backend::{connect, sleep, split, BlockingRuntime};
+
+// a panic in the task comes out as an error once the handle is awaited,
+// dropping the handle leaves the task running
+pub struct JoinHandle<T> {
+  task: backend::Task<std::thread::Result<T>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+  type Output = Result<T>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    match backend::poll_task(&mut self.task, cx) {
+      Poll::Ready(Ok(Ok(output))) => Poll::Ready(Ok(output)),
+      Poll::Ready(Ok(Err(_))) => Poll::Ready(Err(Error::other(anyhow!("Task panicked")))),
+      Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+      Poll::Pending => Poll::Pending,
+    }
+  }
+}
+
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+  where F: Future + Send + 'static,
+        F::Output: Send + 'static
+{
+  JoinHandle {
+    task: backend::spawn(CatchUnwind(Box::pin(future))),
+  }
+}
+
+// None once the duration elapsed first
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+  tokio::select! {
+    biased;
+    output = future => Some(output),
+    _ = sleep(duration) => None,
+  }
+}
+
+// backends differ in what a panicking task does to its handle, catching it here makes them agree
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+  type Output = std::thread::Result<F::Output>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let future = self.0.as_mut();
+    match std::panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+      Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+      Ok(Poll::Pending) => Poll::Pending,
+      Err(panic) => Poll::Ready(Err(panic)),
+    }
+  }
+}
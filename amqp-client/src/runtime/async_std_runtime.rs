@@ -0,0 +1,49 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use crate::Result;
+use super::compat::Compat;
+
+pub use async_std::net::TcpStream;
+pub(crate) type ReadHalf = Compat<TcpStream>;
+pub(crate) type WriteHalf = Compat<TcpStream>;
+
+pub(crate) type Task<T> = async_std::task::JoinHandle<T>;
+
+// tasks run on the global executor, blocking the caller is all that's left to do
+pub(crate) struct BlockingRuntime;
+
+impl BlockingRuntime {
+  pub(crate) fn new() -> Result<Self> {
+    Ok(Self)
+  }
+
+  pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+    async_std::task::block_on(future)
+  }
+}
+
+pub(crate) fn spawn<F>(future: F) -> Task<F::Output>
+  where F: Future + Send + 'static,
+        F::Output: Send + 'static
+{
+  async_std::task::spawn(future)
+}
+
+pub(crate) fn poll_task<T>(task: &mut Task<T>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+  Pin::new(task).poll(cx).map(Ok)
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+  async_std::task::sleep(duration).await
+}
+
+pub(crate) async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+  Ok(TcpStream::connect((host, port)).await?)
+}
+
+// the stream is reference counted, both halves share the socket
+pub(crate) fn split(stream: TcpStream) -> (ReadHalf, WriteHalf) {
+  (Compat::new(stream.clone()), Compat::new(stream))
+}
@@ -0,0 +1,54 @@
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[cfg(feature = "async-std-runtime")]
+use async_std::io::{Read as FuturesRead, Write as FuturesWrite};
+#[cfg(not(feature = "async-std-runtime"))]
+use smol::io::{AsyncRead as FuturesRead, AsyncWrite as FuturesWrite};
+
+// futures-io streams of async-std and smol seen through the tokio io traits the frame reader and writer use
+pub(crate) struct Compat<S>(S);
+
+impl<S> Compat<S> {
+  pub(crate) fn new(stream: S) -> Self {
+    Self(stream)
+  }
+}
+
+impl<S: FuturesRead + Unpin> AsyncRead for Compat<S> {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    let unfilled = buf.initialize_unfilled();
+    match Pin::new(&mut self.0).poll_read(cx, unfilled) {
+      Poll::Ready(Ok(read)) => {
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+      },
+      Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+      Poll::Pending => Poll::Pending,
+    }
+  }
+}
+
+impl<S: FuturesWrite + Unpin> AsyncWrite for Compat<S> {
+  fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.0).poll_write(cx, buf)
+  }
+
+  fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+  }
+
+  fn is_write_vectored(&self) -> bool {
+    true
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.0).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.0).poll_close(cx)
+  }
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use crate::Result;
+use super::compat::Compat;
+
+pub use smol::net::TcpStream;
+pub(crate) type ReadHalf = Compat<TcpStream>;
+pub(crate) type WriteHalf = Compat<TcpStream>;
+
+// smol cancels a task once its handle is dropped, the other runtimes let it run
+pub(crate) struct Task<T>(Option<smol::Task<T>>);
+
+impl<T> Drop for Task<T> {
+  fn drop(&mut self) {
+    if let Some(task) = self.0.take() {
+      task.detach();
+    }
+  }
+}
+
+// tasks run on the global executor, blocking the caller is all that's left to do
+pub(crate) struct BlockingRuntime;
+
+impl BlockingRuntime {
+  pub(crate) fn new() -> Result<Self> {
+    Ok(Self)
+  }
+
+  pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+    smol::block_on(future)
+  }
+}
+
+pub(crate) fn spawn<F>(future: F) -> Task<F::Output>
+  where F: Future + Send + 'static,
+        F::Output: Send + 'static
+{
+  Task(Some(smol::spawn(future)))
+}
+
+pub(crate) fn poll_task<T>(task: &mut Task<T>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+  match task.0.as_mut() {
+    Some(task) => Pin::new(task).poll(cx).map(Ok),
+    None => Poll::Pending,
+  }
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+  smol::Timer::after(duration).await;
+}
+
+pub(crate) async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+  Ok(TcpStream::connect((host, port)).await?)
+}
+
+// the stream is reference counted, both halves share the socket
+pub(crate) fn split(stream: TcpStream) -> (ReadHalf, WriteHalf) {
+  (Compat::new(stream.clone()), Compat::new(stream))
+}
@@ -0,0 +1,51 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use crate::{Error, Result};
+
+pub use tokio::net::TcpStream;
+pub(crate) use tokio::net::tcp::{OwnedReadHalf as ReadHalf, OwnedWriteHalf as WriteHalf};
+
+pub(crate) type Task<T> = tokio::task::JoinHandle<T>;
+
+// the worker thread keeps heartbeats and deliveries going while the caller isn't blocked on the client
+pub(crate) struct BlockingRuntime(tokio::runtime::Runtime);
+
+impl BlockingRuntime {
+  pub(crate) fn new() -> Result<Self> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+      .worker_threads(1)
+      .enable_all()
+      .build()?;
+
+    Ok(Self(runtime))
+  }
+
+  pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+    self.0.block_on(future)
+  }
+}
+
+pub(crate) fn spawn<F>(future: F) -> Task<F::Output>
+  where F: Future + Send + 'static,
+        F::Output: Send + 'static
+{
+  tokio::spawn(future)
+}
+
+pub(crate) fn poll_task<T>(task: &mut Task<T>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+  Pin::new(task).poll(cx).map_err(Error::other)
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+  tokio::time::sleep(duration).await
+}
+
+pub(crate) async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+  Ok(TcpStream::connect((host, port)).await?)
+}
+
+pub(crate) fn split(stream: TcpStream) -> (ReadHalf, WriteHalf) {
+  stream.into_split()
+}
@@ -0,0 +1,46 @@
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::Context;
+use crate::protocol::message::BasicProperties;
+use crate::protocol::types::{PropTable, Property, ShortStr};
+
+// trace context travels in the message headers, e.g. traceparent and tracestate with the W3C propagator.
+// Which fields are written is up to the globally installed propagator
+pub(crate) fn inject(mut properties: BasicProperties) -> BasicProperties {
+  let headers = properties.headers.get_or_insert_with(PropTable::new);
+  global::get_text_map_propagator(|propagator| {
+    propagator.inject_context(&Context::current(), &mut HeaderInjector(headers));
+  });
+  properties
+}
+
+pub(crate) fn extract(properties: &BasicProperties) -> Context {
+  match &properties.headers {
+    Some(headers) => global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers))),
+    None => Context::new(),
+  }
+}
+
+struct HeaderInjector<'a>(&'a mut PropTable);
+
+impl Injector for HeaderInjector<'_> {
+  fn set(&mut self, key: &str, value: String) {
+    self.0.insert(key.into(), Property::LongStr(value.into()));
+  }
+}
+
+struct HeaderExtractor<'a>(&'a PropTable);
+
+impl Extractor for HeaderExtractor<'_> {
+  fn get(&self, key: &str) -> Option<&str> {
+    match self.0.get(&ShortStr::from(key))? {
+      Property::LongStr(value) => value.as_str(),
+      Property::ShortStr(value) => Some(value.as_str()),
+      _ => None,
+    }
+  }
+
+  fn keys(&self) -> Vec<&str> {
+    self.0.keys().map(|key| key.as_str()).collect()
+  }
+}
@@ -1,17 +1,70 @@
-use std::sync::atomic::{AtomicI16, Ordering};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::error::ChannelLimitReached;
+use crate::protocol::types::{ChannelId, Int};
+use crate::Result;
 
+// hands out channel ids in 1..=channel_max, reusing the lowest released id first
 pub struct IdAllocator {
-  prev_id: AtomicI16
+  channel_max: ChannelId,
+  prev_id: ChannelId,
+  released: BTreeSet<ChannelId>,
 }
 
 impl IdAllocator {
-  pub fn new() -> Self {
+  pub fn new(channel_max: ChannelId) -> Self {
     Self {
-      prev_id: AtomicI16::new(1)
+      channel_max,
+      prev_id: 0,
+      released: BTreeSet::new(),
     }
   }
 
-  pub fn allocate(&mut self) -> i16 {
-    self.prev_id.fetch_add(1, Ordering::Relaxed)
+  pub fn allocate(&mut self) -> Option<ChannelId> {
+    if let Some(id) = self.released.pop_first() {
+      return Some(id);
+    }
+
+    if self.prev_id >= self.channel_max {
+      return None;
+    }
+
+    self.prev_id += 1;
+    Some(self.prev_id)
+  }
+
+  pub fn channel_max(&self) -> ChannelId {
+    self.channel_max
+  }
+
+  pub fn release(&mut self, id: ChannelId) {
+    if id <= 0 || id > self.prev_id {
+      return;
+    }
+
+    if id == self.prev_id {
+      self.prev_id -= 1;
+      // keep the high water mark tight so released tail ids don't pile up
+      while self.released.remove(&self.prev_id) {
+        self.prev_id -= 1;
+      }
+    } else {
+      self.released.insert(id);
+    }
+  }
+}
+
+// fails fast instead of letting the broker close the connection for an out of range channel id
+pub fn allocate_channel_id(allocator: &Mutex<IdAllocator>) -> Result<ChannelId> {
+  let mut allocator = allocator.lock().unwrap();
+  match allocator.allocate() {
+    Some(id) => Ok(id),
+    None => Err(ChannelLimitReached { channel_max: allocator.channel_max() }.into())
   }
 }
+
+// millisecond arguments are encoded as signed 32 bit numbers
+pub fn duration_millis(duration: Duration) -> Int {
+  duration.as_millis().min(Int::MAX as u128) as Int
+}
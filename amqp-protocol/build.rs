@@ -0,0 +1,147 @@
+// generates the method definitions and spec constants from the RabbitMQ flavoured 0-9-1 spec
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use serde_json::Value;
+
+const SPEC: &str = "spec/amqp-rabbitmq-0.9.1.json";
+// class, method, argument kept out of Debug output
+const SENSITIVE: &[(&str, &str, &str)] = &[
+  ("connection", "start-ok", "response"),
+  ("connection", "secure-ok", "response"),
+];
+
+fn main() {
+  println!("cargo:rerun-if-changed={}", SPEC);
+  println!("cargo:rerun-if-changed=build.rs");
+
+  let spec: Value = serde_json::from_str(&fs::read_to_string(SPEC).unwrap()).unwrap();
+  let out_dir = env::var("OUT_DIR").unwrap();
+
+  fs::write(Path::new(&out_dir).join("methods.rs"), methods(&spec)).unwrap();
+  fs::write(Path::new(&out_dir).join("constants.rs"), constants(&spec)).unwrap();
+  fs::write(Path::new(&out_dir).join("reply_codes.rs"), reply_codes(&spec)).unwrap();
+}
+
+fn methods(spec: &Value) -> String {
+  let domains: HashMap<&str, &str> = spec["domains"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|domain| (domain[0].as_str().unwrap(), domain[1].as_str().unwrap()))
+    .collect();
+
+  let mut out = String::from("generate_protocol_methods! {\n");
+  for class in spec["classes"].as_array().unwrap() {
+    writeln!(out, "  {}({}) {{", camel_case(class["name"].as_str().unwrap()), class["id"]).unwrap();
+
+    for method in class["methods"].as_array().unwrap() {
+      let name = method["name"].as_str().unwrap();
+      writeln!(out, "    #[doc = \"`{}.{}`\"]", class["name"].as_str().unwrap(), name).unwrap();
+      writeln!(out, "    #[derive(Clone)]").unwrap();
+      write!(out, "    {}({}) {{ ", camel_case(name), method["id"]).unwrap();
+
+      for argument in method["arguments"].as_array().unwrap() {
+        let domain = argument.get("domain").or_else(|| argument.get("type")).unwrap().as_str().unwrap();
+        let ty = field_type(domains.get(domain).copied().unwrap_or(domain));
+        let argument_name = argument["name"].as_str().unwrap();
+        let flag = match SENSITIVE.contains(&(class["name"].as_str().unwrap(), name, argument_name)) {
+          true => " [sensitive]",
+          false => "",
+        };
+        let default = match argument.get("default-value") {
+          Some(Value::String(value)) => format!(" = {:?}.into()", value),
+          Some(Value::Object(_)) => " = PropTable::new()".into(),
+          Some(value) => format!(" = {}", value),
+          None => String::new(),
+        };
+        write!(out, "{}: {}{}{}, ", field_name(argument_name), ty, flag, default).unwrap();
+      }
+
+      out.push_str("}\n");
+    }
+
+    out.push_str("  }\n");
+  }
+  out.push_str("}\n");
+
+  out
+}
+
+fn constants(spec: &Value) -> String {
+  let mut out = String::new();
+
+  for constant in spec["constants"].as_array().unwrap() {
+    let name = constant["name"].as_str().unwrap();
+    // frame types and the end octet go into single bytes, reply codes into the reply_code fields
+    let ty = match name {
+      "FRAME-MIN-SIZE" => "Int",
+      "REPLY-SUCCESS" => "Short",
+      _ if constant.get("class").is_some() => "Short",
+      _ => "Byte",
+    };
+    writeln!(out, "pub const {}: {} = {};", name.replace('-', "_"), ty, constant["value"]).unwrap();
+  }
+
+  out
+}
+
+fn reply_codes(spec: &Value) -> String {
+  let mut out = String::from("generate_reply_codes! {\n");
+
+  for constant in spec["constants"].as_array().unwrap() {
+    let name = constant["name"].as_str().unwrap();
+    let class = match constant.get("class").and_then(Value::as_str) {
+      Some("soft-error") => "soft",
+      Some("hard-error") => "hard",
+      _ if name == "REPLY-SUCCESS" => "success",
+      _ => continue,
+    };
+    let variant = match name {
+      "REPLY-SUCCESS" => "Success".to_string(),
+      name => camel_case(&name.to_lowercase()),
+    };
+    writeln!(out, "  {}({}, {}, {:?}),", variant, constant["value"], class, name.replace('-', "_")).unwrap();
+  }
+  out.push_str("}\n");
+
+  out
+}
+
+fn field_type(domain: &str) -> &'static str {
+  match domain {
+    "bit" => "Bit",
+    "octet" => "Byte",
+    "short" => "Short",
+    "long" => "Int",
+    "longlong" => "Long",
+    "shortstr" => "ShortStr",
+    "longstr" => "LongStr",
+    "table" => "PropTable",
+    "timestamp" => "Timestamp",
+    _ => panic!("Unknown domain {}", domain),
+  }
+}
+
+fn field_name(name: &str) -> String {
+  match name {
+    "type" => "ty".into(),
+    "nowait" => "no_wait".into(),
+    name => name.replace('-', "_"),
+  }
+}
+
+fn camel_case(name: &str) -> String {
+  name
+    .split('-')
+    .map(|part| {
+      let mut chars = part.chars();
+      match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+      }
+    })
+    .collect()
+}
@@ -0,0 +1,266 @@
+use bytes::Bytes;
+use crate::dec::Decode;
+use crate::enc::{longstr_size, proptable_size, shortstr_size, Encode};
+use crate::types::{Bit, Byte, Int, Long, LongStr, PropTable, Short, ShortStr};
+use crate::{bail, Result};
+
+// consecutive bit arguments of a method share octets, lowest bit first,
+// any other argument type starts a new octet
+pub struct MethodReader {
+  buf: Bytes,
+  len: usize,
+  bits: Byte,
+  bit_pos: u8,
+}
+
+macro_rules! read_with_reset {
+  ($($name:ident -> $type:ty),+) => {
+    $(
+      pub fn $name(&mut self) -> Result<$type> {
+        self.bit_pos = 8;
+        self.buf.$name()
+      }
+    )+
+  }
+}
+
+impl MethodReader {
+  pub fn new(buf: Bytes) -> Self {
+    Self {
+      len: buf.len(),
+      buf,
+      bits: 0,
+      bit_pos: 8,
+    }
+  }
+
+  // bytes consumed so far, counted from the class id
+  pub fn offset(&self) -> usize {
+    self.len - self.buf.len()
+  }
+
+  // false once all arguments are read, including the bits of the last octet
+  pub fn has_remaining(&self) -> bool {
+    !self.buf.is_empty() || self.bit_pos < 8
+  }
+
+  pub fn read_bit(&mut self) -> Result<Bit> {
+    if self.bit_pos == 8 {
+      self.bits = self.buf.read_byte()?;
+      self.bit_pos = 0;
+    }
+
+    let bit = self.bits & (1 << self.bit_pos) != 0;
+    self.bit_pos += 1;
+    Ok(bit)
+  }
+
+  read_with_reset! {
+    read_byte -> Byte,
+    read_short -> Short,
+    read_int -> Int,
+    read_long -> Long,
+    read_shortstr -> ShortStr,
+    read_longstr -> LongStr,
+    read_proptable -> PropTable
+  }
+}
+
+// mirrors MethodWriter, adjacent bits share an octet
+#[derive(Default)]
+pub struct MethodSize {
+  size: usize,
+  bit_pos: u8,
+}
+
+macro_rules! size_with_flush {
+  ($($name:ident($type:ty) => $size:expr),+) => {
+    $(
+      pub fn $name(&mut self, val: &$type) {
+        self.flush_bits();
+        let size: fn(&$type) -> usize = $size;
+        self.size += size(val);
+      }
+    )+
+  }
+}
+
+impl MethodSize {
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  pub fn size_bit(&mut self, _val: &Bit) {
+    if self.bit_pos == 8 {
+      self.flush_bits();
+    }
+    self.bit_pos += 1;
+  }
+
+  size_with_flush! {
+    size_byte(Byte) => |_| 1,
+    size_short(Short) => |_| 2,
+    size_int(Int) => |_| 4,
+    size_long(Long) => |_| 8,
+    size_shortstr(ShortStr) => shortstr_size,
+    size_longstr(LongStr) => longstr_size,
+    size_proptable(PropTable) => proptable_size
+  }
+
+  pub fn finish(mut self) -> usize {
+    self.flush_bits();
+    self.size
+  }
+
+  fn flush_bits(&mut self) {
+    if self.bit_pos > 0 {
+      self.size += 1;
+      self.bit_pos = 0;
+    }
+  }
+}
+
+pub struct MethodWriter<'a> {
+  buf: &'a mut Vec<u8>,
+  bits: Byte,
+  bit_pos: u8,
+  omitted: bool,
+}
+
+macro_rules! write_with_flush {
+  ($($name:ident($type:ty)),+) => {
+    $(
+      pub fn $name(&mut self, val: $type) -> Result<()> {
+        self.check_not_omitted()?;
+        self.flush_bits()?;
+        self.buf.$name(val)
+      }
+    )+
+  }
+}
+
+impl <'a> MethodWriter<'a> {
+  pub fn new(buf: &'a mut Vec<u8>) -> Self {
+    Self {
+      buf,
+      bits: 0,
+      bit_pos: 0,
+      omitted: false,
+    }
+  }
+
+  // absent optional arguments can only be left off the end of a method
+  pub fn omit(&mut self) {
+    self.omitted = true;
+  }
+
+  pub fn write_bit(&mut self, val: Bit) -> Result<()> {
+    self.check_not_omitted()?;
+    if self.bit_pos == 8 {
+      self.flush_bits()?;
+    }
+
+    if val {
+      self.bits |= 1 << self.bit_pos;
+    }
+    self.bit_pos += 1;
+    Ok(())
+  }
+
+  write_with_flush! {
+    write_byte(Byte),
+    write_short(Short),
+    write_int(Int),
+    write_long(Long),
+    write_shortstr(ShortStr),
+    write_longstr(LongStr),
+    write_proptable(PropTable)
+  }
+
+  pub fn finish(mut self) -> Result<()> {
+    self.flush_bits()
+  }
+
+  fn check_not_omitted(&self) -> Result<()> {
+    if self.omitted {
+      bail!("Optional argument left out before a present one");
+    }
+
+    Ok(())
+  }
+
+  fn flush_bits(&mut self) -> Result<()> {
+    if self.bit_pos > 0 {
+      self.buf.write_byte(self.bits)?;
+      self.bits = 0;
+      self.bit_pos = 0;
+    }
+
+    Ok(())
+  }
+}
+
+// argument types the generated methods can carry
+pub trait MethodField: Sized {
+  fn read_from(reader: &mut MethodReader) -> Result<Self>;
+  fn write_to(self, writer: &mut MethodWriter) -> Result<()>;
+  fn size_of(&self, size: &mut MethodSize);
+}
+
+macro_rules! method_field {
+  ($($type:ty => $read:ident, $write:ident, $size:ident);+) => {
+    $(
+      impl MethodField for $type {
+        fn read_from(reader: &mut MethodReader) -> Result<Self> {
+          reader.$read()
+        }
+
+        fn write_to(self, writer: &mut MethodWriter) -> Result<()> {
+          writer.$write(self)
+        }
+
+        fn size_of(&self, size: &mut MethodSize) {
+          size.$size(self)
+        }
+      }
+    )+
+  }
+}
+
+method_field! {
+  Bit => read_bit, write_bit, size_bit;
+  Byte => read_byte, write_byte, size_byte;
+  Short => read_short, write_short, size_short;
+  Int => read_int, write_int, size_int;
+  Long => read_long, write_long, size_long;
+  ShortStr => read_shortstr, write_shortstr, size_shortstr;
+  LongStr => read_longstr, write_longstr, size_longstr;
+  PropTable => read_proptable, write_proptable, size_proptable
+}
+
+// trailing arguments older peers don't send, absent once the payload is exhausted
+impl <T: MethodField> MethodField for Option<T> {
+  fn read_from(reader: &mut MethodReader) -> Result<Self> {
+    if !reader.has_remaining() {
+      return Ok(None);
+    }
+
+    T::read_from(reader).map(Some)
+  }
+
+  fn write_to(self, writer: &mut MethodWriter) -> Result<()> {
+    match self {
+      Some(val) => val.write_to(writer),
+      None => {
+        writer.omit();
+        Ok(())
+      }
+    }
+  }
+
+  fn size_of(&self, size: &mut MethodSize) {
+    if let Some(val) = self {
+      val.size_of(size);
+    }
+  }
+}
@@ -0,0 +1,187 @@
+use anyhow::Context;
+use bytes::{Buf, Bytes, BytesMut};
+use crate::dec::Decode;
+use crate::enc::Encode;
+use crate::error::{FrameTooLarge, MalformedFrame};
+use crate::frame::{ContentBody, ContentHeader, Frame};
+use crate::spec::{FRAME_BODY, FRAME_END, FRAME_HEADER, FRAME_HEARTBEAT, FRAME_METHOD};
+use crate::types::{ChannelId, Int};
+use crate::Result;
+
+pub const FRAME_HEADER_SIZE: usize = 7;
+pub const FRAME_END_SIZE: usize = 1;
+
+#[derive(Debug, Clone, Copy)]
+enum DecodeState {
+  Header,
+  Payload { frame_type: u8, channel: ChannelId, size: usize },
+}
+
+// resumable frame parser, bytes can be fed in chunks of any size,
+// the header is validated as soon as it's buffered so a bogus size can't make the decoder buffer gigabytes
+pub struct FrameDecoder {
+  buf: BytesMut,
+  state: DecodeState,
+  frame_max: Int,
+  last_frame_size: usize,
+  last_payload: Bytes,
+}
+
+impl FrameDecoder {
+  pub fn new(frame_max: Int) -> Self {
+    Self::with_capacity(frame_max, 128 * 1024)
+  }
+
+  pub fn with_capacity(frame_max: Int, capacity: usize) -> Self {
+    Self {
+      buf: BytesMut::with_capacity(capacity),
+      state: DecodeState::Header,
+      frame_max,
+      last_frame_size: 0,
+      last_payload: Bytes::new(),
+    }
+  }
+
+  pub fn set_frame_max(&mut self, frame_max: Int) {
+    self.frame_max = frame_max;
+  }
+
+  pub fn push(&mut self, chunk: &[u8]) {
+    self.buf.extend_from_slice(chunk);
+  }
+
+  // lets transports read straight into the decoder
+  pub fn buffer_mut(&mut self) -> &mut BytesMut {
+    &mut self.buf
+  }
+
+  // bytes still needed before the current frame can be decoded
+  pub fn remaining(&self) -> usize {
+    let needed = match self.state {
+      DecodeState::Header => FRAME_HEADER_SIZE,
+      DecodeState::Payload { size, .. } => size + FRAME_END_SIZE,
+    };
+    needed.saturating_sub(self.buf.len())
+  }
+
+  // size on the wire of the frame decode returned last
+  pub fn last_frame_size(&self) -> usize {
+    self.last_frame_size
+  }
+
+  // payload of the frame decode returned last, shares the memory of the decoded frame
+  pub fn last_payload(&self) -> &Bytes {
+    &self.last_payload
+  }
+
+  pub fn decode(&mut self) -> Result<Option<(ChannelId, Frame)>> {
+    loop {
+      match self.state {
+        DecodeState::Header => {
+          if self.buf.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+          }
+
+          self.state = self.decode_header()?;
+        },
+        DecodeState::Payload { frame_type, channel, size } => {
+          if self.buf.len() < size + FRAME_END_SIZE {
+            // the payload lands in place instead of growing the buffer read by read
+            self.buf.reserve(size + FRAME_END_SIZE - self.buf.len());
+            return Ok(None);
+          }
+
+          self.state = DecodeState::Header;
+          self.last_frame_size = FRAME_HEADER_SIZE + size + FRAME_END_SIZE;
+          let body = self.buf.split_to(size).freeze();
+          self.last_payload = body.clone();
+          return decode_payload(frame_type, channel, body, &mut self.buf).map(Some);
+        },
+      }
+    }
+  }
+
+  fn decode_header(&mut self) -> Result<DecodeState> {
+    let frame_type = self.buf.get_u8();
+    let channel = self.buf.get_i16();
+    let size = self.buf.get_u32() as usize;
+
+    let malformed = |reason| MalformedFrame { frame_type, channel, reason };
+    match frame_type {
+      FRAME_METHOD | FRAME_HEADER | FRAME_BODY => {},
+      FRAME_HEARTBEAT if channel != 0 => return Err(malformed("heartbeat on a non-zero channel").into()),
+      FRAME_HEARTBEAT if size != 0 => return Err(malformed("heartbeat with a payload").into()),
+      FRAME_HEARTBEAT => {},
+      _ => return Err(malformed("unknown frame type").into()),
+    }
+
+    // header + body_size + frame_end_byte
+    let frame_size = FRAME_HEADER_SIZE + size + FRAME_END_SIZE;
+    if self.frame_max > 0 && frame_size > self.frame_max as usize {
+      return Err(FrameTooLarge { size: frame_size, frame_max: self.frame_max }.into());
+    }
+
+    Ok(DecodeState::Payload { frame_type, channel, size })
+  }
+}
+
+// payloads share the buffer memory
+fn decode_payload(frame_type: u8, chan: ChannelId, body: Bytes, buf: &mut BytesMut) -> Result<(ChannelId, Frame)> {
+  let size = body.len();
+  if buf.get_u8() != FRAME_END {
+    return Err(MalformedFrame { frame_type, channel: chan, reason: "missing frame end octet" }.into());
+  }
+
+  let frame = match frame_type {
+    FRAME_METHOD => {
+      let mut meta = body.clone();
+      let class_id = meta.read_short()?;
+      let method_id = meta.read_short()?;
+
+      Frame::method(class_id, method_id, body)
+        .with_context(|| format!("Malformed method frame of {} bytes on channel {}", size, chan))?
+    },
+    FRAME_HEADER => {
+      let header = ContentHeader::try_from(body)
+        .with_context(|| format!("Malformed content header of {} bytes on channel {}", size, chan))?;
+
+      Frame::ContentHeader(header)
+    }
+    FRAME_BODY => {
+      Frame::ContentBody(ContentBody(body))
+    }
+    _ => {
+      Frame::Heartbeat
+    },
+  };
+
+  Ok((chan, frame))
+}
+
+pub fn frame_type(frame: &Frame) -> u8 {
+  match frame {
+    Frame::ContentHeader(..) => FRAME_HEADER,
+    Frame::ContentBody(..) => FRAME_BODY,
+    Frame::Heartbeat => FRAME_HEARTBEAT,
+    _ => FRAME_METHOD,
+  }
+}
+
+// appends the frame and returns its size on the wire, on error the buffer is left as it was
+pub fn encode_frame(channel: ChannelId, frame: Frame, buf: &mut Vec<u8>) -> Result<usize> {
+  let size = frame.encoded_size();
+  let frame_start = buf.len();
+  buf.reserve(FRAME_HEADER_SIZE + size + FRAME_END_SIZE);
+  buf.write_byte(frame_type(&frame))?;
+  buf.write_short(channel)?;
+  buf.write_uint(size as u32)?;
+
+  if let Err(err) = frame.write_raw_repr(buf) {
+    buf.truncate(frame_start);
+    return Err(err);
+  }
+  debug_assert_eq!(FRAME_HEADER_SIZE + size, buf.len() - frame_start);
+  buf.write_byte(FRAME_END)?;
+
+  Ok(buf.len() - frame_start)
+}
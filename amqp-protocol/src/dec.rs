@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::time::{Duration, UNIX_EPOCH};
+use byteorder::{BigEndian, ReadBytesExt};
+use bytes::{Buf, Bytes};
+use tracing::{debug};
+use crate::types::{Decimal, LongStr, Property, ShortStr, Timestamp};
+use crate::{bail, Result};
+
+pub trait Decode {
+  fn read_bool(&mut self) -> Result<bool>;
+  fn read_byte(&mut self) -> Result<u8>;
+  fn read_short(&mut self) -> Result<i16>;
+  fn read_ushort(&mut self) -> Result<u16>;
+  fn read_int(&mut self) -> Result<i32>;
+  fn read_uint(&mut self) -> Result<u32>;
+  fn read_long(&mut self) -> Result<i64>;
+  fn read_ulong(&mut self) -> Result<u64>;
+  fn read_float(&mut self) -> Result<f32>;
+  fn read_double(&mut self) -> Result<f64>;
+  fn read_decimal(&mut self) -> Result<Decimal>;
+  fn read_timestamp(&mut self) -> Result<Timestamp>;
+  fn read_shortstr(&mut self) -> Result<ShortStr>;
+  fn read_longstr(&mut self) -> Result<LongStr>;
+  fn read_field_value_pair(&mut self) -> Result<(ShortStr, Property)>;
+  fn read_field_value(&mut self) -> Result<Property>;
+  fn read_field_value_type(&mut self, ch: char) -> Result<Property>;
+  fn read_proptable(&mut self) -> Result<HashMap<ShortStr, Property>>;
+  fn read_array(&mut self) -> Result<Vec<Property>>;
+}
+
+// decodes from the frame payload in place, strings, byte arrays and the nested tables in them are
+// slices of the payload. Lengths read off the wire are checked against the bytes left first
+impl Decode for Bytes {
+  fn read_bool(&mut self) -> Result<bool> {
+    Ok(self.read_byte()? != 0)
+  }
+  fn read_byte(&mut self) -> Result<u8> {
+    read_fixed(self, |buf| buf.read_u8())
+  }
+
+  fn read_short(&mut self) -> Result<i16> {
+    read_fixed(self, |buf| buf.read_i16::<BigEndian>())
+  }
+
+  fn read_ushort(&mut self) -> Result<u16> {
+    read_fixed(self, |buf| buf.read_u16::<BigEndian>())
+  }
+
+  fn read_int(&mut self) -> Result<i32> {
+    read_fixed(self, |buf| buf.read_i32::<BigEndian>())
+  }
+
+  fn read_uint(&mut self) -> Result<u32> {
+    read_fixed(self, |buf| buf.read_u32::<BigEndian>())
+  }
+
+  fn read_long(&mut self) -> Result<i64> {
+    read_fixed(self, |buf| buf.read_i64::<BigEndian>())
+  }
+
+  fn read_ulong(&mut self) -> Result<u64> {
+    read_fixed(self, |buf| buf.read_u64::<BigEndian>())
+  }
+
+  fn read_float(&mut self) -> Result<f32> {
+    read_fixed(self, |buf| buf.read_f32::<BigEndian>())
+  }
+
+  fn read_double(&mut self) -> Result<f64> {
+    read_fixed(self, |buf| buf.read_f64::<BigEndian>())
+  }
+
+  fn read_decimal(&mut self) -> Result<Decimal> {
+    let scale = self.read_byte()?;
+    let value = self.read_int()?;
+    Ok(Decimal::new(scale, value))
+  }
+
+  fn read_timestamp(&mut self) -> Result<Timestamp> {
+    let secs = self.read_ulong()?;
+    match UNIX_EPOCH.checked_add(Duration::from_secs(secs)) {
+      Some(timestamp) => Ok(timestamp),
+      None => bail!("Timestamp of {} seconds is out of range", secs),
+    }
+  }
+
+  fn read_shortstr(&mut self) -> Result<ShortStr> {
+    let size = self.read_byte()?;
+    let buff = split(self, size as usize, "Short string")?;
+    Ok(ShortStr::from_utf8(buff)?)
+  }
+
+  fn read_longstr(&mut self) -> Result<LongStr> {
+    let size = self.read_uint()?;
+    Ok(LongStr(split(self, size as usize, "Long string")?))
+  }
+
+  fn read_field_value_pair(&mut self) -> Result<(ShortStr, Property)> {
+    let key = self.read_shortstr()?;
+    let value = self.read_field_value()?;
+    Ok((key, value))
+  }
+
+  fn read_field_value(&mut self) -> Result<Property> {
+    let value_type = self.read_byte()? as char;
+    self.read_field_value_type(value_type)
+  }
+
+  fn read_field_value_type(&mut self, ch: char) -> Result<Property> {
+    let value = match ch {
+      't' => Property::Bool(self.read_bool()?),
+      'b' => Property::SignedByte(self.read_byte()? as i8),
+      'B' => Property::Byte(self.read_byte()?),
+      's' => Property::Short(self.read_short()?),
+      'u' => Property::UShort(self.read_ushort()?),
+      'I' => Property::Int(self.read_int()?),
+      'i' => Property::UInt(self.read_uint()?),
+      'l' => Property::Long(self.read_long()?),
+      // unsigned long of the original spec, never sent by RabbitMQ
+      'L' => Property::ULong(self.read_ulong()?),
+      'f' => Property::Float(self.read_float()?),
+      'd' => Property::Double(self.read_double()?),
+      'D' => Property::Decimal(self.read_decimal()?),
+      'T' => Property::Timestamp(self.read_timestamp()?),
+      'S' => Property::LongStr(self.read_longstr()?),
+      'F' => Property::Table(self.read_proptable()?),
+      'A' => Property::Array(self.read_array()?),
+      'x' => {
+        let size = self.read_uint()?;
+        Property::Bytes(split(self, size as usize, "Byte array")?)
+      },
+      'V' => Property::Void,
+      _ => {
+        bail!("Unsupported field value type: {}", ch);
+      }
+    };
+
+    Ok(value)
+  }
+
+  fn read_proptable(&mut self) -> Result<HashMap<ShortStr, Property>> {
+    let mut table = HashMap::new();
+    let table_size = self.read_uint()?;
+    debug!("Table size {}", table_size);
+    let mut fields = split(self, table_size as usize, "Table")?;
+
+    while !fields.is_empty() {
+      let pair = fields.read_field_value_pair()?;
+      debug!("Table pair {:?}", &pair);
+      table.insert(pair.0, pair.1);
+    }
+
+    Ok(table)
+  }
+
+  fn read_array(&mut self) -> Result<Vec<Property>> {
+    let mut values = vec![];
+    let array_size = self.read_uint()?;
+    let mut items = split(self, array_size as usize, "Array")?;
+
+    while !items.is_empty() {
+      values.push(items.read_field_value()?);
+    }
+
+    Ok(values)
+  }
+}
+
+// the next size bytes of the buffer sharing its memory, the buffer moves past them
+fn split(buf: &mut Bytes, size: usize, field: &str) -> Result<Bytes> {
+  if size > buf.len() {
+    bail!("{} of {} bytes exceeds the {} bytes left in the frame", field, size, buf.len());
+  }
+
+  Ok(buf.split_to(size))
+}
+
+// numbers are read off a view of the buffer, which moves past them once they're complete
+fn read_fixed<T>(buf: &mut Bytes, read: impl FnOnce(&mut &[u8]) -> std::io::Result<T>) -> Result<T> {
+  let mut view = &buf[..];
+  let value = read(&mut view)?;
+  buf.advance(buf.len() - view.len());
+  Ok(value)
+}
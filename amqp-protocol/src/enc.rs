@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+use byteorder::{BigEndian, WriteBytesExt};
+use crate::types::{Decimal, LongStr, Property, ShortStr, Timestamp};
+use crate::{bail, Result};
+
+pub trait Encode {
+  fn write_bool(&mut self, val: bool) -> Result<()>;
+  fn write_byte(&mut self, val: u8) -> Result<()>;
+  fn write_short(&mut self, val: i16) -> Result<()>;
+  fn write_ushort(&mut self, val: u16) -> Result<()>;
+  fn write_int(&mut self, val: i32) -> Result<()>;
+  fn write_uint(&mut self, val: u32) -> Result<()>;
+  fn write_long(&mut self, val: i64) -> Result<()>;
+  fn write_ulong(&mut self, val: u64) -> Result<()>;
+  fn write_float(&mut self, val: f32) -> Result<()>;
+  fn write_double(&mut self, val: f64) -> Result<()>;
+  fn write_decimal(&mut self, val: Decimal) -> Result<()>;
+  fn write_timestamp(&mut self, val: Timestamp) -> Result<()>;
+  fn write_shortstr(&mut self, val: ShortStr) -> Result<()>;
+  fn write_longstr(&mut self, val: LongStr) -> Result<()>;
+  fn write_field_value_pair(&mut self, val: (ShortStr, Property)) -> Result<()>;
+  fn write_field_value(&mut self, val: Property) -> Result<()>;
+  fn write_proptable(&mut self, val: HashMap<ShortStr, Property>) -> Result<()>;
+  fn write_array(&mut self, val: Vec<Property>) -> Result<()>;
+}
+
+impl <T: std::io::Write + ?Sized> Encode for T {
+  fn write_bool(&mut self, val: bool) -> Result<()> {
+    self.write_u8(u8::from(val))?;
+    Ok(())
+  }
+
+  fn write_byte(&mut self, val: u8) -> Result<()> {
+    self.write_u8(val)?;
+    Ok(())
+  }
+
+  fn write_short(&mut self, val: i16) -> Result<()> {
+    self.write_i16::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_ushort(&mut self, val: u16) -> Result<()> {
+    self.write_u16::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_int(&mut self, val: i32) -> Result<()> {
+    self.write_i32::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_uint(&mut self, val: u32) -> Result<()> {
+    self.write_u32::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_long(&mut self, val: i64) -> Result<()> {
+    self.write_i64::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_ulong(&mut self, val: u64) -> Result<()> {
+    self.write_u64::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_float(&mut self, val: f32) -> Result<()> {
+    self.write_f32::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_double(&mut self, val: f64) -> Result<()> {
+    self.write_f64::<BigEndian>(val)?;
+    Ok(())
+  }
+
+  fn write_decimal(&mut self, val: Decimal) -> Result<()> {
+    self.write_byte(val.scale)?;
+    Encode::write_int(self, val.value)
+  }
+
+  fn write_timestamp(&mut self, val: Timestamp) -> Result<()> {
+    let secs = match val.duration_since(UNIX_EPOCH) {
+      Ok(elapsed) => elapsed.as_secs(),
+      Err(_) => bail!("Timestamp {:?} is before the unix epoch", val)
+    };
+    self.write_ulong(secs)
+  }
+
+  fn write_shortstr(&mut self, val: ShortStr) -> Result<()> {
+    val.validate()?;
+    self.write_byte(val.len() as u8)?;
+    self.write_all(val.as_bytes())?;
+    Ok(())
+  }
+
+  fn write_longstr(&mut self, val: LongStr) -> Result<()> {
+    let str_bytes = val.0;
+    Encode::write_uint(self, str_bytes.len() as u32)?;
+    self.write_all(&str_bytes)?;
+    Ok(())
+  }
+
+  fn write_field_value_pair(&mut self, val: (ShortStr, Property)) -> Result<()> {
+    self.write_shortstr(val.0)?;
+    self.write_field_value(val.1)?;
+    Ok(())
+  }
+
+  fn write_field_value(&mut self, val: Property) -> Result<()> {
+    match val {
+      Property::Bool(v) => {
+        self.write_byte(b't')?;
+        self.write_bool(v)?;
+      },
+      Property::SignedByte(v) => {
+        self.write_byte(b'b')?;
+        self.write_i8(v)?;
+      },
+      Property::Byte(v) => {
+        self.write_byte(b'B')?;
+        self.write_byte(v)?;
+      },
+      Property::Short(v) => {
+        self.write_byte(b's')?;
+        self.write_short(v)?;
+      },
+      Property::UShort(v) => {
+        self.write_byte(b'u')?;
+        self.write_ushort(v)?;
+      }
+      Property::Int(v) => {
+        self.write_byte(b'I')?;
+        Encode::write_int(self, v)?;
+      }
+      Property::UInt(v) => {
+        self.write_byte(b'i')?;
+        Encode::write_uint(self, v)?;
+      }
+      Property::Long(v) => {
+        self.write_byte(b'l')?;
+        self.write_long(v)?;
+      }
+      Property::ULong(v) => {
+        // the broker knows signed 64 bit integers only
+        if v > i64::MAX as u64 {
+          bail!("Unsigned long {} doesn't fit into a signed long field", v);
+        }
+        self.write_byte(b'l')?;
+        self.write_long(v as i64)?;
+      }
+      Property::Float(v) => {
+        self.write_byte(b'f')?;
+        self.write_float(v)?;
+      }
+      Property::Double(v) => {
+        self.write_byte(b'd')?;
+        self.write_double(v)?;
+      }
+      Property::Decimal(v) => {
+        self.write_byte(b'D')?;
+        self.write_decimal(v)?;
+      }
+      Property::Timestamp(v) => {
+        self.write_byte(b'T')?;
+        self.write_timestamp(v)?;
+      }
+      Property::ShortStr(v) => {
+        // 's' is a short int for the broker, short strings go as long ones
+        self.write_byte(b'S')?;
+        Encode::write_uint(self, v.len() as u32)?;
+        self.write_all(v.as_bytes())?;
+      }
+      Property::LongStr(v) => {
+        self.write_byte(b'S')?;
+        self.write_longstr(v)?;
+      }
+      Property::Table(v) => {
+        self.write_byte(b'F')?;
+        self.write_proptable(v)?;
+      }
+      Property::Array(v) => {
+        self.write_byte(b'A')?;
+        self.write_array(v)?;
+      }
+      Property::Bytes(v) => {
+        self.write_byte(b'x')?;
+        Encode::write_uint(self, v.len() as u32)?;
+        self.write_all(&v)?;
+      }
+      Property::Void => {
+        self.write_byte(b'V')?;
+      }
+    }
+
+    Ok(())
+  }
+
+  fn write_proptable(&mut self, val: HashMap<ShortStr, Property>) -> Result<()> {
+    Encode::write_uint(self, (proptable_size(&val) - 4) as u32)?;
+
+    for pair in val {
+      self.write_field_value_pair(pair)?;
+    }
+
+    Ok(())
+  }
+
+  fn write_array(&mut self, val: Vec<Property>) -> Result<()> {
+    Encode::write_uint(self, (array_size(&val) - 4) as u32)?;
+
+    for value in val {
+      self.write_field_value(value)?;
+    }
+
+    Ok(())
+  }
+}
+
+// sizes on the wire, including the length prefix
+pub fn shortstr_size(val: &ShortStr) -> usize {
+  1 + val.len()
+}
+
+pub fn longstr_size(val: &LongStr) -> usize {
+  4 + val.0.len()
+}
+
+pub fn proptable_size(val: &HashMap<ShortStr, Property>) -> usize {
+  4 + val.iter()
+    .map(|(key, value)| shortstr_size(key) + field_value_size(value))
+    .sum::<usize>()
+}
+
+pub fn array_size(val: &[Property]) -> usize {
+  4 + val.iter().map(field_value_size).sum::<usize>()
+}
+
+// including the type tag
+pub fn field_value_size(val: &Property) -> usize {
+  1 + match val {
+    Property::Bool(_) | Property::SignedByte(_) | Property::Byte(_) => 1,
+    Property::Short(_) | Property::UShort(_) => 2,
+    Property::Int(_) | Property::UInt(_) | Property::Float(_) => 4,
+    Property::Long(_) | Property::ULong(_) | Property::Double(_) | Property::Timestamp(_) => 8,
+    Property::Decimal(_) => 5,
+    Property::ShortStr(v) => 4 + v.len(),
+    Property::LongStr(v) => longstr_size(v),
+    Property::Table(v) => proptable_size(v),
+    Property::Array(v) => array_size(v),
+    Property::Bytes(v) => 4 + v.len(),
+    Property::Void => 0,
+  }
+}
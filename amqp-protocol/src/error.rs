@@ -0,0 +1,87 @@
+use std::fmt::{Display, Formatter};
+use crate::types::{ChannelId, Int, Short};
+
+#[derive(Debug, Clone)]
+pub struct FrameTooLarge {
+  pub size: usize,
+  pub frame_max: Int,
+}
+
+impl Display for FrameTooLarge {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Frame of {} bytes exceeds the negotiated frame max of {}", self.size, self.frame_max)
+  }
+}
+
+impl std::error::Error for FrameTooLarge {}
+
+#[derive(Debug, Clone)]
+pub struct DecodeError {
+  pub class_id: Short,
+  pub method_id: Short,
+  pub field: &'static str,
+  pub expected: &'static str,
+  // from the start of the method payload
+  pub offset: usize,
+  pub reason: String,
+}
+
+impl Display for DecodeError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Failed to decode {} ({}) of method {}.{} at byte {}: {}",
+      self.field, self.expected, self.class_id, self.method_id, self.offset, self.reason
+    )
+  }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidShortStr {
+  TooLong(usize),
+  ContainsNul,
+}
+
+impl Display for InvalidShortStr {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      InvalidShortStr::TooLong(len) => write!(f, "Short string of {} bytes exceeds the limit of 255", len),
+      InvalidShortStr::ContainsNul => write!(f, "Short string must not contain NUL"),
+    }
+  }
+}
+
+impl std::error::Error for InvalidShortStr {}
+
+// the stream can't be trusted past a malformed frame, the connection has to be closed with a frame error
+#[derive(Debug, Clone)]
+pub struct MalformedFrame {
+  pub frame_type: u8,
+  pub channel: ChannelId,
+  pub reason: &'static str,
+}
+
+impl Display for MalformedFrame {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Malformed frame of type {} on channel {}: {}", self.frame_type, self.channel, self.reason)
+  }
+}
+
+impl std::error::Error for MalformedFrame {}
+
+// content frames out of order, e.g. a body without a header
+#[derive(Debug, Clone)]
+pub struct UnexpectedFrame {
+  pub channel: ChannelId,
+  pub reason: &'static str,
+}
+
+impl Display for UnexpectedFrame {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Unexpected frame on channel {}: {}", self.channel, self.reason)
+  }
+}
+
+impl std::error::Error for UnexpectedFrame {}
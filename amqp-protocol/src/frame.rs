@@ -0,0 +1,193 @@
+use crate::{bail, debug_field, field_default, generate_protocol_methods, Result};
+use crate::error::DecodeError;
+
+use bytes::{Bytes, BytesMut};
+use paste::paste;
+use crate::bits::{MethodField, MethodReader, MethodSize, MethodWriter};
+use crate::dec::Decode;
+use crate::enc::Encode;
+use crate::properties::BasicProperties;
+use crate::types::{Bit, ChannelId, Long};
+use crate::types::{Byte, PropTable, LongStr, ShortStr, Short, Int};
+
+// generated by build.rs from spec/amqp-rabbitmq-0.9.1.json
+include!(concat!(env!("OUT_DIR"), "/methods.rs"));
+
+// stands in for credentials in Debug output
+pub struct Redacted;
+
+impl std::fmt::Debug for Redacted {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("<redacted>")
+  }
+}
+
+impl BasicNack {
+  pub fn new(delivery_tag: Long, multiple: bool, requeue: bool) -> Self {
+    Self { delivery_tag, multiple, requeue }
+  }
+}
+
+// method the crate doesn't model, arguments are encoded by the caller
+#[derive(Debug, Clone)]
+pub struct RawMethod {
+  pub class_id: Short,
+  pub method_id: Short,
+  pub arguments: Vec<u8>,
+}
+
+impl RawMethod {
+  pub fn new(class_id: Short, method_id: Short, arguments: Vec<u8>) -> Self {
+    Self {
+      class_id,
+      method_id,
+      arguments
+    }
+  }
+
+  pub fn write_raw_repr(self, buf: &mut Vec<u8>) -> Result<()> {
+    buf.write_short(self.class_id)?;
+    buf.write_short(self.method_id)?;
+    buf.extend_from_slice(&self.arguments);
+    Ok(())
+  }
+
+  pub fn into_frame(self) -> Frame {
+    Frame::RawMethod(self)
+  }
+}
+
+impl TryFrom<Bytes> for RawMethod {
+  type Error = anyhow::Error;
+
+  fn try_from(mut buf: Bytes) -> Result<Self> {
+    let class_id = buf.read_short()?;
+    let method_id = buf.read_short()?;
+
+    Ok(Self::new(class_id, method_id, buf.to_vec()))
+  }
+}
+
+#[derive(Debug)]
+pub struct ContentHeader {
+  pub class_id: Short,
+  pub body_len: Long,
+  pub prop_list: BasicProperties,
+}
+
+impl TryFrom<Bytes> for ContentHeader {
+  type Error = anyhow::Error;
+
+  fn try_from(mut buf: Bytes) -> Result<Self> {
+    let class_id = buf.read_short()?;
+    // weight, unused
+    buf.read_short()?;
+    let body_len = buf.read_long()?;
+
+    Ok(Self {
+      class_id,
+      body_len,
+      prop_list: BasicProperties::decode(buf)?
+    })
+  }
+}
+
+impl ContentHeader {
+
+  pub fn into_raw_repr(self) -> Result<Vec<u8>> {
+    let mut buf = vec![];
+    self.write_raw_repr(&mut buf)?;
+    Ok(buf)
+  }
+
+  pub fn encoded_size(&self) -> usize {
+    12 + self.prop_list.encoded_size()
+  }
+
+  pub fn write_raw_repr(self, buf: &mut Vec<u8>) -> Result<()> {
+    buf.write_short(self.class_id)?;
+    buf.write_short(0)?;
+    buf.write_long(self.body_len as Long)?;
+    self.prop_list.encode(buf)
+  }
+
+  pub fn into_frame(self) -> Frame {
+    Frame::ContentHeader(self)
+  }
+}
+
+#[derive(Debug)]
+pub struct ContentBody(pub Bytes);
+
+impl ContentBody {
+  pub fn from_raw_repr(buf: &[u8]) -> Self {
+    Self(Bytes::copy_from_slice(buf))
+  }
+
+  pub fn into_raw_repr(self) -> Vec<u8> {
+    self.0.into()
+  }
+
+  pub fn into_frame(self) -> Frame {
+    Frame::ContentBody(self)
+  }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug)]
+pub enum ContentFrame {
+  WithMethod(Frame),
+  WithContentHeader((Frame, ContentHeader)),
+  // bodies split over several frames are joined into a buffer sized for the whole content
+  WithPartialBody((Frame, ContentHeader, BytesMut)),
+  WithBody((Frame, ContentHeader, ContentBody))
+}
+
+
+impl ContentFrame {
+  pub fn with_content_header(self, header: ContentHeader) -> Result<Self> {
+    match self {
+      ContentFrame::WithMethod(frame) => Ok(Self::WithContentHeader((frame, header))),
+      _ => bail!("Content header after the content header"),
+    }
+  }
+
+  pub fn with_body(self, body: ContentBody) -> Result<Self> {
+    let content = match self {
+      ContentFrame::WithContentHeader((frame, header)) if header.body_len <= body.0.len() as Long => {
+        Self::WithBody((frame, header, body))
+      },
+      ContentFrame::WithContentHeader((frame, header)) => {
+        let mut buf = BytesMut::with_capacity(header.body_len as usize);
+        buf.extend_from_slice(&body.0);
+        Self::WithPartialBody((frame, header, buf))
+      },
+      ContentFrame::WithPartialBody((frame, header, mut buf)) => {
+        buf.extend_from_slice(&body.0);
+
+        if header.body_len <= buf.len() as Long {
+          Self::WithBody((frame, header, ContentBody(buf.freeze())))
+        } else {
+          Self::WithPartialBody((frame, header, buf))
+        }
+      },
+      ContentFrame::WithMethod(..) => bail!("Content body without a content header"),
+      ContentFrame::WithBody(..) => bail!("Content body after the complete content"),
+    };
+
+    Ok(content)
+  }
+
+  pub fn is_complete(&self) -> bool {
+    match self {
+      ContentFrame::WithBody((_,header,body)) => {
+        header.body_len <= body.0.len() as Long
+      }
+      _ => {
+        false
+      }
+    }
+  }
+}
+
+pub type FrameEnvelope = (ChannelId, Frame);
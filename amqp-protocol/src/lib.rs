@@ -0,0 +1,12 @@
+mod macros;
+pub mod types;
+pub mod enc;
+pub mod dec;
+pub mod bits;
+pub mod frame;
+pub mod properties;
+pub mod spec;
+pub mod reply_code;
+pub mod error;
+pub mod codec;
+pub use anyhow::{Result, Error, bail};
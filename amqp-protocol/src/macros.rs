@@ -0,0 +1,436 @@
+// Debug and a builder are generated, fields marked with a trailing [sensitive] show up redacted,
+// fields with a default value can be left out of the builder
+#[macro_export]
+macro_rules! generate_protocol_methods {
+  (
+    $(
+      $class:ident($class_id:literal) {
+        $(
+          $(#[$meta:meta])*
+          $method:ident($method_id:literal) {
+            $($(#[$field_meta:meta])* $field:ident : $type:ty $([$flag:ident])? $(= $default:expr)?,)*
+          }
+        )+
+      }
+    )+
+  ) => {
+    $(
+      $(
+        paste! {
+          $(#[$meta])*
+          pub struct [<$class $method>] {
+            $($(#[$field_meta])* pub $field : $type,)*
+          }
+
+          #[derive(Default)]
+          pub struct [<$class $method Builder>] {
+            $($field : Option<$type>,)*
+          }
+
+          impl [<$class $method Builder>] {
+            $(
+              pub fn $field(mut self, $field: impl Into<$type>) -> Self {
+                self.$field = Some($field.into());
+                self
+              }
+            )*
+
+            pub fn build(self) -> Result<[<$class $method>]> {
+              Ok([<$class $method>] {
+                $(
+                  $field: match self.$field {
+                    Some(value) => value,
+                    None => field_default!($field $(, $default)?),
+                  },
+                )*
+              })
+            }
+          }
+
+          impl std::fmt::Debug for [<$class $method>] {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+              f.debug_struct(stringify!([<$class $method>]))
+                $(.field(stringify!($field), debug_field!(&self.$field $(, $flag)?)))*
+                .finish()
+            }
+          }
+
+          impl TryFrom<Bytes> for [<$class $method>] {
+            type Error = anyhow::Error;
+
+            fn try_from(buf: Bytes) -> Result<Self> {
+              let mut reader = MethodReader::new(buf);
+              // discard class and method id
+              reader.read_short()?;
+              reader.read_short()?;
+              $(
+                let offset = reader.offset();
+                let $field = <$type as MethodField>::read_from(&mut reader).map_err(|err| DecodeError {
+                  class_id: $class_id,
+                  method_id: $method_id,
+                  field: stringify!($field),
+                  expected: stringify!($type),
+                  offset,
+                  reason: err.to_string(),
+                })?;
+              )*
+              Ok(Self {
+                $($field),*
+              })
+            }
+          }
+
+          impl [<$class $method>] {
+            pub const CLASS_ID: Short = $class_id;
+            pub const METHOD_ID: Short = $method_id;
+
+            pub fn builder() -> [<$class $method Builder>] {
+              Default::default()
+            }
+
+            pub fn into_raw_repr(self) -> Result<Vec<u8>> {
+              let mut buf = vec![];
+              self.write_raw_repr(&mut buf)?;
+              Ok(buf)
+            }
+
+            pub fn write_raw_repr(self, buf: &mut Vec<u8>) -> Result<()> {
+              let mut writer = MethodWriter::new(buf);
+              writer.write_short(Self::CLASS_ID)?;
+              writer.write_short(Self::METHOD_ID)?;
+              $(
+                MethodField::write_to(self.$field, &mut writer)?;
+              )*
+              writer.finish()
+            }
+
+            // size of the method payload on the wire, class and method ids included
+            pub fn encoded_size(&self) -> usize {
+              let mut size = MethodSize::new();
+              size.size_short(&Self::CLASS_ID);
+              size.size_short(&Self::METHOD_ID);
+              $(
+                MethodField::size_of(&self.$field, &mut size);
+              )*
+              size.finish()
+            }
+
+            pub fn class_id(&self) -> Short {
+              Self::CLASS_ID
+            }
+
+            pub fn method_id(&self) -> Short {
+              Self::METHOD_ID
+            }
+
+            pub fn into_frame(self) -> Frame {
+              self.into()
+            }
+          }
+
+          impl From<[<$class $method>]> for Frame {
+            fn from(method: [<$class $method>]) -> Self {
+              Frame::[<$class $method>](method)
+            }
+          }
+        }
+      )+
+    )+
+
+    paste! {
+      #[derive(Debug)]
+      pub enum Frame {
+        $(
+          $(
+            [<$class $method>]([<$class $method>]),
+          )+
+        )+
+        ContentHeader(ContentHeader),
+        ContentBody(ContentBody),
+        RawMethod(RawMethod),
+        Heartbeat,
+        // frames of one channel written with a single flush
+        Batch(Vec<Frame>)
+      }
+
+      impl Frame {
+        pub fn method(class_id: Short, method_id: Short, body: Bytes) -> Result<Self> {
+          let frame = match class_id {
+           $(
+              $class_id => {
+                match method_id {
+                  $(
+                    $method_id => {
+                      Frame::[<$class $method>]([<$class $method>]::try_from(body)?)
+                    }
+                  ),+
+                  _ => {
+                    Frame::RawMethod(RawMethod::try_from(body)?)
+                  }
+                }
+              }
+           ),+
+           _ => {
+             Frame::RawMethod(RawMethod::try_from(body)?)
+           }
+          };
+
+          Ok(frame)
+        }
+
+        pub fn into_raw_repr(self) -> Result<Vec<u8>> {
+          let mut buf = vec![];
+          self.write_raw_repr(&mut buf)?;
+          Ok(buf)
+        }
+
+        // class and method id, None for frames that aren't methods
+        pub fn method_ids(&self) -> Option<(Short, Short)> {
+          match self {
+            $(
+              $(
+                Frame::[<$class $method>](..) => Some(($class_id, $method_id)),
+              )+
+            )+
+            Frame::RawMethod(method) => Some((method.class_id, method.method_id)),
+            _ => None,
+          }
+        }
+
+        pub fn name(&self) -> &'static str {
+          match self {
+            $(
+              $(
+                Frame::[<$class $method>](..) => stringify!([<$class $method>]),
+              )+
+            )+
+            Frame::ContentHeader(..) => "ContentHeader",
+            Frame::ContentBody(..) => "ContentBody",
+            Frame::RawMethod(..) => "RawMethod",
+            Frame::Heartbeat => "Heartbeat",
+            Frame::Batch(..) => "Batch",
+          }
+        }
+
+        // size of the frame payload on the wire
+        pub fn encoded_size(&self) -> usize {
+          match self {
+            $(
+              $(
+                Frame::[<$class $method>](payload) => {
+                  payload.encoded_size()
+                }
+              )+
+            )+,
+            Frame::ContentHeader(header) => {
+              header.encoded_size()
+            },
+            Frame::ContentBody(body) => {
+              body.0.len()
+            },
+            Frame::RawMethod(method) => {
+              4 + method.arguments.len()
+            },
+            Frame::Heartbeat => 0,
+            Frame::Batch(..) => {
+              panic!("Batch is not a single frame")
+            }
+          }
+        }
+
+        // appends the frame payload, lets the writer encode frames into a reused buffer
+        pub fn write_raw_repr(self, buf: &mut Vec<u8>) -> Result<()> {
+          match self {
+            $(
+              $(
+                Frame::[<$class $method>](payload) => {
+                  payload.write_raw_repr(buf)
+                }
+              )+
+            )+,
+            Frame::ContentHeader(header) => {
+              header.write_raw_repr(buf)
+            },
+            Frame::ContentBody(body) => {
+              buf.extend_from_slice(&body.0);
+              Ok(())
+            },
+            Frame::RawMethod(method) => {
+              method.write_raw_repr(buf)
+            },
+            Frame::Heartbeat => Ok(()),
+            Frame::Batch(..) => {
+              panic!("Batch is not a single frame")
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! debug_field {
+  ($val:expr) => {
+    $val
+  };
+  ($val:expr, sensitive) => {
+    &$crate::frame::Redacted
+  };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! field_default {
+  ($field:ident) => {
+    bail!("Missing required argument {}", stringify!($field))
+  };
+  ($field:ident, $default:expr) => {
+    $default
+  };
+}
+
+// content header property lists, every field is optional and owns a bit of the flag word,
+// the first field gets the highest bit
+#[macro_export]
+macro_rules! generate_content_properties {
+  (
+    $(#[$meta:meta])*
+    pub struct $name:ident {
+      $($(#[$field_meta:meta])* pub $field:ident : $type:ty,)+
+    }
+  ) => {
+    $(#[$meta])*
+    pub struct $name {
+      $($(#[$field_meta])* pub $field : Option<$type>,)+
+    }
+
+    impl $name {
+      pub fn decode(mut buf: Bytes) -> Result<Self> {
+        let flags = buf.read_ushort()?;
+        // extra flag words only carry properties the class doesn't know about
+        let mut flag_word = flags;
+        while (flag_word & CONTINUATION_FLAG) != 0 {
+          flag_word = buf.read_ushort()?;
+        }
+
+        let mut bits = (1..16).rev();
+        $(
+          let flag: u16 = 1 << bits.next().expect("More properties than bits in a flag word");
+          let $field = match (flags & flag) != 0 {
+            true => Some(
+              <$type as PropertyField>::read_from(&mut buf)
+                .with_context(|| format!("Failed to decode {} property", stringify!($field)))?
+            ),
+            false => None,
+          };
+        )+
+
+        Ok(Self {
+          $($field),+
+        })
+      }
+
+      // flags are patched in once all present properties are written
+      pub(crate) fn encode(self, buf: &mut Vec<u8>) -> Result<()> {
+        let flag_pos = buf.len();
+        let mut flags = 0_u16;
+        buf.write_ushort(0)?;
+
+        let mut bits = (1..16).rev();
+        $(
+          let flag: u16 = 1 << bits.next().expect("More properties than bits in a flag word");
+          if let Some(value) = self.$field {
+            flags |= flag;
+            PropertyField::write_to(value, buf)?;
+          }
+        )+
+
+        buf[flag_pos..flag_pos + 2].copy_from_slice(&flags.to_be_bytes());
+        Ok(())
+      }
+
+      pub fn encoded_size(&self) -> usize {
+        2 $(+ self.$field.as_ref().map_or(0, PropertyField::size_of))+
+      }
+
+      fn validate_fields(&self) -> Result<()> {
+        $(
+          if let Some(value) = &self.$field {
+            PropertyField::validate(value)
+              .with_context(|| format!("Invalid {} property", stringify!($field)))?;
+          }
+        )+
+
+        Ok(())
+      }
+    }
+  }
+}
+
+// reply codes of channel and connection close, soft errors close the channel, hard errors the connection
+#[macro_export]
+macro_rules! generate_reply_codes {
+  (
+    $($name:ident($code:literal, $class:ident, $label:literal),)+
+  ) => {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum ReplyCode {
+      $($name,)+
+      // codes the spec doesn't define, e.g. broker extensions
+      Other(Short),
+    }
+
+    impl ReplyCode {
+      pub fn code(&self) -> Short {
+        match self {
+          $(ReplyCode::$name => $code,)+
+          ReplyCode::Other(code) => *code,
+        }
+      }
+
+      pub fn is_soft_error(&self) -> bool {
+        match self {
+          $(ReplyCode::$name => stringify!($class) == "soft",)+
+          ReplyCode::Other(code) => (300..400).contains(code),
+        }
+      }
+
+      pub fn is_hard_error(&self) -> bool {
+        match self {
+          $(ReplyCode::$name => stringify!($class) == "hard",)+
+          ReplyCode::Other(code) => (500..600).contains(code),
+        }
+      }
+
+      fn label(&self) -> &'static str {
+        match self {
+          $(ReplyCode::$name => $label,)+
+          ReplyCode::Other(_) => "UNKNOWN",
+        }
+      }
+    }
+
+    impl From<Short> for ReplyCode {
+      fn from(code: Short) -> Self {
+        match code {
+          $($code => ReplyCode::$name,)+
+          code => ReplyCode::Other(code),
+        }
+      }
+    }
+
+    impl From<ReplyCode> for Short {
+      fn from(code: ReplyCode) -> Self {
+        code.code()
+      }
+    }
+
+    impl std::fmt::Display for ReplyCode {
+      fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.label())
+      }
+    }
+  }
+}
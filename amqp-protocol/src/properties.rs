@@ -0,0 +1,184 @@
+use std::time::{Duration, SystemTime};
+use anyhow::Context;
+use bytes::Bytes;
+use crate::dec::Decode;
+use crate::enc::{proptable_size, Encode};
+use crate::types::{validate_short_str, PropTable};
+use crate::{bail, generate_content_properties, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MessageDeliveryMode {
+  Persistent,
+  NonPersistent
+}
+
+// the last bit signals that another flag word follows, basic class never uses it
+const CONTINUATION_FLAG: u16 = 1;
+
+// wire representation of a single content property
+pub trait PropertyField: Sized {
+  fn read_from(buf: &mut Bytes) -> Result<Self>;
+  fn write_to(self, buf: &mut Vec<u8>) -> Result<()>;
+  fn size_of(&self) -> usize;
+
+  fn validate(&self) -> Result<()> {
+    Ok(())
+  }
+}
+
+// short strings
+impl PropertyField for String {
+  fn read_from(buf: &mut Bytes) -> Result<Self> {
+    Ok(buf.read_shortstr()?.into())
+  }
+
+  fn write_to(self, buf: &mut Vec<u8>) -> Result<()> {
+    buf.write_shortstr(self.into())
+  }
+
+  fn size_of(&self) -> usize {
+    1 + self.len()
+  }
+
+  fn validate(&self) -> Result<()> {
+    Ok(validate_short_str(self)?)
+  }
+}
+
+impl PropertyField for PropTable {
+  fn read_from(buf: &mut Bytes) -> Result<Self> {
+    buf.read_proptable()
+  }
+
+  fn write_to(self, buf: &mut Vec<u8>) -> Result<()> {
+    buf.write_proptable(self)
+  }
+
+  fn size_of(&self) -> usize {
+    proptable_size(self)
+  }
+}
+
+impl PropertyField for u8 {
+  fn read_from(buf: &mut Bytes) -> Result<Self> {
+    buf.read_byte()
+  }
+
+  fn write_to(self, buf: &mut Vec<u8>) -> Result<()> {
+    buf.write_byte(self)
+  }
+
+  fn size_of(&self) -> usize {
+    1
+  }
+}
+
+impl PropertyField for SystemTime {
+  fn read_from(buf: &mut Bytes) -> Result<Self> {
+    buf.read_timestamp()
+  }
+
+  fn write_to(self, buf: &mut Vec<u8>) -> Result<()> {
+    buf.write_timestamp(self)
+  }
+
+  fn size_of(&self) -> usize {
+    8
+  }
+}
+
+impl PropertyField for MessageDeliveryMode {
+  fn read_from(buf: &mut Bytes) -> Result<Self> {
+    let mode = buf.read_byte()?;
+
+    Ok(if mode == 2 {
+      MessageDeliveryMode::Persistent
+    } else {
+      MessageDeliveryMode::NonPersistent
+    })
+  }
+
+  fn write_to(self, buf: &mut Vec<u8>) -> Result<()> {
+    match self {
+      MessageDeliveryMode::NonPersistent => buf.write_byte(1),
+      MessageDeliveryMode::Persistent => buf.write_byte(2),
+    }
+  }
+
+  fn size_of(&self) -> usize {
+    1
+  }
+}
+
+generate_content_properties! {
+  #[derive(Default, Debug, Clone)]
+  #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+  pub struct BasicProperties {
+    pub content_type: String,
+    pub content_encoding: String,
+    pub headers: PropTable,
+    pub delivery_mode: MessageDeliveryMode,
+    pub priority: u8,
+    pub correlation_id: String,
+    pub reply_to: String,
+    pub expiration: String,
+    pub message_id: String,
+    pub timestamp: SystemTime,
+    pub ty: String,
+    pub user_id: String,
+    pub app_id: String,
+  }
+}
+
+impl BasicProperties {
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  // expiration is carried as a string with the number of milliseconds
+  pub fn set_expiration(&mut self, ttl: Duration) {
+    self.expiration = Some(ttl.as_millis().to_string());
+  }
+
+  pub fn get_expiration(&self) -> Option<Duration> {
+    let expiration = self.expiration.as_ref()?;
+    expiration.parse().ok().map(Duration::from_millis)
+  }
+
+  pub fn set_timestamp_now(&mut self) {
+    self.timestamp = Some(SystemTime::now());
+  }
+
+  #[cfg(feature = "chrono")]
+  pub fn set_timestamp_utc(&mut self, timestamp: chrono::DateTime<chrono::Utc>) {
+    self.timestamp = Some(timestamp.into());
+  }
+
+  #[cfg(feature = "chrono")]
+  pub fn get_timestamp_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+    self.timestamp.map(Into::into)
+  }
+
+  pub fn validate(&self) -> Result<()> {
+    self.validate_fields()?;
+
+    if let Some(expiration) = &self.expiration {
+      if expiration.parse::<u64>().is_err() {
+        bail!("Expiration must be a non-negative number of milliseconds, got {:?}", expiration);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl TryFrom<BasicProperties> for Vec<u8> {
+  type Error = anyhow::Error;
+
+  fn try_from(properties: BasicProperties) -> Result<Self> {
+    let mut result = vec![];
+    properties.encode(&mut result)?;
+    Ok(result)
+  }
+}
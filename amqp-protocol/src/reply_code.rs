@@ -0,0 +1,5 @@
+use crate::generate_reply_codes;
+use crate::types::Short;
+
+// generated by build.rs from spec/amqp-rabbitmq-0.9.1.json
+include!(concat!(env!("OUT_DIR"), "/reply_codes.rs"));
@@ -0,0 +1,4 @@
+use crate::types::{Byte, Int, Short};
+
+// generated by build.rs from spec/amqp-rabbitmq-0.9.1.json
+include!(concat!(env!("OUT_DIR"), "/constants.rs"));
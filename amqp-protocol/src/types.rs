@@ -0,0 +1,397 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::SystemTime;
+use bytes::Bytes;
+use crate::error::InvalidShortStr;
+
+pub type PropTable = HashMap<ShortStr, Property>;
+
+pub type SignedByte = i8;
+pub type Byte = u8;
+pub type Bool = bool;
+// packed with the adjacent bits of a method into shared octets
+pub type Bit = bool;
+pub type UShort = u16;
+pub type Short = i16;
+pub type Int = i32;
+pub type UInt = u32;
+pub type Long = i64;
+pub type ULong = u64;
+pub type Float = f32;
+pub type Double = f64;
+// seconds precision on the wire
+pub type Timestamp = SystemTime;
+pub type ChannelId = i16;
+
+// utf8, checked on construction. Decoded strings share the memory of the frame they came with
+#[derive(Default, Clone, PartialEq, Eq, Hash)]
+pub struct ShortStr(Bytes);
+
+pub const SHORT_STR_MAX_LEN: usize = 255;
+
+impl ShortStr {
+  // the From impls don't check, oversized values are rejected once the frame is encoded
+  pub fn new(str: impl Into<String>) -> std::result::Result<Self, InvalidShortStr> {
+    let str = Self::from(str.into());
+    str.validate()?;
+    Ok(str)
+  }
+
+  pub(crate) fn from_utf8(bytes: Bytes) -> std::result::Result<Self, std::str::Utf8Error> {
+    std::str::from_utf8(&bytes)?;
+    Ok(Self(bytes))
+  }
+
+  pub fn validate(&self) -> std::result::Result<(), InvalidShortStr> {
+    validate_short_str(self.as_str())
+  }
+
+  pub fn as_str(&self) -> &str {
+    // never fails, the bytes were checked on construction
+    std::str::from_utf8(&self.0).unwrap_or_default()
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.0
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
+pub fn validate_short_str(str: &str) -> std::result::Result<(), InvalidShortStr> {
+  if str.len() > SHORT_STR_MAX_LEN {
+    return Err(InvalidShortStr::TooLong(str.len()));
+  }
+
+  if str.contains('\0') {
+    return Err(InvalidShortStr::ContainsNul);
+  }
+
+  Ok(())
+}
+
+impl std::fmt::Debug for ShortStr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("ShortStr").field(&self.as_str()).finish()
+  }
+}
+
+impl std::fmt::Display for ShortStr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl From<String> for ShortStr {
+  fn from(str: String) -> Self {
+    Self(str.into())
+  }
+}
+
+impl From<&str> for ShortStr {
+  fn from(str: &str) -> Self {
+    Self(Bytes::copy_from_slice(str.as_bytes()))
+  }
+}
+
+impl From<ShortStr> for String {
+  fn from(str: ShortStr) -> Self {
+    str.as_str().to_owned()
+  }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ShortStr {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ShortStr {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    String::deserialize(deserializer).map(Into::into)
+  }
+}
+
+// arbitrary bytes on the wire, e.g. sasl responses or binary header values. Decoded values share
+// the memory of the frame they came with
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LongStr(pub Bytes);
+
+impl LongStr {
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.0
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    std::str::from_utf8(&self.0).ok()
+  }
+
+  pub fn to_string_lossy(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.0)
+  }
+}
+
+impl From<String> for LongStr {
+  fn from(str: String) -> Self {
+    Self(str.into())
+  }
+}
+
+impl From<&str> for LongStr {
+  fn from(str: &str) -> Self {
+    Self(Bytes::copy_from_slice(str.as_bytes()))
+  }
+}
+
+impl From<Vec<u8>> for LongStr {
+  fn from(bytes: Vec<u8>) -> Self {
+    Self(bytes.into())
+  }
+}
+
+impl From<&[u8]> for LongStr {
+  fn from(bytes: &[u8]) -> Self {
+    Self(Bytes::copy_from_slice(bytes))
+  }
+}
+
+impl From<Bytes> for LongStr {
+  fn from(bytes: Bytes) -> Self {
+    Self(bytes)
+  }
+}
+
+// a string when the bytes are valid utf8, raw bytes otherwise
+#[cfg(feature = "serde")]
+impl serde::Serialize for LongStr {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match self.as_str() {
+      Some(str) => serializer.serialize_str(str),
+      None => serializer.serialize_bytes(&self.0),
+    }
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for LongStr {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    struct LongStrVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for LongStrVisitor {
+      type Value = LongStr;
+
+      fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a string or a byte array")
+      }
+
+      fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<LongStr, E> {
+        Ok(v.into())
+      }
+
+      fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> std::result::Result<LongStr, E> {
+        Ok(v.into())
+      }
+
+      fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<LongStr, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+          bytes.push(byte);
+        }
+        Ok(bytes.into())
+      }
+    }
+
+    deserializer.deserialize_any(LongStrVisitor)
+  }
+}
+
+// value * 10^-scale
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decimal {
+  pub scale: Byte,
+  pub value: Int,
+}
+
+impl Decimal {
+  pub fn new(scale: Byte, value: Int) -> Self {
+    Self {
+      scale,
+      value
+    }
+  }
+}
+
+// field table values, type tags follow the RabbitMQ errata of the 0-9-1 spec
+// serialized externally tagged, e.g. {"Int": 5}, so values round-trip with their wire type
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Property {
+  Bool(Bool),
+  SignedByte(SignedByte),
+  Byte(Byte),
+  Short(Short),
+  UShort(UShort),
+  Int(Int),
+  UInt(UInt),
+  Long(Long),
+  ULong(ULong),
+  Float(Float),
+  Double(Double),
+  Decimal(Decimal),
+  Timestamp(Timestamp),
+  ShortStr(ShortStr),
+  LongStr(LongStr),
+  Table(PropTable),
+  Array(Vec<Property>),
+  Bytes(Bytes),
+  Void
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Property {
+  fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+    Property::Timestamp(time.into())
+  }
+}
+
+// typed access to table values. Integers convert between widths as long as the value fits,
+// so a header published as a short reads as an i64 just as well
+pub trait FromProperty: Sized {
+  fn from_property(value: &Property) -> Option<Self>;
+}
+
+impl Property {
+  fn as_integer(&self) -> Option<i128> {
+    match self {
+      Property::SignedByte(v) => Some(*v as i128),
+      Property::Byte(v) => Some(*v as i128),
+      Property::Short(v) => Some(*v as i128),
+      Property::UShort(v) => Some(*v as i128),
+      Property::Int(v) => Some(*v as i128),
+      Property::UInt(v) => Some(*v as i128),
+      Property::Long(v) => Some(*v as i128),
+      Property::ULong(v) => Some(*v as i128),
+      _ => None,
+    }
+  }
+}
+
+macro_rules! integer_from_property {
+  ($($ty:ty),*) => {
+    $(
+      impl FromProperty for $ty {
+        fn from_property(value: &Property) -> Option<Self> {
+          value.as_integer().and_then(|v| <$ty>::try_from(v).ok())
+        }
+      }
+    )*
+  };
+}
+
+integer_from_property!(i8, u8, i16, u16, i32, u32, i64, u64);
+
+impl FromProperty for bool {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Bool(v) => Some(*v),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for f64 {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Float(v) => Some(*v as f64),
+      Property::Double(v) => Some(*v),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for f32 {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Float(v) => Some(*v),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for String {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::ShortStr(v) => Some(v.as_str().into()),
+      Property::LongStr(v) => v.as_str().map(Into::into),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for Vec<u8> {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::LongStr(v) => Some(v.0.to_vec()),
+      Property::Bytes(v) => Some(v.to_vec()),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for Decimal {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Decimal(v) => Some(*v),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for SystemTime {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Timestamp(v) => Some(*v),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(feature = "chrono")]
+impl FromProperty for chrono::DateTime<chrono::Utc> {
+  fn from_property(value: &Property) -> Option<Self> {
+    SystemTime::from_property(value).map(Into::into)
+  }
+}
+
+impl FromProperty for PropTable {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Table(v) => Some(v.clone()),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for Vec<Property> {
+  fn from_property(value: &Property) -> Option<Self> {
+    match value {
+      Property::Array(v) => Some(v.clone()),
+      _ => None,
+    }
+  }
+}
+
+impl FromProperty for Property {
+  fn from_property(value: &Property) -> Option<Self> {
+    Some(value.clone())
+  }
+}